@@ -0,0 +1,361 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! In-memory chain mining on demand. Transactions are checked for missing or double
+//! spent inputs, input and output values and BIP68 relative locktimes. Inputs spending
+//! P2WPKH and P2WSH outputs must reveal the key or script of the program and carry valid
+//! signatures, the witness scripts are not executed but the `OP_CHECKSEQUENCEVERIFY` of
+//! the timelocked branch is checked against the sequence of the input.
+
+use crate::types::{Result, Error, RelativeLocktime};
+use crate::types::btc::{self, Utxo};
+use crate::types::btc::scripts::{parse_sig, redeem_p2pkh};
+use crate::types::constants::{SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_TYPE_FLAG, SEQUENCE_LOCKTIME_MASK,
+    SEQUENCE_LOCKTIME_GRANULARITY};
+use super::BitcoinBackend;
+
+use bitcoin::{Transaction, TxIn, TxOut, OutPoint, Script};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::blockdata::opcodes::all;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::util::bip143::SighashComponents;
+use bitcoin_hashes::{Hash, hash160, sha256, sha256d};
+
+use std::collections::HashMap;
+
+/// Time between two mocked blocks in seconds
+const BLOCK_INTERVAL: u32 = 600;
+
+#[derive(Debug)]
+pub struct MockChain {
+    height: u32,
+    /// Transactions with the height of their block, none in the mempool
+    transactions: HashMap<sha256d::Hash, (Transaction, Option<u32>)>,
+    /// Unspent outputs with the height of their block, none in the mempool
+    utxos: HashMap<OutPoint, (TxOut, Option<u32>)>,
+    feerate: u64,
+    /// Counter making the funding transactions unique
+    coinbase: u32,
+}
+
+impl MockChain {
+    /// Create an empty chain at height 0 estimating fees at `feerate` sat/vB
+    pub fn new(feerate: u64) -> MockChain {
+        MockChain {
+            height: 0,
+            transactions: HashMap::new(),
+            utxos: HashMap::new(),
+            feerate,
+            coinbase: 0,
+        }
+    }
+
+    /// Create a confirmed output of `amount` paying to `script_pubkey`
+    pub fn fund(&mut self, script_pubkey: Script, amount: u64) -> Utxo {
+        self.coinbase += 1;
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::from(self.coinbase.to_le_bytes().to_vec()),
+                sequence: std::u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: amount, script_pubkey }],
+        };
+        let txid = tx.txid();
+        self.utxos.insert(OutPoint { txid, vout: 0 }, (tx.output[0].clone(), Some(self.height)));
+        self.transactions.insert(txid, (tx, Some(self.height)));
+        Utxo { txid, vout: 0, amount }
+    }
+
+    /// Mine the mempool in a new block followed by `blocks - 1` empty blocks
+    pub fn mine(&mut self, blocks: u32) {
+        if blocks == 0 {
+            return;
+        }
+        let height = self.height + 1;
+        for (_, block) in self.transactions.values_mut() {
+            block.get_or_insert(height);
+        }
+        for (_, block) in self.utxos.values_mut() {
+            block.get_or_insert(height);
+        }
+        self.height += blocks;
+    }
+
+    /// Check the BIP68 relative locktime of an input for inclusion in the next block
+    fn check_sequence(&self, txin: &TxIn, confirmed: Option<u32>) -> Result<()> {
+        if txin.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+        let value = txin.sequence & SEQUENCE_LOCKTIME_MASK;
+        // Outputs in the mempool are confirmed at the earliest in the next block
+        let elapsed = self.height + 1 - confirmed.unwrap_or(self.height + 1);
+        let mature = match txin.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG {
+            0 => elapsed >= value,
+            _ => elapsed * BLOCK_INTERVAL >= value << SEQUENCE_LOCKTIME_GRANULARITY,
+        };
+        match mature {
+            true => Ok(()),
+            false => Err(Error::TransactionRejected("non-BIP68-final".into())),
+        }
+    }
+}
+
+fn rejected(reason: &str) -> Error {
+    Error::TransactionRejected(reason.into())
+}
+
+fn parse_key(bytes: &[u8]) -> Option<btc::PublicKey> {
+    match bytes.len() {
+        33 => btc::PublicKey::parse_slice(bytes, None).ok(),
+        _ => None,
+    }
+}
+
+/// Check the witness of an input spending a v0 witness program: the last item must hash to
+/// the program, every signature must be valid for one of the keys of the script and the
+/// sequence must satisfy the relative locktime of the branch taken
+fn check_witness(bip143: &SighashComponents, txin: &TxIn, prevout: &TxOut) -> Result<()> {
+    let program = prevout.script_pubkey.as_bytes();
+    let p2wpkh = program.len() == 22 && program[..2] == [0x00, 0x14];
+    let p2wsh = program.len() == 34 && program[..2] == [0x00, 0x20];
+    if !p2wpkh && !p2wsh {
+        return Ok(());
+    }
+    let item = txin.witness.last().ok_or_else(|| rejected("witness-program-witness-empty"))?;
+    let (script_code, keys) = if p2wpkh {
+        if txin.witness.len() != 2 || hash160::Hash::hash(item)[..] != program[2..] {
+            return Err(rejected("witness-program-mismatch"));
+        }
+        let key = parse_key(item).ok_or_else(|| rejected("witness-pubkey-type"))?;
+        (redeem_p2pkh(&key), vec![key])
+    } else {
+        if sha256::Hash::hash(item)[..] != program[2..] {
+            return Err(rejected("witness-program-mismatch"));
+        }
+        let script = Script::from(item.clone());
+        check_timelocked_branch(txin, &script)?;
+        let keys: Vec<btc::PublicKey> = script.iter(false)
+            .filter_map(|instruction| match instruction {
+                Instruction::PushBytes(bytes) => parse_key(bytes),
+                _ => None,
+            })
+            .collect();
+        (script, keys)
+    };
+
+    // Generate Segwit sighash for SIG_ALL
+    let sig_hash = bip143.sighash_all(txin, &script_code, prevout.value);
+    let msg = secp256k1::Message::parse_slice(&sig_hash[..])?;
+    // DER signatures with their sighash flag, keys, preimages and selectors are shorter
+    let items = &txin.witness[..txin.witness.len() - 1];
+    let sigs = items.iter().filter(|item| item.len() > 64 && item[0] == 0x30);
+    let mut count = 0;
+    for sig in sigs {
+        let valid = parse_sig(sig)
+            .map_or(false, |sig| keys.iter().any(|key| secp256k1::verify(&msg, &sig, key)));
+        if !valid {
+            return Err(rejected("mandatory-script-verify-flag-failed"));
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return Err(rejected("mandatory-script-verify-flag-failed"));
+    }
+    Ok(())
+}
+
+/// Swap scripts are `OP_IF <branch> OP_ELSE <t> OP_CHECKSEQUENCEVERIFY ... OP_ENDIF`, an empty
+/// item before the script selects the timelocked branch whose locktime must be satisfied by
+/// the sequence of the input
+fn check_timelocked_branch(txin: &TxIn, script: &Script) -> Result<()> {
+    let starts_with_if = match script.iter(false).next() {
+        Some(Instruction::Op(op)) => op == all::OP_IF,
+        _ => false,
+    };
+    let selector = txin.witness.len().checked_sub(2).map(|index| &txin.witness[index]);
+    if !starts_with_if || selector.map_or(true, |selector| !selector.is_empty()) {
+        return Ok(());
+    }
+    match RelativeLocktime::from_script(script) {
+        Ok(locktime) if !locktime.is_satisfied_by(txin.sequence) =>
+            Err(rejected("non-mandatory-script-verify-flag (Locktime requirement not satisfied)")),
+        _ => Ok(()),
+    }
+}
+
+impl BitcoinBackend for MockChain {
+    fn get_transaction(&self, txid: &sha256d::Hash) -> Result<Option<Transaction>> {
+        Ok(self.transactions.get(txid).map(|(tx, _)| tx.clone()))
+    }
+
+    fn broadcast(&mut self, tx_hex: &str) -> Result<sha256d::Hash> {
+        let tx: Transaction = deserialize(&hex::decode(tx_hex)?)?;
+        let txid = tx.txid();
+        if self.transactions.contains_key(&txid) {
+            return Err(Error::TransactionRejected("txn-already-known".into()));
+        }
+
+        let bip143 = SighashComponents::new(&tx);
+        let mut in_value = 0u64;
+        for txin in tx.input.iter() {
+            let (prevout, confirmed) = self.utxos.get(&txin.previous_output)
+                .ok_or_else(|| Error::TransactionRejected("missing-inputs".into()))?;
+            if tx.version >= 2 {
+                self.check_sequence(txin, *confirmed)?;
+            }
+            check_witness(&bip143, txin, prevout)?;
+            in_value += prevout.value;
+        }
+        let out_value: u64 = tx.output.iter().map(|txout| txout.value).sum();
+        if out_value > in_value {
+            return Err(Error::TransactionRejected("bad-txns-in-belowout".into()));
+        }
+
+        for txin in tx.input.iter() {
+            self.utxos.remove(&txin.previous_output);
+        }
+        for (vout, txout) in tx.output.iter().enumerate() {
+            self.utxos.insert(OutPoint { txid, vout: vout as u32 }, (txout.clone(), None));
+        }
+        self.transactions.insert(txid, (tx, None));
+        Ok(txid)
+    }
+
+    fn spending_transaction(&self, outpoint: &OutPoint, from_height: u32) -> Result<Option<Transaction>> {
+        Ok(self.transactions.values()
+            .filter(|(_, block)| block.map_or(true, |block| block >= from_height))
+            .find(|(tx, _)| tx.input.iter().any(|txin| &txin.previous_output == outpoint))
+            .map(|(tx, _)| tx.clone()))
+    }
+
+    fn confirmations(&self, txid: &sha256d::Hash) -> Result<Option<u32>> {
+        Ok(self.transactions.get(txid).map(|(_, block)| match block {
+            Some(block) => self.height - block + 1,
+            None => 0,
+        }))
+    }
+
+    fn block_height(&self) -> Result<u32> {
+        Ok(self.height)
+    }
+
+    fn estimate_feerate(&self, _target: u16) -> Result<u64> {
+        Ok(self.feerate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Transaction, TxIn, TxOut, OutPoint, Script};
+    use bitcoin::consensus::encode::serialize_hex;
+
+    use bitcoin::util::bip143::SighashComponents;
+
+    use crate::types::Error;
+    use crate::types::btc::{Utxo, PrivateKey, PublicKey, scripts};
+    use super::super::BitcoinBackend;
+    use super::MockChain;
+
+    fn spend(utxo: &Utxo, sequence: u32) -> String {
+        serialize_hex(&Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: utxo.txid, vout: utxo.vout },
+                script_sig: Script::new(),
+                sequence,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: utxo.amount - 1_000, script_pubkey: Script::new() }],
+        })
+    }
+
+    #[test]
+    fn enforce_csv() {
+        let mut chain = MockChain::new(1);
+        let utxo = chain.fund(Script::new(), 100_000);
+        chain.mine(1);
+
+        // Funding output is confirmed at height 0, the next block is 2
+        assert!(chain.broadcast(&spend(&utxo, 3)).is_err());
+        chain.mine(1);
+        let txid = chain.broadcast(&spend(&utxo, 3)).unwrap();
+        assert_eq!(Some(0), chain.confirmations(&txid).unwrap());
+        let outpoint = OutPoint { txid: utxo.txid, vout: utxo.vout };
+        assert_eq!(txid, chain.spending_transaction(&outpoint, 0).unwrap().unwrap().txid());
+        chain.mine(2);
+        assert_eq!(Some(2), chain.confirmations(&txid).unwrap());
+
+        // Double spend
+        assert!(chain.broadcast(&spend(&utxo, std::u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn enforce_csv_in_mempool() {
+        let mut chain = MockChain::new(1);
+        let utxo = chain.fund(Script::new(), 100_000);
+        chain.mine(1);
+        let txid = chain.broadcast(&spend(&utxo, std::u32::MAX)).unwrap();
+        let child = Utxo { txid, vout: 0, amount: utxo.amount - 1_000 };
+
+        // Parent is unconfirmed, a relative locktime of 1 block needs the parent mined
+        assert!(chain.broadcast(&spend(&child, 1)).is_err());
+        chain.mine(1);
+        assert!(chain.broadcast(&spend(&child, 1)).is_ok());
+    }
+
+    fn claim(utxo: &Utxo, script: &Script, privkey: &PrivateKey, sequence: u32) -> String {
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: utxo.txid, vout: utxo.vout },
+                script_sig: Script::new(),
+                sequence,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: utxo.amount - 1_000, script_pubkey: Script::new() }],
+        };
+        let sig_hash = SighashComponents::new(&tx).sighash_all(&tx.input[0], script, utxo.amount);
+        let msg = secp256k1::Message::parse_slice(&sig_hash[..]).unwrap();
+        let mut sig = secp256k1::sign(&msg, privkey).unwrap().0;
+        sig.normalize_s();
+        tx.input[0].witness = scripts::redeem_refund(script.clone(), sig, None);
+        serialize_hex(&tx)
+    }
+
+    #[test]
+    fn enforce_csv_in_witness_script() {
+        let privkey = PrivateKey::parse(&[1; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&privkey);
+        let refund = scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], 6);
+        let mut chain = MockChain::new(1);
+        let utxo = chain.fund(refund.to_v0_p2wsh(), 100_000);
+        chain.mine(6);
+
+        // BIP68 is satisfied but not the relative locktime of the script
+        match chain.broadcast(&claim(&utxo, &refund, &privkey, 5)) {
+            Err(Error::TransactionRejected(reason)) => assert!(reason.contains("Locktime")),
+            e => panic!("unexpected result {:?}", e),
+        }
+        assert!(chain.broadcast(&claim(&utxo, &refund, &privkey, std::u32::MAX)).is_err());
+        assert!(chain.broadcast(&claim(&utxo, &refund, &privkey, 6)).is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Bitcoin chain backend
+
+use crate::types::Result;
+
+use bitcoin_hashes::sha256d;
+
+pub mod mock;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
+pub use self::mock::MockChain;
+
+/// Access to the Bitcoin chain and mempool
+pub trait BitcoinBackend {
+    /// Get a transaction from the mempool or the chain
+    fn get_transaction(&self, txid: &sha256d::Hash) -> Result<Option<bitcoin::Transaction>>;
+
+    /// Broadcast a hex encoded transaction and return its txid
+    fn broadcast(&mut self, tx_hex: &str) -> Result<sha256d::Hash>;
+
+    /// Find the transaction spending an output in the mempool or in the blocks starting at
+    /// `from_height`
+    fn spending_transaction(&self, outpoint: &bitcoin::OutPoint, from_height: u32) -> Result<Option<bitcoin::Transaction>>;
+
+    /// Number of confirmations of a transaction, `0` if in the mempool, none if unknown
+    fn confirmations(&self, txid: &sha256d::Hash) -> Result<Option<u32>>;
+
+    /// Height of the chain tip
+    fn block_height(&self) -> Result<u32>;
+
+    /// Feerate in satoshi per virtual byte to confirm within `target` blocks
+    fn estimate_feerate(&self, target: u16) -> Result<u64>;
+}
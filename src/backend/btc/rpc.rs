@@ -0,0 +1,123 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Bitcoin Core JSON-RPC backend, enabled with the `rpc` feature
+
+use crate::types::{Result, Error};
+use super::BitcoinBackend;
+
+use bitcoin::{Block, OutPoint, Transaction};
+use bitcoin::consensus::encode::deserialize;
+use bitcoin_hashes::sha256d;
+use bitcoin_hashes::hex::FromHex;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::{json, Value};
+
+/// RPC error code of unknown transactions
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// RPC error code of transactions rejected by the mempool
+const RPC_VERIFY_REJECTED: i64 = -26;
+
+pub struct BitcoindBackend {
+    client: Client,
+}
+
+impl BitcoindBackend {
+    pub fn new(url: String, user: Option<String>, pass: Option<String>) -> BitcoindBackend {
+        BitcoindBackend {
+            client: Client::new(url, user, pass),
+        }
+    }
+
+    fn call<T: serde::de::DeserializeOwned>(&self, cmd: &str, args: &[Value]) -> Result<T> {
+        self.client.call(cmd, args).map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    /// Verbose transaction, none if unknown by the node
+    fn raw_transaction(&self, txid: &sha256d::Hash) -> Result<Option<Value>> {
+        match self.client.call::<Value>("getrawtransaction", &[json!(txid.to_string()), json!(true)]) {
+            Ok(tx) => Ok(Some(tx)),
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(ref e))) if e.code == RPC_INVALID_ADDRESS_OR_KEY => Ok(None),
+            Err(e) => Err(Error::Backend(e.to_string())),
+        }
+    }
+}
+
+impl BitcoinBackend for BitcoindBackend {
+    fn get_transaction(&self, txid: &sha256d::Hash) -> Result<Option<Transaction>> {
+        match self.raw_transaction(txid)? {
+            Some(tx) => {
+                let hex = tx["hex"].as_str().ok_or(Error::Backend("missing transaction hex".into()))?;
+                Ok(Some(deserialize(&hex::decode(hex)?)?))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn broadcast(&mut self, tx_hex: &str) -> Result<sha256d::Hash> {
+        let txid: String = match self.client.call("sendrawtransaction", &[json!(tx_hex)]) {
+            Ok(txid) => txid,
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(ref e))) if e.code == RPC_VERIFY_REJECTED => {
+                return Err(Error::TransactionRejected(e.message.clone()));
+            },
+            Err(e) => return Err(Error::Backend(e.to_string())),
+        };
+        sha256d::Hash::from_hex(&txid).map_err(|e| Error::Backend(format!("{:?}", e)))
+    }
+
+    fn spending_transaction(&self, outpoint: &OutPoint, from_height: u32) -> Result<Option<Transaction>> {
+        let spends = |tx: &Transaction| tx.input.iter().any(|txin| &txin.previous_output == outpoint);
+
+        let mempool: Vec<String> = self.call("getrawmempool", &[])?;
+        for txid in mempool.iter() {
+            let txid = sha256d::Hash::from_hex(txid).map_err(|e| Error::Backend(format!("{:?}", e)))?;
+            // Transactions can leave the mempool between the two calls
+            if let Some(tx) = self.get_transaction(&txid)? {
+                if spends(&tx) {
+                    return Ok(Some(tx));
+                }
+            }
+        }
+
+        for height in from_height..=self.block_height()? {
+            let hash: String = self.call("getblockhash", &[json!(height)])?;
+            let block: String = self.call("getblock", &[json!(hash), json!(0)])?;
+            let block: Block = deserialize(&hex::decode(block)?)?;
+            if let Some(tx) = block.txdata.into_iter().find(|tx| spends(tx)) {
+                return Ok(Some(tx));
+            }
+        }
+        Ok(None)
+    }
+
+    fn confirmations(&self, txid: &sha256d::Hash) -> Result<Option<u32>> {
+        // Mempool transactions have no confirmations field
+        Ok(self.raw_transaction(txid)?.map(|tx| tx["confirmations"].as_u64().unwrap_or(0) as u32))
+    }
+
+    fn block_height(&self) -> Result<u32> {
+        Ok(self.call::<u64>("getblockcount", &[])? as u32)
+    }
+
+    fn estimate_feerate(&self, target: u16) -> Result<u64> {
+        let estimate: Value = self.call("estimatesmartfee", &[json!(target)])?;
+        // BTC per kvB to satoshi per vB
+        match estimate["feerate"].as_f64() {
+            Some(feerate) => Ok((feerate * 100_000.0).ceil() as u64),
+            None => Err(Error::CannotEstimateFeerate),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Deterministic in-memory Monero chain mining on demand, also acting as the
+//! wallet of the swap participants. Transactions carry real outputs, amounts and
+//! commitments but no ring signatures nor range proofs, key images are derived
+//! from the one-time private keys to detect double spends.
+
+use crate::types::{Result, Error, TxKind};
+use crate::types::xmr::{self, PrivateKey, PublicKey};
+use crate::types::xmr::keys::{self, write_varint};
+use crate::transactions::xmr::{MoneroTransaction, SignedTransfer, Wallet};
+use super::{MoneroBackend, ChainTransaction, OwnedOutput, scan_transaction};
+
+use tiny_keccak::keccak256;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// Fee paid by every transaction in piconero
+pub const FEE: u64 = 10_000_000;
+
+const RCT_TYPE_BULLETPROOF_PLUS: u8 = 6;
+
+#[derive(Debug)]
+struct Entry {
+    tx_hash: [u8; 32],
+    tx_hex: String,
+    /// Height of the block, none in the pool
+    height: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Chain {
+    height: u64,
+    /// Transactions in submission order
+    transactions: Vec<Entry>,
+    key_images: HashSet<[u8; 32]>,
+    /// Counter deriving the transaction private keys
+    nonce: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct FakeMonero {
+    chain: RefCell<Chain>,
+}
+
+impl FakeMonero {
+    /// Create an empty chain at height 0
+    pub fn new() -> FakeMonero {
+        FakeMonero::default()
+    }
+
+    /// Mine the pool in a new block followed by `blocks - 1` empty blocks
+    pub fn mine(&self, blocks: u64) {
+        if blocks == 0 {
+            return;
+        }
+        let mut chain = self.chain.borrow_mut();
+        let height = chain.height;
+        for entry in chain.transactions.iter_mut() {
+            entry.height.get_or_insert(height);
+        }
+        chain.height += blocks;
+    }
+
+    /// Build a transaction spending the `key_images` and paying `amount` to
+    /// `(spend, view)`, the transaction private key is derived from a counter
+    fn create(&self, key_images: &[[u8; 32]], spend: &PublicKey, view: &PublicKey, amount: u64) -> Result<SignedTransfer> {
+        let r = {
+            let mut chain = self.chain.borrow_mut();
+            chain.nonce += 1;
+            let mut data = b"fake monero".to_vec();
+            data.extend_from_slice(&chain.nonce.to_le_bytes());
+            keys::hash_to_scalar(&data)
+        };
+        let tx_pubkey = keys::public_key(&r);
+        // 8·r·A is equal to 8·a·R computed by the receiver
+        let derivation = keys::derivation(&r, view);
+        let shared = keys::derivation_to_scalar(&derivation, 0);
+
+        let mut tx = vec![];
+        write_varint(&mut tx, 2);
+        write_varint(&mut tx, 0);
+        write_varint(&mut tx, key_images.len() as u64);
+        for key_image in key_images {
+            // txin_to_key with a single ring member
+            tx.push(0x02);
+            write_varint(&mut tx, 0);
+            write_varint(&mut tx, 1);
+            write_varint(&mut tx, 0);
+            tx.extend_from_slice(key_image);
+        }
+        write_varint(&mut tx, 1);
+        write_varint(&mut tx, 0);
+        tx.push(0x02);
+        tx.extend_from_slice(keys::derive_output_key(&derivation, 0, spend).compress().as_bytes());
+        write_varint(&mut tx, 33);
+        tx.push(0x01);
+        tx.extend_from_slice(tx_pubkey.compress().as_bytes());
+        tx.push(RCT_TYPE_BULLETPROOF_PLUS);
+        write_varint(&mut tx, FEE);
+        tx.extend_from_slice(&keys::encode_amount(&shared, amount));
+        tx.extend_from_slice(keys::commit(&keys::commitment_mask(&shared), amount)?.compress().as_bytes());
+
+        Ok(SignedTransfer {
+            tx_hash: keccak256(&tx),
+            tx_hex: hex::encode(tx),
+            tx_pubkey,
+        })
+    }
+}
+
+/// Key image of an output, only computable with the one-time private key
+fn key_image(view: &PrivateKey, spend: &PrivateKey, output: &OwnedOutput) -> [u8; 32] {
+    let derivation = keys::derivation(view, &output.tx_pubkey);
+    let p = keys::derive_output_privkey(&derivation, output.index as u64, spend);
+    keccak256(p.as_bytes())
+}
+
+impl Wallet for FakeMonero {
+    /// Funds are created out of thin air
+    fn transfer(&self, spend: &xmr::PublicKey, view: &xmr::PublicKey, amount: u64) -> Result<SignedTransfer> {
+        self.create(&[], spend, view, amount)
+    }
+
+    fn sweep(&self, spend: &xmr::PrivateKey, view: &xmr::PrivateKey, dest_spend: &xmr::PublicKey,
+             dest_view: &xmr::PublicKey) -> Result<SignedTransfer> {
+        let outputs = self.scan(view, &keys::public_key(spend), 0)?;
+        let mut key_images = vec![];
+        let mut amount = 0u64;
+        {
+            let chain = self.chain.borrow();
+            for output in outputs.iter() {
+                let key_image = key_image(view, spend, output);
+                if !chain.key_images.contains(&key_image) {
+                    key_images.push(key_image);
+                    amount = amount.saturating_add(output.amount);
+                }
+            }
+        }
+        if key_images.is_empty() {
+            return Err(Error::XmrOutputNotFound);
+        }
+        let amount = amount.checked_sub(FEE).ok_or(Error::AmountBelowFee { tx: TxKind::XmrSweep, amount, fee: FEE })?;
+        self.create(&key_images, dest_spend, dest_view, amount)
+    }
+}
+
+impl MoneroBackend for FakeMonero {
+    fn submit(&self, tx: &SignedTransfer) -> Result<()> {
+        let key_images = MoneroTransaction::deserialize(&hex::decode(&tx.tx_hex)?)?.key_images;
+        let mut chain = self.chain.borrow_mut();
+        if chain.transactions.iter().any(|entry| entry.tx_hash == tx.tx_hash) {
+            return Err(Error::TransactionRejected("already known".into()));
+        }
+        if key_images.iter().any(|key_image| chain.key_images.contains(key_image)) {
+            return Err(Error::TransactionRejected("double spend".into()));
+        }
+        chain.key_images.extend(key_images);
+        chain.transactions.push(Entry {
+            tx_hash: tx.tx_hash,
+            tx_hex: tx.tx_hex.clone(),
+            height: None,
+        });
+        Ok(())
+    }
+
+    fn get_transaction(&self, tx_hash: &[u8; 32]) -> Result<Option<ChainTransaction>> {
+        Ok(self.chain.borrow().transactions.iter()
+            .find(|entry| &entry.tx_hash == tx_hash)
+            .map(|entry| ChainTransaction { tx_hex: entry.tx_hex.clone(), height: entry.height }))
+    }
+
+    fn height(&self) -> Result<u64> {
+        Ok(self.chain.borrow().height)
+    }
+
+    fn scan(&self, view: &PrivateKey, spend: &PublicKey, from_height: u64) -> Result<Vec<OwnedOutput>> {
+        let chain = self.chain.borrow();
+        let mut outputs = vec![];
+        for entry in chain.transactions.iter() {
+            if entry.height.map_or(false, |height| height < from_height) {
+                continue;
+            }
+            if let Some(output) = scan_transaction(entry.tx_hash, &entry.tx_hex, entry.height, view, spend)? {
+                outputs.push(output);
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::types::Error;
+    use crate::types::xmr::{self, keys};
+    use crate::transactions::xmr::Wallet;
+    use super::super::MoneroBackend;
+    use super::{FakeMonero, FEE};
+
+    #[test]
+    fn transfer_and_sweep() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let chain = FakeMonero::new();
+        let x = xmr::PrivateKey::random(&mut rng);
+        let a = xmr::PrivateKey::random(&mut rng);
+        let (X, A) = (keys::public_key(&x), keys::public_key(&a));
+
+        let lock = chain.transfer(&X, &A, 1_000_000_000).unwrap();
+        chain.submit(&lock).unwrap();
+        assert_eq!(chain.confirmations(&lock.tx_hash).unwrap(), Some(0));
+        chain.mine(10);
+        assert_eq!(chain.confirmations(&lock.tx_hash).unwrap(), Some(10));
+
+        let outputs = chain.scan(&a, &X, 0).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].amount, 1_000_000_000);
+        assert!(chain.scan(&a, &X, 1).unwrap().is_empty());
+
+        let y = xmr::PrivateKey::random(&mut rng);
+        let b = xmr::PrivateKey::random(&mut rng);
+        let sweep = chain.sweep(&x, &a, &keys::public_key(&y), &keys::public_key(&b)).unwrap();
+        chain.submit(&sweep).unwrap();
+        let swept = chain.scan(&b, &keys::public_key(&y), 0).unwrap();
+        assert_eq!(swept[0].amount, 1_000_000_000 - FEE);
+
+        // Outputs are spent, a second sweep has nothing to spend
+        match chain.sweep(&x, &a, &keys::public_key(&y), &keys::public_key(&b)) {
+            Err(Error::XmrOutputNotFound) => (),
+            _ => panic!("outputs already spent"),
+        }
+    }
+
+    #[test]
+    fn reject_double_spend() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let chain = FakeMonero::new();
+        let x = xmr::PrivateKey::random(&mut rng);
+        let a = xmr::PrivateKey::random(&mut rng);
+        let lock = chain.transfer(&keys::public_key(&x), &keys::public_key(&a), 1_000_000_000).unwrap();
+        chain.submit(&lock).unwrap();
+
+        let first = chain.sweep(&x, &a, &keys::public_key(&a), &keys::public_key(&a)).unwrap();
+        let second = chain.sweep(&x, &a, &keys::public_key(&x), &keys::public_key(&x)).unwrap();
+        chain.submit(&first).unwrap();
+        assert!(chain.submit(&second).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Monero chain backend
+
+use crate::types::{Result, Error};
+use crate::types::xmr::{PrivateKey, PublicKey};
+use crate::transactions::xmr::{MoneroTransaction, SignedTransfer};
+
+pub mod fake;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
+pub use self::fake::FakeMonero;
+
+/// Transaction known by the node
+#[derive(Debug, Clone)]
+pub struct ChainTransaction {
+    /// Serialized transaction
+    pub tx_hex: String,
+    /// Height of the block including the transaction, none if in the pool
+    pub height: Option<u64>,
+}
+
+/// Output sent to a spend key found while scanning the chain
+#[derive(Debug, Clone)]
+pub struct OwnedOutput {
+    pub tx_hash: [u8; 32],
+    /// Transaction public key `R` used to derive the output
+    pub tx_pubkey: PublicKey,
+    /// Index of the output in the transaction
+    pub index: usize,
+    /// Decoded amount in piconero
+    pub amount: u64,
+    /// Height of the block including the transaction, none if in the pool
+    pub height: Option<u64>,
+}
+
+/// Access to the Monero chain and transaction pool. Backends are shared by the
+/// swap phases and only take `&self`
+pub trait MoneroBackend {
+    /// Submit a signed transaction to the network
+    fn submit(&self, tx: &SignedTransfer) -> Result<()>;
+
+    /// Get a transaction from the pool or the chain
+    fn get_transaction(&self, tx_hash: &[u8; 32]) -> Result<Option<ChainTransaction>>;
+
+    /// Number of blocks in the chain
+    fn height(&self) -> Result<u64>;
+
+    /// Find the outputs sent to the spend key `X` with the view key `a` in the
+    /// blocks starting at `from_height`
+    fn scan(&self, view: &PrivateKey, spend: &PublicKey, from_height: u64) -> Result<Vec<OwnedOutput>>;
+
+    /// Number of confirmations of a transaction, `0` if in the pool, none if unknown
+    fn confirmations(&self, tx_hash: &[u8; 32]) -> Result<Option<u64>> {
+        match self.get_transaction(tx_hash)? {
+            Some(ChainTransaction { height: Some(height), .. }) => Ok(Some(self.height()?.saturating_sub(height))),
+            Some(ChainTransaction { height: None, .. }) => Ok(Some(0)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Scan a serialized transaction for an output sent to `X`, none if the
+/// transaction does not pay to the address
+pub(crate) fn scan_transaction(tx_hash: [u8; 32], tx_hex: &str, height: Option<u64>, view: &PrivateKey,
+                               spend: &PublicKey) -> Result<Option<OwnedOutput>> {
+    let tx = MoneroTransaction::deserialize(&hex::decode(tx_hex)?)?;
+    let tx_pubkey = match tx.tx_pubkey() {
+        Some(tx_pubkey) => tx_pubkey,
+        None => return Ok(None),
+    };
+    match tx.scan(view, &tx_pubkey, spend) {
+        Ok((index, amount)) => Ok(Some(OwnedOutput { tx_hash, tx_pubkey, index, amount, height })),
+        Err(Error::XmrOutputNotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
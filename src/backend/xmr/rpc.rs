@@ -0,0 +1,151 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Monero daemon RPC backend, enabled with the `rpc` feature. The daemon does not
+//! scan for view keys, blocks are fetched and scanned locally.
+
+use crate::types::{Result, Error};
+use crate::types::xmr::{PrivateKey, PublicKey};
+use crate::transactions::xmr::SignedTransfer;
+use super::{MoneroBackend, ChainTransaction, OwnedOutput, scan_transaction};
+
+use serde_json::{json, Value};
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// Maximum number of transactions requested at once from `/get_transactions`
+const MAX_TRANSACTIONS: usize = 100;
+
+pub struct MonerodBackend {
+    /// Daemon address as `host:port`
+    address: String,
+}
+
+fn backend_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::Backend(e.to_string())
+}
+
+fn parse_hash(hash: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hash)?;
+    if bytes.len() != 32 {
+        return Err(Error::Backend("invalid transaction hash".into()));
+    }
+    let mut tx_hash = [0u8; 32];
+    tx_hash.copy_from_slice(&bytes);
+    Ok(tx_hash)
+}
+
+impl MonerodBackend {
+    pub fn new(address: String) -> MonerodBackend {
+        MonerodBackend { address }
+    }
+
+    /// POST a JSON body to an endpoint of the daemon
+    fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let body = body.to_string();
+        let mut stream = TcpStream::connect(&self.address).map_err(backend_error)?;
+        write!(stream, "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+               path, self.address, body.len(), body).map_err(backend_error)?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).map_err(backend_error)?;
+        let start = response.windows(4).position(|w| w == b"\r\n\r\n")
+            .ok_or(Error::Backend("invalid HTTP response".into()))?;
+        let status = String::from_utf8_lossy(&response[..start]);
+        if !status.starts_with("HTTP/1.1 200") && !status.starts_with("HTTP/1.0 200") {
+            return Err(Error::Backend(status.lines().next().unwrap_or("").into()));
+        }
+        let value: Value = serde_json::from_slice(&response[start + 4..])?;
+        match value["status"].as_str() {
+            None | Some("OK") => Ok(value),
+            Some(status) => Err(Error::Backend(format!("{} {}", status, value["reason"]))),
+        }
+    }
+
+    /// Call a method of the `/json_rpc` endpoint
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let response = self.post("/json_rpc", &json!({
+            "jsonrpc": "2.0",
+            "id": "0",
+            "method": method,
+            "params": params,
+        }))?;
+        if !response["error"].is_null() {
+            return Err(Error::Backend(response["error"]["message"].to_string()));
+        }
+        Ok(response["result"].clone())
+    }
+
+    fn transactions(&self, tx_hashes: &[String]) -> Result<Vec<(String, ChainTransaction)>> {
+        let response = self.post("/get_transactions", &json!({
+            "txs_hashes": tx_hashes,
+            "decode_as_json": false,
+        }))?;
+        let txs = match response["txs"].as_array() {
+            Some(txs) => txs,
+            None => return Ok(vec![]),
+        };
+        txs.iter().map(|tx| {
+            let tx_hash = tx["tx_hash"].as_str().ok_or(Error::Backend("missing transaction hash".into()))?;
+            let tx_hex = tx["as_hex"].as_str().ok_or(Error::Backend("missing transaction hex".into()))?;
+            let height = match tx["in_pool"].as_bool() {
+                Some(true) => None,
+                _ => tx["block_height"].as_u64(),
+            };
+            Ok((tx_hash.to_string(), ChainTransaction { tx_hex: tx_hex.to_string(), height }))
+        }).collect()
+    }
+}
+
+impl MoneroBackend for MonerodBackend {
+    fn submit(&self, tx: &SignedTransfer) -> Result<()> {
+        self.post("/send_raw_transaction", &json!({
+            "tx_as_hex": tx.tx_hex,
+            "do_not_relay": false,
+        })).map(|_| ())
+    }
+
+    fn get_transaction(&self, tx_hash: &[u8; 32]) -> Result<Option<ChainTransaction>> {
+        Ok(self.transactions(&[hex::encode(tx_hash)])?.pop().map(|(_, tx)| tx))
+    }
+
+    fn height(&self) -> Result<u64> {
+        self.call("get_block_count", json!({}))?["count"].as_u64()
+            .ok_or(Error::Backend("missing block count".into()))
+    }
+
+    fn scan(&self, view: &PrivateKey, spend: &PublicKey, from_height: u64) -> Result<Vec<OwnedOutput>> {
+        let mut tx_hashes = vec![];
+        for height in from_height..self.height()? {
+            let block = self.call("get_block", json!({ "height": height }))?;
+            if let Some(hashes) = block["tx_hashes"].as_array() {
+                tx_hashes.extend(hashes.iter().filter_map(|hash| hash.as_str().map(String::from)));
+            }
+        }
+
+        let mut outputs = vec![];
+        for chunk in tx_hashes.chunks(MAX_TRANSACTIONS) {
+            for (tx_hash, tx) in self.transactions(chunk)? {
+                if let Some(output) = scan_transaction(parse_hash(&tx_hash)?, &tx.tx_hex, tx.height, view, spend)? {
+                    outputs.push(output);
+                }
+            }
+        }
+        Ok(outputs)
+    }
+}
@@ -0,0 +1,262 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Buyer's driver, locks the bitcoins and acquires the Monero
+
+use crate::{Protocol, Phase, Btc};
+use crate::types::{Result, Error, common, btc, xmr};
+use crate::types::state::{SwapState, SwapTransactions};
+use crate::types::wire::Message;
+use crate::transactions::btc::buy::BuyTx;
+use crate::transactions::btc::funding::FundingTx;
+use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::xmr::Wallet;
+use crate::backend::btc::BitcoinBackend;
+use crate::backend::xmr::MoneroBackend;
+use super::{Chains, Coin, locktime_blocks, broadcast_locked, decode_tx, txid};
+
+use bitcoin::{Address, OutPoint};
+use bitcoin::consensus::encode::serialize_hex;
+use rand::{Rng, CryptoRng};
+
+/// Buyer's swap parameters
+pub struct BuyerConfig {
+    pub params: common::Params,
+    /// Coins funding the swap
+    pub coins: Vec<Coin>,
+    /// Address receiving the change and the refund
+    pub address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+    /// Confirmations of the Monero lock before releasing the secret
+    pub min_confirmations: u64,
+    /// Standard Monero address receiving the Monero
+    pub dest: xmr::address::Address,
+}
+
+pub struct BuyerDriver {
+    config: BuyerConfig,
+    parameters: btc::SetupParams,
+    setup: Option<btc::Setup>,
+    state: SwapState,
+    transactions: SwapTransactions,
+    /// Height of the chain when the funding transaction was broadcast
+    funded_height: u32,
+    halt_at: Option<SwapState>,
+}
+
+impl BuyerDriver {
+    /// Generate the buyer's setup parameters
+    pub fn new<R: Rng + CryptoRng>(config: BuyerConfig, rng: &mut R) -> Result<BuyerDriver> {
+        config.dest.check_standard()?;
+        let parameters = Btc::setup(config.params.clone(), rng)?;
+        Ok(BuyerDriver {
+            config,
+            parameters,
+            setup: None,
+            state: SwapState::Initialized,
+            transactions: SwapTransactions::default(),
+            funded_height: 0,
+            halt_at: None,
+        })
+    }
+
+    /// Stop acting once `state` is reached, simulates a party going offline
+    pub fn halt_at(&mut self, state: SwapState) {
+        self.halt_at = Some(state);
+    }
+
+    pub fn state(&self) -> SwapState {
+        self.state
+    }
+
+    pub fn transactions(&self) -> &SwapTransactions {
+        &self.transactions
+    }
+
+    /// Swap is over for the buyer, or the buyer is offline
+    pub fn is_done(&self) -> bool {
+        self.state.is_final() || self.halt_at == Some(self.state)
+    }
+
+    /// First message sent to the seller
+    pub fn start(&self) -> Message {
+        Message::BtcSetup((&self.parameters).into())
+    }
+
+    /// Process a message from the seller and return the answers
+    pub fn handle<B, M, W>(&mut self, message: Message, chains: &mut Chains<B, M, W>) -> Result<Vec<Message>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        if self.is_done() {
+            return Ok(vec![]);
+        }
+
+        match (self.state, message) {
+            (SwapState::Initialized, Message::XmrSetup(exported)) => {
+                let setup = Btc::verify_setup(&self.parameters, &exported)?;
+                self.state.transition(SwapState::SetupVerified)?;
+
+                let init_txs = Btc::execute(&setup, &btc::CreateTransactions {
+                    utxos: self.config.coins.iter().map(|coin| coin.utxo.clone()).collect(),
+                    change_address: self.config.address.clone(),
+                    feerate: self.config.feerate,
+                })?;
+                self.setup = Some(setup);
+                self.state.transition(SwapState::TransactionsCreated)?;
+                self.transactions.btx_1 = Some(init_txs.btx_1.clone());
+                self.transactions.btx_2 = Some(init_txs.btx_2.clone());
+                Ok(vec![Message::InitialTransactions(init_txs)])
+            },
+            (SwapState::TransactionsCreated, Message::VerifiedTransaction(verified)) => {
+                let setup = self.setup.as_ref().ok_or(Error::MissingValue("setup"))?;
+                let inputs = self.config.coins.iter().map(|coin| btc::Input {
+                    txid: coin.utxo.txid,
+                    vout: coin.utxo.vout,
+                    amount: coin.utxo.amount,
+                    address: &coin.address,
+                    privkey: &coin.privkey,
+                }).collect();
+                // Verify the seller signed our refund, with both signatures valid, before locking
+                // the funds
+                let btx_1 = Btc::execute(setup, &btc::LockFunds {
+                    inputs,
+                    btx_1: self.transactions.btx_1.clone().ok_or(Error::MissingValue("btx_1"))?,
+                    btx_2: self.transactions.btx_2.clone().ok_or(Error::MissingValue("btx_2"))?,
+                    btx_2_signed: verified.btx_2_signed.clone(),
+                })?;
+                self.state.transition(SwapState::RefundSigned)?;
+                self.transactions.btx_2_signed = Some(verified.btx_2_signed);
+
+                self.funded_height = chains.btc.block_height()?;
+                chains.btc.broadcast(&btx_1)?;
+                self.transactions.btx_1 = Some(btx_1);
+                self.state.transition(SwapState::Funded)?;
+                Ok(vec![])
+            },
+            // The lock can arrive after the refund started, it is then ignored
+            (_, Message::LockedXmr(xtx)) => {
+                self.transactions.xtx = Some(xtx);
+                Ok(vec![])
+            },
+            _ => Err(Error::UnexpectedMessage),
+        }
+    }
+
+    /// Follow the chains and act until nothing more can be done in the current block,
+    /// return the messages for the seller
+    pub fn poll<B, M, W>(&mut self, chains: &mut Chains<B, M, W>) -> Result<Vec<Message>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        let mut messages = vec![];
+        while !self.is_done() {
+            match self.step(chains)? {
+                Some(mut out) => messages.append(&mut out),
+                None => break,
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Move to the next state if possible, none if waiting
+    fn step<B, M, W>(&mut self, chains: &mut Chains<B, M, W>) -> Result<Option<Vec<Message>>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        let setup = match self.setup.as_ref() {
+            Some(setup) => setup,
+            None => return Ok(None),
+        };
+
+        // The seller can broadcast the refund transaction as well
+        if self.state.can_refund() {
+            if chains.btc.get_transaction(&txid(&self.transactions.btx_2_signed)?)?.is_some() {
+                self.state.transition(SwapState::RefundStarted)?;
+                return Ok(Some(vec![]));
+            }
+        }
+
+        match self.state {
+            SwapState::Funded => {
+                if let Some(xtx) = self.transactions.xtx.clone() {
+                    match Btc::execute(setup, &btc::VerifyXmrLock {
+                        xtx,
+                        backend: chains.xmr,
+                        min_confirmations: self.config.min_confirmations,
+                    }) {
+                        Ok(_) => {
+                            self.state.transition(SwapState::XmrLocked)?;
+                            return Ok(Some(vec![]));
+                        },
+                        Err(Error::NotEnoughConfirmations) => (),
+                        // An invalid lock is dropped, the buyer waits for the refund
+                        Err(_) => self.transactions.xtx = None,
+                    }
+                }
+            },
+            SwapState::XmrLocked => {
+                self.state.transition(SwapState::SecretReleased)?;
+                return Ok(Some(vec![Message::Secret(setup.get_s())]));
+            },
+            SwapState::SecretReleased => {
+                let swaplock = OutPoint { txid: txid(&self.transactions.btx_1)?, vout: 0 };
+                if let Some(buy) = chains.btc.spending_transaction(&swaplock, self.funded_height)? {
+                    let buy = serialize_hex(&buy);
+                    Btc::execute(setup, &btc::ReleaseXmr {
+                        buy: BuyTx::from_hex(buy.clone()),
+                        dest: self.config.dest,
+                        wallet: chains.wallet,
+                        backend: chains.xmr,
+                    })?;
+                    self.transactions.buy = Some(buy);
+                    self.state.transition(SwapState::Bought)?;
+                    return Ok(Some(vec![]));
+                }
+            },
+            SwapState::RefundStarted => {
+                let btx_2 = decode_tx(&self.transactions.btx_2_signed)?;
+                let refund = OutPoint { txid: btx_2.txid(), vout: 0 };
+                // Refund already claimed by the seller after t_1
+                if chains.btc.spending_transaction(&refund, self.funded_height)?.is_some() {
+                    self.state.transition(SwapState::Punished)?;
+                    return Ok(Some(vec![]));
+                }
+
+                let btx_1 = FundingTx::from_hex(self.transactions.btx_1.clone().ok_or(Error::MissingValue("btx_1"))?);
+                let btx_2 = RefundTx::from_hex(serialize_hex(&btx_2), &btx_1);
+                let spend_refund = Btc::execute(setup, &btc::SpendRefund {
+                    btx_2_signed: &btx_2,
+                    address: self.config.address.clone(),
+                    feerate: self.config.feerate,
+                })?;
+                chains.btc.broadcast(&spend_refund)?;
+                self.transactions.spend_refund = Some(spend_refund);
+                self.state.transition(SwapState::Refunded)?;
+                return Ok(Some(vec![]));
+            },
+            _ => (),
+        }
+
+        // Refund after t_0 if the swap did not complete
+        if self.state.can_refund() {
+            let confirmations = chains.btc.confirmations(&txid(&self.transactions.btx_1)?)?.unwrap_or(0);
+            if confirmations >= locktime_blocks(&setup.t_0) {
+                let btx_2 = self.transactions.btx_2_signed.clone().ok_or(Error::MissingValue("btx_2_signed"))?;
+                if broadcast_locked(chains.btc, &setup.t_0, &btx_2)? {
+                    self.state.transition(SwapState::RefundStarted)?;
+                    return Ok(Some(vec![]));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
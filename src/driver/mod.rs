@@ -0,0 +1,332 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Drivers sequencing the phases of each role. A driver reacts to the messages of the
+//! counterparty and to the chain events, it moves the swap forward and starts the
+//! refund when the timelocks expire. `simulate` runs both roles on the local mock
+//! chains.
+
+use crate::types::{Result, Error, RelativeLocktime};
+use crate::types::constants::SEQUENCE_LOCKTIME_GRANULARITY;
+use crate::types::btc::{self, Utxo};
+use crate::types::wire::Message;
+use crate::transactions::xmr::Wallet;
+use crate::backend::btc::{BitcoinBackend, MockChain};
+use crate::backend::xmr::{MoneroBackend, FakeMonero};
+
+use bitcoin::Address;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin_hashes::sha256d;
+
+use std::collections::VecDeque;
+
+pub mod buyer;
+pub mod seller;
+
+pub use self::buyer::{BuyerConfig, BuyerDriver};
+pub use self::seller::{SellerConfig, SellerDriver};
+
+/// Time between two Bitcoin blocks in seconds
+const BLOCK_INTERVAL: u32 = 600;
+
+/// Coin owned by the buyer to fund the swap
+#[derive(Debug, Clone)]
+pub struct Coin {
+    pub utxo: Utxo,
+    pub address: Address,
+    pub privkey: btc::PrivateKey,
+}
+
+/// Chains and wallet used by a driver
+pub struct Chains<'a, B: BitcoinBackend, M: MoneroBackend, W: Wallet> {
+    pub btc: &'a mut B,
+    pub xmr: &'a M,
+    pub wallet: &'a W,
+}
+
+/// Number of blocks after which a relative locktime expires, time based locktimes are
+/// rounded up to the next block
+pub(crate) fn locktime_blocks(locktime: &RelativeLocktime) -> u32 {
+    match *locktime {
+        RelativeLocktime::Blocks(blocks) => blocks as u32,
+        RelativeLocktime::Time(units) => {
+            (((units as u32) << SEQUENCE_LOCKTIME_GRANULARITY) + BLOCK_INTERVAL - 1) / BLOCK_INTERVAL
+        },
+    }
+}
+
+/// Broadcast a transaction spending an output locked with `locktime`. Time based locktimes
+/// expire with the median time past of the chain which is only estimated in blocks, a
+/// transaction rejected as not final yet is not broadcast and `false` is returned to retry
+/// later.
+pub(crate) fn broadcast_locked<B: BitcoinBackend>(btc: &mut B, locktime: &RelativeLocktime, tx_hex: &str) -> Result<bool> {
+    match btc.broadcast(tx_hex) {
+        Ok(_) => Ok(true),
+        Err(Error::TransactionRejected(ref reason))
+            if locktime.as_secs().is_some() && reason.contains("non-BIP68-final") => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decode a transaction from its hex encoding
+pub(crate) fn decode_tx(tx_hex: &Option<String>) -> Result<bitcoin::Transaction> {
+    let tx_hex = tx_hex.as_ref().ok_or(Error::MissingTransaction)?;
+    Ok(deserialize(&hex::decode(tx_hex)?)?)
+}
+
+/// Transaction id of a hex encoded transaction
+pub(crate) fn txid(tx_hex: &Option<String>) -> Result<sha256d::Hash> {
+    Ok(decode_tx(tx_hex)?.txid())
+}
+
+/// Outputs spent by the inputs of a hex encoded transaction, looked up on chain
+pub(crate) fn spent_outputs<B: BitcoinBackend>(backend: &B, tx_hex: &str) -> Result<Vec<bitcoin::TxOut>> {
+    let tx: bitcoin::Transaction = deserialize(&hex::decode(tx_hex)?)?;
+    tx.input.iter().map(|txin| {
+        let prev = backend.get_transaction(&txin.previous_output.txid)?
+            .ok_or(Error::MissingTransaction)?;
+        prev.output.get(txin.previous_output.vout as usize).cloned()
+            .ok_or(Error::MissingInput)
+    }).collect()
+}
+
+/// Send a message through the binary wire format
+fn relay(message: Message) -> Result<Message> {
+    Message::from_bytes(&message.to_bytes()?)
+}
+
+/// Run a swap between a buyer and a seller on the mock chains, one Bitcoin and one
+/// Monero block are mined between each round. Stop when both parties reached a final
+/// state or are halted, or after `max_blocks`
+pub fn simulate(buyer: &mut BuyerDriver, seller: &mut SellerDriver, btc: &mut MockChain, xmr: &FakeMonero,
+                max_blocks: u32) -> Result<()> {
+    let mut to_seller = VecDeque::new();
+    let mut to_buyer = VecDeque::new();
+    to_seller.push_back(buyer.start());
+    to_buyer.push_back(seller.start());
+
+    for _ in 0..max_blocks {
+        // Exchange messages and poll the chains until nothing moves in this block
+        loop {
+            let mut chains = Chains { btc: &mut *btc, xmr, wallet: xmr };
+            let states = (buyer.state(), seller.state());
+            let mut delivered = false;
+
+            while let Some(message) = to_seller.pop_front() {
+                to_buyer.extend(seller.handle(relay(message)?, &mut chains)?);
+                delivered = true;
+            }
+            while let Some(message) = to_buyer.pop_front() {
+                to_seller.extend(buyer.handle(relay(message)?, &mut chains)?);
+                delivered = true;
+            }
+            to_seller.extend(buyer.poll(&mut chains)?);
+            to_buyer.extend(seller.poll(&mut chains)?);
+
+            let idle = to_seller.is_empty() && to_buyer.is_empty();
+            if !delivered && idle && states == (buyer.state(), seller.state()) {
+                break;
+            }
+        }
+
+        if buyer.is_done() && seller.is_done() {
+            return Ok(());
+        }
+        btc.mine(1);
+        xmr.mine(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use bitcoin::Address;
+
+    use crate::types::{common, btc, xmr, RelativeLocktime};
+    use crate::types::amount::{Satoshi, Piconero};
+    use crate::types::btc::scripts::p2wpkh_script_pubkey;
+    use crate::types::state::SwapState;
+    use crate::backend::btc::{BitcoinBackend, MockChain};
+    use crate::backend::xmr::{MoneroBackend, FakeMonero};
+    use crate::backend::xmr::fake::FEE;
+    use super::{Coin, BuyerConfig, BuyerDriver, SellerConfig, SellerDriver, simulate, broadcast_locked};
+    use bitcoin::{Transaction, TxIn, TxOut, OutPoint, Script};
+    use bitcoin::consensus::encode::{serialize_hex, deserialize};
+
+    use std::str::FromStr;
+
+    const T_0: u16 = 10;
+    const T_1: u16 = 6;
+    const FEERATE: u64 = 2;
+    const SWAP_AMOUNT: u64 = 50_000_000;
+    const XMR_AMOUNT: u64 = 1_000_000_000_000;
+    const MAX_BLOCKS: u32 = 50;
+
+    // P2WPKH address of the private key 1 (BIP173 test vector)
+    const ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    struct Party {
+        spend: xmr::PrivateKey,
+        view: xmr::PrivateKey,
+    }
+
+    impl Party {
+        fn new(rng: &mut OsRng) -> Party {
+            Party { spend: xmr::PrivateKey::random(rng), view: xmr::PrivateKey::random(rng) }
+        }
+
+        fn address(&self) -> xmr::address::Address {
+            let (spend, view) = (xmr::keys::public_key(&self.spend), xmr::keys::public_key(&self.view));
+            xmr::address::Address::standard(xmr::address::Network::Stagenet, &spend, &view)
+        }
+
+        fn balance(&self, chain: &FakeMonero) -> u64 {
+            let spend = xmr::keys::public_key(&self.spend);
+            chain.scan(&self.view, &spend, 0).unwrap().iter().map(|output| output.amount).sum()
+        }
+    }
+
+    fn drivers(btc_chain: &mut MockChain, buyer_confirmations: u64, seller_confirmations: u32)
+        -> (BuyerDriver, SellerDriver, Party, Party) {
+        let mut rng = OsRng::new().expect("OsRng");
+        let networks = common::Networks::new(common::BtcNetwork::Testnet, xmr::address::Network::Stagenet);
+        let params = common::Params::new(RelativeLocktime::Blocks(T_0), RelativeLocktime::Blocks(T_1), networks,
+            common::Amounts::new(Satoshi(SWAP_AMOUNT), Piconero(XMR_AMOUNT))).unwrap();
+        let address = Address::from_str(ADDRESS).unwrap();
+
+        let mut key = [0u8; 32];
+        key[31] = 1;
+        let privkey = btc::PrivateKey::parse(&key).unwrap();
+        let utxo = btc_chain.fund(p2wpkh_script_pubkey(&btc::PublicKey::from_secret_key(&privkey)), 100_000_000);
+
+        let buyer_xmr = Party::new(&mut rng);
+        let seller_xmr = Party::new(&mut rng);
+        let buyer = BuyerDriver::new(BuyerConfig {
+            params: params.clone(),
+            coins: vec![Coin { utxo, address: address.clone(), privkey }],
+            address: address.clone(),
+            feerate: FEERATE,
+            min_confirmations: buyer_confirmations,
+            dest: buyer_xmr.address(),
+        }, &mut rng).unwrap();
+        let seller = SellerDriver::new(SellerConfig {
+            params,
+            address,
+            feerate: FEERATE,
+            min_confirmations: seller_confirmations,
+            dest: seller_xmr.address(),
+        }, &mut rng).unwrap();
+        (buyer, seller, buyer_xmr, seller_xmr)
+    }
+
+    #[test]
+    fn happy_path() {
+        let mut btc_chain = MockChain::new(FEERATE);
+        let xmr_chain = FakeMonero::new();
+        let (mut buyer, mut seller, buyer_xmr, _) = drivers(&mut btc_chain, 2, 1);
+
+        simulate(&mut buyer, &mut seller, &mut btc_chain, &xmr_chain, MAX_BLOCKS).unwrap();
+        assert_eq!(SwapState::Bought, buyer.state());
+        assert_eq!(SwapState::Bought, seller.state());
+        assert_eq!(XMR_AMOUNT - FEE, buyer_xmr.balance(&xmr_chain));
+    }
+
+    #[test]
+    fn reject_corrupted_signature() {
+        let mut btc_chain = MockChain::new(FEERATE);
+        let xmr_chain = FakeMonero::new();
+        let (mut buyer, mut seller, _, _) = drivers(&mut btc_chain, 2, 1);
+        simulate(&mut buyer, &mut seller, &mut btc_chain, &xmr_chain, MAX_BLOCKS).unwrap();
+        let funding = buyer.transactions().btx_1.clone().unwrap();
+        let buy = buyer.transactions().buy.clone().unwrap();
+
+        // Replay the swap on a chain holding the same coin
+        let mut key = [0u8; 32];
+        key[31] = 1;
+        let pubkey = btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&key).unwrap());
+        let mut replay = MockChain::new(FEERATE);
+        replay.fund(p2wpkh_script_pubkey(&pubkey), 100_000_000);
+
+        let corrupt = |tx_hex: &str| {
+            let mut tx: Transaction = deserialize(&hex::decode(tx_hex).unwrap()).unwrap();
+            // Flip a bit of `r` in the first signature
+            let sig = tx.input[0].witness.iter_mut().find(|item| item.len() > 64).unwrap();
+            sig[10] ^= 1;
+            serialize_hex(&tx)
+        };
+        // P2WPKH funding input
+        assert!(replay.broadcast(&corrupt(&funding)).is_err());
+        replay.broadcast(&funding).unwrap();
+        // P2WSH swaplock input
+        assert!(replay.broadcast(&corrupt(&buy)).is_err());
+        replay.broadcast(&buy).unwrap();
+    }
+
+    #[test]
+    fn refund_path() {
+        let mut btc_chain = MockChain::new(FEERATE);
+        let xmr_chain = FakeMonero::new();
+        // Seller locks just before t_0 and the lock is not confirmed enough in time
+        let (mut buyer, mut seller, buyer_xmr, seller_xmr) = drivers(&mut btc_chain, 3, T_0 as u32 - 1);
+
+        simulate(&mut buyer, &mut seller, &mut btc_chain, &xmr_chain, MAX_BLOCKS).unwrap();
+        assert_eq!(SwapState::Refunded, buyer.state());
+        assert_eq!(SwapState::Refunded, seller.state());
+        assert_eq!(0, buyer_xmr.balance(&xmr_chain));
+        assert_eq!(XMR_AMOUNT - FEE, seller_xmr.balance(&xmr_chain));
+    }
+
+    #[test]
+    fn punish_path() {
+        let mut btc_chain = MockChain::new(FEERATE);
+        let xmr_chain = FakeMonero::new();
+        let (mut buyer, mut seller, _, _) = drivers(&mut btc_chain, 1, 1);
+        // Buyer goes offline once the Monero are locked, without releasing the secret
+        buyer.halt_at(SwapState::XmrLocked);
+
+        simulate(&mut buyer, &mut seller, &mut btc_chain, &xmr_chain, MAX_BLOCKS).unwrap();
+        assert_eq!(SwapState::XmrLocked, buyer.state());
+        assert_eq!(SwapState::Punished, seller.state());
+        assert!(seller.transactions().claim_refund.is_some());
+    }
+
+    #[test]
+    fn retry_time_locked_broadcast() {
+        let mut btc_chain = MockChain::new(FEERATE);
+        let utxo = btc_chain.fund(Script::new(), 100_000);
+        let spend = |locktime: &RelativeLocktime| serialize_hex(&Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: utxo.txid, vout: 0 },
+                script_sig: Script::new(),
+                sequence: locktime.as_u32(),
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 99_000, script_pubkey: Script::new() }],
+        });
+
+        // Time based locktimes are retried, block based ones are exact and fail
+        let time = RelativeLocktime::Time(2);
+        assert_eq!(false, broadcast_locked(&mut btc_chain, &time, &spend(&time)).unwrap());
+        let blocks = RelativeLocktime::Blocks(2);
+        assert!(broadcast_locked(&mut btc_chain, &blocks, &spend(&blocks)).is_err());
+
+        btc_chain.mine(2);
+        assert_eq!(true, broadcast_locked(&mut btc_chain, &time, &spend(&time)).unwrap());
+    }
+}
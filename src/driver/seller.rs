@@ -0,0 +1,253 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Seller's driver, locks the Monero and acquires the bitcoins
+
+use crate::{Protocol, Phase, Xmr};
+use crate::types::{Result, Error, common, xmr};
+use crate::types::state::{SwapState, SwapTransactions};
+use crate::types::wire::Message;
+use crate::transactions::btc::funding::FundingTx;
+use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::btc::spend_refund::SpendRefundTx;
+use crate::transactions::xmr::Wallet;
+use crate::backend::btc::BitcoinBackend;
+use crate::backend::xmr::MoneroBackend;
+use super::{Chains, locktime_blocks, broadcast_locked, decode_tx, txid, spent_outputs};
+
+use bitcoin::{Address, OutPoint};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin_hashes::{Hash, sha256};
+use rand::{Rng, CryptoRng};
+
+/// Seller's swap parameters
+pub struct SellerConfig {
+    pub params: common::Params,
+    /// Address receiving the bitcoins
+    pub address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+    /// Confirmations of the funding transaction before locking the Monero
+    pub min_confirmations: u32,
+    /// Standard Monero address receiving the Monero back after a refund
+    pub dest: xmr::address::Address,
+}
+
+pub struct SellerDriver {
+    config: SellerConfig,
+    parameters: xmr::SetupParams,
+    setup: Option<xmr::Setup>,
+    state: SwapState,
+    transactions: SwapTransactions,
+    /// Secret `s` released by the buyer
+    secret: Option<[u8; 32]>,
+    /// Height of the chain when the funding transaction was confirmed
+    funded_height: u32,
+    halt_at: Option<SwapState>,
+}
+
+impl SellerDriver {
+    /// Generate the seller's setup parameters
+    pub fn new<R: Rng + CryptoRng>(config: SellerConfig, rng: &mut R) -> Result<SellerDriver> {
+        config.dest.check_standard()?;
+        let parameters = Xmr::setup(config.params.clone(), rng)?;
+        Ok(SellerDriver {
+            config,
+            parameters,
+            setup: None,
+            state: SwapState::Initialized,
+            transactions: SwapTransactions::default(),
+            secret: None,
+            funded_height: 0,
+            halt_at: None,
+        })
+    }
+
+    /// Stop acting once `state` is reached, simulates a party going offline
+    pub fn halt_at(&mut self, state: SwapState) {
+        self.halt_at = Some(state);
+    }
+
+    pub fn state(&self) -> SwapState {
+        self.state
+    }
+
+    pub fn transactions(&self) -> &SwapTransactions {
+        &self.transactions
+    }
+
+    /// Swap is over for the seller, or the seller is offline
+    pub fn is_done(&self) -> bool {
+        self.state.is_final() || self.halt_at == Some(self.state)
+    }
+
+    /// First message sent to the buyer
+    pub fn start(&self) -> Message {
+        Message::XmrSetup((&self.parameters).into())
+    }
+
+    /// Process a message from the buyer and return the answers
+    pub fn handle<B, M, W>(&mut self, message: Message, chains: &mut Chains<B, M, W>) -> Result<Vec<Message>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        if self.is_done() {
+            return Ok(vec![]);
+        }
+
+        match (self.state, message) {
+            (SwapState::Initialized, Message::BtcSetup(exported)) => {
+                self.setup = Some(Xmr::verify_setup(&self.parameters, &exported)?);
+                self.state.transition(SwapState::SetupVerified)?;
+                Ok(vec![])
+            },
+            (SwapState::SetupVerified, Message::InitialTransactions(transactions)) => {
+                let setup = self.setup.as_ref().ok_or(Error::MissingValue("setup"))?;
+                let verified = Xmr::execute(setup, &xmr::VerifyTransactions {
+                    transactions: transactions.clone(),
+                    spent_outputs: spent_outputs(&*chains.btc, &transactions.btx_1)?,
+                })?;
+                self.state.transition(SwapState::RefundSigned)?;
+                self.transactions.btx_1 = Some(transactions.btx_1);
+                self.transactions.btx_2 = Some(transactions.btx_2);
+                self.transactions.btx_2_signed = Some(verified.btx_2_signed.clone());
+                Ok(vec![Message::VerifiedTransaction(verified)])
+            },
+            (SwapState::XmrLocked, Message::Secret(s)) => {
+                let setup = self.setup.as_ref().ok_or(Error::MissingValue("setup"))?;
+                if sha256::Hash::hash(&s)[..] != setup.h_2[..] {
+                    return Err(Error::InvalidSecret);
+                }
+                self.secret = Some(s);
+                self.state.transition(SwapState::SecretReleased)?;
+                Ok(vec![])
+            },
+            _ => Err(Error::UnexpectedMessage),
+        }
+    }
+
+    /// Follow the chains and act until nothing more can be done in the current block,
+    /// return the messages for the buyer
+    pub fn poll<B, M, W>(&mut self, chains: &mut Chains<B, M, W>) -> Result<Vec<Message>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        let mut messages = vec![];
+        while !self.is_done() {
+            match self.step(chains)? {
+                Some(mut out) => messages.append(&mut out),
+                None => break,
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Move to the next state if possible, none if waiting
+    fn step<B, M, W>(&mut self, chains: &mut Chains<B, M, W>) -> Result<Option<Vec<Message>>>
+        where B: BitcoinBackend, M: MoneroBackend, W: Wallet {
+        let setup = match self.setup.as_ref() {
+            Some(setup) => setup,
+            None => return Ok(None),
+        };
+
+        // The buyer can broadcast the refund transaction after t_0
+        if self.state.can_refund() {
+            if chains.btc.get_transaction(&txid(&self.transactions.btx_2_signed)?)?.is_some() {
+                self.state.transition(SwapState::RefundStarted)?;
+                return Ok(Some(vec![]));
+            }
+        }
+
+        let funding = match self.transactions.btx_1 {
+            Some(_) => chains.btc.confirmations(&txid(&self.transactions.btx_1)?)?.unwrap_or(0),
+            None => 0,
+        };
+
+        match self.state {
+            SwapState::RefundSigned => {
+                if funding > 0 {
+                    self.funded_height = chains.btc.block_height()? + 1 - funding;
+                    self.state.transition(SwapState::Funded)?;
+                    return Ok(Some(vec![]));
+                }
+            },
+            // Do not lock the Monero once the buyer can refund
+            SwapState::Funded if funding >= self.config.min_confirmations && funding < locktime_blocks(&setup.t_0) => {
+                let xtx = Xmr::execute(setup, &xmr::InitiateSwap {
+                    wallet: chains.wallet,
+                    backend: chains.xmr,
+                })?;
+                self.transactions.xtx = Some(xtx.clone());
+                self.state.transition(SwapState::XmrLocked)?;
+                return Ok(Some(vec![Message::LockedXmr(xtx)]));
+            },
+            // Buyer is silent, start the refund to claim it after t_1
+            SwapState::XmrLocked if funding >= locktime_blocks(&setup.t_0) => {
+                let btx_2 = self.transactions.btx_2_signed.clone().ok_or(Error::MissingValue("btx_2_signed"))?;
+                if broadcast_locked(chains.btc, &setup.t_0, &btx_2)? {
+                    self.state.transition(SwapState::RefundStarted)?;
+                    return Ok(Some(vec![]));
+                }
+            },
+            SwapState::SecretReleased => {
+                let buy = Xmr::execute(setup, &xmr::Swap {
+                    funding: FundingTx::from_hex(self.transactions.btx_1.clone().ok_or(Error::MissingValue("btx_1"))?),
+                    address: self.config.address.clone(),
+                    s: self.secret.ok_or(Error::MissingValue("secret"))?,
+                    feerate: self.config.feerate,
+                })?;
+                chains.btc.broadcast(&buy)?;
+                self.transactions.buy = Some(buy);
+                self.state.transition(SwapState::Bought)?;
+                return Ok(Some(vec![]));
+            },
+            SwapState::RefundStarted => {
+                let btx_2 = decode_tx(&self.transactions.btx_2_signed)?;
+                let refund = OutPoint { txid: btx_2.txid(), vout: 0 };
+
+                // Buyer spent the refund and revealed its key share
+                if let Some(spend_refund) = chains.btc.spending_transaction(&refund, self.funded_height)? {
+                    let spend_refund = serialize_hex(&spend_refund);
+                    if self.transactions.xtx.is_some() {
+                        Xmr::execute(setup, &xmr::RecoverXmrAfterRefund {
+                            spend_refund: SpendRefundTx::from_hex(spend_refund.clone()),
+                            dest: self.config.dest,
+                            wallet: chains.wallet,
+                            backend: chains.xmr,
+                        })?;
+                    }
+                    self.transactions.spend_refund = Some(spend_refund);
+                    self.state.transition(SwapState::Refunded)?;
+                    return Ok(Some(vec![]));
+                }
+
+                let confirmations = chains.btc.confirmations(&btx_2.txid())?.unwrap_or(0);
+                if confirmations >= locktime_blocks(&setup.t_1) {
+                    let btx_1 = FundingTx::from_hex(self.transactions.btx_1.clone().ok_or(Error::MissingValue("btx_1"))?);
+                    let btx_2 = RefundTx::from_hex(serialize_hex(&btx_2), &btx_1);
+                    let claim_refund = Xmr::execute(setup, &xmr::ClaimRefund {
+                        btx_2_signed: &btx_2,
+                        address: self.config.address.clone(),
+                        feerate: self.config.feerate,
+                    })?;
+                    if broadcast_locked(chains.btc, &setup.t_1, &claim_refund)? {
+                        self.transactions.claim_refund = Some(claim_refund);
+                        self.state.transition(SwapState::Punished)?;
+                        return Ok(Some(vec![]));
+                    }
+                }
+            },
+            _ => (),
+        }
+        Ok(None)
+    }
+}
@@ -13,8 +13,6 @@
 // copies or substantial portions of the Software.
 //
 
-#![feature(try_trait)]
-
 extern crate rand;
 extern crate hex;
 extern crate curve25519_dalek;
@@ -22,14 +20,31 @@ extern crate secp256k1;
 extern crate bitcoin;
 extern crate bitcoin_hashes;
 extern crate wasm_bindgen;
+extern crate tiny_keccak;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate bincode;
+extern crate chacha20poly1305;
+extern crate argon2;
+#[cfg(feature = "rpc")]
+extern crate bitcoincore_rpc;
+#[cfg(feature = "rpc")]
+extern crate jsonrpc;
 
 pub mod node;
 pub mod types;
 pub mod transactions;
 pub mod protocol;
+pub mod store;
+pub mod backend;
+pub mod driver;
 
 pub use crate::protocol::{Protocol, Phase};
 pub use crate::protocol::btc::Btc;
 pub use crate::protocol::xmr::Xmr;
+pub use crate::protocol::adaptor::btc::AdaptorBtc;
+pub use crate::protocol::adaptor::xmr::AdaptorXmr;
 pub use crate::node::Buyer;
 pub use crate::node::Seller;
@@ -16,19 +16,35 @@
 //! Export buyer/seller nodes with wasm-bindgen links
 
 use crate::{Protocol, Phase, Btc, Xmr};
-use crate::types::{common, xmr, btc, RelativeLocktime};
+use crate::types::{common, xmr, btc, RelativeLocktime, Error};
+use crate::types::amount::{Satoshi, Piconero};
+use crate::types::state::{SwapState, SwapTransactions, Snapshot, BuyerSnapshot, SellerSnapshot};
 
 use rand::rngs::OsRng;
+use bitcoin::Address;
 use bitcoin_hashes::hex::FromHex;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
 
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Relative locktime of `value` blocks, or expiring after at least `value` seconds
+fn relative_locktime(value: u32, unit: &str) -> Result<RelativeLocktime, Error> {
+    match unit {
+        "blocks" => RelativeLocktime::from_blocks(value),
+        "seconds" => RelativeLocktime::from_duration(Duration::from_secs(u64::from(value))),
+        _ => Err(Error::InvalidLocktime("unknown unit")),
+    }
+}
+
 /// Seller node wants to swap Monero for Bitcoin
 #[wasm_bindgen]
 pub struct Seller {
     parameters: Option<xmr::SetupParams>,
     setup: Option<xmr::Setup>,
-    setup_is_ready: bool,
+    state: SwapState,
+    transactions: SwapTransactions,
 }
 
 #[wasm_bindgen]
@@ -38,17 +54,26 @@ impl Seller {
         Seller {
             parameters: None,
             setup: None,
-            setup_is_ready: false,
+            state: SwapState::Initialized,
+            transactions: SwapTransactions::default(),
         }
     }
 
-    /// Generates the first parameters with randomness
-    pub fn generate_params(&mut self, t0: u16, t1: u16) -> Result<(), JsValue> {
+    /// Generates the first parameters with randomness. Locktimes are in `blocks` or in
+    /// `seconds` as given by `locktime_unit`, networks are given by name, e.g. `testnet` and
+    /// `stagenet`, amounts in satoshi and piconero are passed as `BigInt`
+    pub fn generate_params(&mut self, t0: u32, t1: u32, locktime_unit: &str, btc_network: &str,
+                           xmr_network: &str, btc_amount: u64, xmr_amount: u64) -> Result<(), JsValue> {
         let mut rng = OsRng::new().expect("OsRng");
         let params = common::Params::new(
-            RelativeLocktime::Blocks(t0),
-            RelativeLocktime::Blocks(t1),
-        );
+            relative_locktime(t0, locktime_unit)?,
+            relative_locktime(t1, locktime_unit)?,
+            common::Networks::new(
+                common::BtcNetwork::from_str(btc_network)?,
+                xmr::address::Network::from_str(xmr_network)?,
+            ),
+            common::Amounts::new(Satoshi(btc_amount), Piconero(xmr_amount)),
+        )?;
         self.parameters = Some(Xmr::setup(params.clone(), &mut rng)?);
         Ok(())
     }
@@ -66,8 +91,8 @@ impl Seller {
         match &self.parameters {
             Some(params) => {
                 let xmr_setup = Xmr::verify_setup(params, buyer_params)?;
+                self.state.transition(SwapState::SetupVerified)?;
                 self.setup = Some(xmr_setup);
-                self.setup_is_ready = true;
                 Ok(())
             },
             None => Err("Parameters is missing".into()),
@@ -76,7 +101,39 @@ impl Seller {
 
     /// Check if the setup is complete
     pub fn is_setup_ready(&self) -> bool {
-        self.setup_is_ready
+        self.setup.is_some()
+    }
+
+    /// Monero address of the shared lock wallet
+    pub fn lock_address(&self) -> Result<String, JsValue> {
+        let setup = self.setup.as_ref().ok_or(Error::MissingValue("setup"))?;
+        Ok(setup.lock_address().to_string())
+    }
+
+    /// Current state of the swap
+    pub fn state(&self) -> String {
+        format!("{:?}", self.state)
+    }
+
+    /// Serialize the swap with its secrets to resume it later
+    pub fn snapshot(&self) -> Result<String, JsValue> {
+        Ok(Snapshot::new(
+            self.state,
+            self.parameters.as_ref(),
+            self.setup.as_ref(),
+            self.transactions.clone(),
+        ).to_json()?)
+    }
+
+    /// Reload a swap from a snapshot
+    pub fn restore(snapshot: &str) -> Result<Seller, JsValue> {
+        let snapshot = SellerSnapshot::from_json(snapshot)?;
+        Ok(Seller {
+            parameters: snapshot.parameters,
+            setup: snapshot.setup,
+            state: snapshot.state,
+            transactions: snapshot.transactions,
+        })
     }
 }
 
@@ -85,7 +142,8 @@ impl Seller {
 pub struct Buyer {
     parameters: Option<btc::SetupParams>,
     setup: Option<btc::Setup>,
-    setup_is_ready: bool,
+    state: SwapState,
+    transactions: SwapTransactions,
 }
 
 #[wasm_bindgen]
@@ -95,17 +153,26 @@ impl Buyer {
         Buyer {
             parameters: None,
             setup: None,
-            setup_is_ready: false,
+            state: SwapState::Initialized,
+            transactions: SwapTransactions::default(),
         }
     }
 
-    /// Generates the first parameters with randomness
-    pub fn generate_params(&mut self, t0: u16, t1: u16) -> Result<(), JsValue> {
+    /// Generates the first parameters with randomness. Locktimes are in `blocks` or in
+    /// `seconds` as given by `locktime_unit`, networks are given by name, e.g. `testnet` and
+    /// `stagenet`, amounts in satoshi and piconero are passed as `BigInt`
+    pub fn generate_params(&mut self, t0: u32, t1: u32, locktime_unit: &str, btc_network: &str,
+                           xmr_network: &str, btc_amount: u64, xmr_amount: u64) -> Result<(), JsValue> {
         let mut rng = OsRng::new().expect("OsRng");
         let params = common::Params::new(
-            RelativeLocktime::Blocks(t0),
-            RelativeLocktime::Blocks(t1),
-        );
+            relative_locktime(t0, locktime_unit)?,
+            relative_locktime(t1, locktime_unit)?,
+            common::Networks::new(
+                common::BtcNetwork::from_str(btc_network)?,
+                xmr::address::Network::from_str(xmr_network)?,
+            ),
+            common::Amounts::new(Satoshi(btc_amount), Piconero(xmr_amount)),
+        )?;
         self.parameters = Some(Btc::setup(params.clone(), &mut rng)?);
         Ok(())
     }
@@ -123,8 +190,8 @@ impl Buyer {
         match &self.parameters {
             Some(params) => {
                 let btc_setup = Btc::verify_setup(params, seller_params)?;
+                self.state.transition(SwapState::SetupVerified)?;
                 self.setup = Some(btc_setup);
-                self.setup_is_ready = true;
                 Ok(())
             },
             None => Err("Parameters is missing".into()),
@@ -133,24 +200,64 @@ impl Buyer {
 
     /// Check if the setup is complete
     pub fn is_setup_ready(&self) -> bool {
-        self.setup_is_ready
+        self.setup.is_some()
+    }
+
+    /// Monero address of the shared lock wallet
+    pub fn lock_address(&self) -> Result<String, JsValue> {
+        let setup = self.setup.as_ref().ok_or(Error::MissingValue("setup"))?;
+        Ok(setup.lock_address().to_string())
+    }
+
+    /// Current state of the swap
+    pub fn state(&self) -> String {
+        format!("{:?}", self.state)
     }
 
-    /// Create the first Bitcoin transaction and return it as hex string
-    pub fn create_transactions(&mut self, txid: &str, vout: u32, amount: u32) -> Result<String, JsValue> {
-        // TODO: amount is u32 for testing, u64 use BigInt in JS, but only supported on Chrome
-        // right now
+    /// Serialize the swap with its secrets to resume it later
+    pub fn snapshot(&self) -> Result<String, JsValue> {
+        Ok(Snapshot::new(
+            self.state,
+            self.parameters.as_ref(),
+            self.setup.as_ref(),
+            self.transactions.clone(),
+        ).to_json()?)
+    }
+
+    /// Reload a swap from a snapshot
+    pub fn restore(snapshot: &str) -> Result<Buyer, JsValue> {
+        let snapshot = BuyerSnapshot::from_json(snapshot)?;
+        Ok(Buyer {
+            parameters: snapshot.parameters,
+            setup: snapshot.setup,
+            state: snapshot.state,
+            transactions: snapshot.transactions,
+        })
+    }
+
+    /// Create the first Bitcoin transaction locking the agreed amount from the coin, with the
+    /// change sent to `change_address` and a feerate in satoshi per virtual byte, and return it
+    /// as hex string. The coin amount in satoshi is passed as `BigInt`
+    pub fn create_transactions(&mut self, txid: &str, vout: u32, amount: u64,
+                               change_address: &str, feerate: u32) -> Result<String, JsValue> {
         match &self.setup {
             Some(setup) => {
                 let txid = bitcoin_hashes::sha256d::Hash::from_hex(txid)
                     .map_err(|e| format!("{:?}", e))?;
+                let change_address = Address::from_str(change_address)
+                    .map_err(|e| format!("{:?}", e))?;
                 let init_txs = Btc::execute(setup, &btc::CreateTransactions {
-                    utxo: btc::Utxo {
+                    utxos: vec![btc::Utxo {
                         txid,
                         vout,
-                        amount: amount as u64,
-                    },
+                        amount,
+                    }],
+                    change_address,
+                    feerate: feerate as u64,
                 })?;
+                self.state.transition(SwapState::TransactionsCreated)?;
+                self.transactions.btx_1 = Some(init_txs.btx_1.clone());
+                self.transactions.btx_2 = Some(init_txs.btx_2);
                 Ok(init_txs.btx_1)
             },
             None => Err("Setup is missing".into()),
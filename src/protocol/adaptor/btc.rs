@@ -0,0 +1,287 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Implements the adaptor signature protocol and phases for the Bitcoin side
+
+use crate::types::{Result, Error, TxKind};
+use crate::types::{btc, xmr, common, dleq, adaptor};
+use crate::types::btc::fee;
+use crate::types::btc::LockFunds;
+use crate::types::btc::adaptor::{decrypt, recover, EncryptedSignature};
+use crate::types::btc::scripts::{create_adaptor_swaplock, create_adaptor_refund, extract_adaptor_multisig};
+use crate::transactions as tx;
+use crate::transactions::Transaction;
+use crate::transactions::btc::buy::BuyTx;
+use crate::transactions::btc::funding::FundingTx;
+use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::btc::spend_refund::SpendRefundTx;
+use crate::transactions::xmr::{LockTx, SweepTx, Wallet};
+use crate::backend::xmr::MoneroBackend;
+use crate::transactions::{Builder, Validator};
+use crate::protocol::{Protocol, Phase};
+use crate::protocol::btc::Btc;
+
+use rand::{Rng, CryptoRng};
+
+pub struct AdaptorBtc;
+
+impl Phase<adaptor::CreateTransactions> for AdaptorBtc {
+    type Ret = adaptor::InitialTransactions;
+
+    fn execute(setup: &btc::Setup, params: &adaptor::CreateTransactions) -> Result<adaptor::InitialTransactions> {
+        setup.networks.btc.check(&params.change_address)?;
+        setup.networks.btc.check(&params.refund_address)?;
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+        let refund_script = create_adaptor_refund(&setup.B_a, &setup.B_b, setup.t_1.as_u32());
+
+        let mut btx_1 = FundingTx::new();
+        btx_1.build(tx::btc::funding::New {
+            utxos: &params.utxos,
+            amount: setup.amounts.btc,
+            change_address: &params.change_address,
+            swaplock_script: &swaplock_script,
+            feerate: params.feerate,
+        })?;
+
+        let mut btx_2 = RefundTx::new(&btx_1);
+        btx_2.build(tx::btc::refund::New {
+            refund_script: &refund_script,
+            t_0: setup.t_0,
+            witness_size: fee::swaplock_multisig_witness(&swaplock_script),
+            feerate: params.feerate,
+        })?;
+
+        let sig_b = btx_2.build(tx::btc::refund::Sign {
+            privkey: &setup.b_b,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        let mut spend_refund = SpendRefundTx::new();
+        spend_refund.build(tx::btc::common::New {
+            prev_tx: &btx_2,
+            final_address: params.refund_address.clone(),
+            witness_size: fee::adaptor_multisig_witness(&refund_script),
+            feerate: params.feerate,
+        })?;
+
+        Ok(adaptor::InitialTransactions {
+            transactions: btc::InitialTransactions {
+                btx_1: btx_1.to_hex().ok_or(Error::MissingTransaction)?,
+                btx_2: btx_2.to_hex().ok_or(Error::MissingTransaction)?,
+                sig_b,
+            },
+            spend_refund: spend_refund.to_hex().ok_or(Error::MissingTransaction)?,
+        })
+    }
+}
+
+impl<'a> Phase<LockFunds<'a>> for AdaptorBtc {
+    type Ret = String;
+
+    fn execute(setup: &btc::Setup, params: &LockFunds) -> Result<String> {
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+
+        let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
+        let btx_2 = RefundTx::from_hex(params.btx_2.clone(), &btx_1);
+        let btx_2_signed = RefundTx::from_hex(params.btx_2_signed.clone(), &btx_1);
+
+        btx_2_signed.validate(tx::btc::refund::VerifySigned {
+            btx_2: &btx_2,
+            pubkey: &setup.B_b,
+            swaplock_script: &swaplock_script,
+        })?;
+        btx_2_signed.validate(tx::btc::refund::VerifySigA {
+            pubkey: &setup.B_a,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        let sigs = btx_1.build(tx::btc::funding::Sign {
+            inputs: &params.inputs,
+        })?;
+
+        Ok(btx_1.build(tx::btc::funding::Finalize { sigs })?)
+    }
+}
+
+impl Phase<btc::ExportFunding> for AdaptorBtc {
+    type Ret = String;
+
+    fn execute(setup: &btc::Setup, params: &btc::ExportFunding) -> Result<String> {
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+
+        let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
+        btx_1.build(tx::btc::funding::ExportPsbt {
+            inputs: &params.inputs,
+            swaplock_script: &swaplock_script,
+        })
+    }
+}
+
+impl Phase<btc::LockFundsPsbt> for AdaptorBtc {
+    type Ret = String;
+
+    fn execute(setup: &btc::Setup, params: &btc::LockFundsPsbt) -> Result<String> {
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+
+        let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
+        let btx_2 = RefundTx::from_hex(params.btx_2.clone(), &btx_1);
+        let btx_2_signed = RefundTx::from_hex(params.btx_2_signed.clone(), &btx_1);
+
+        btx_2_signed.validate(tx::btc::refund::VerifySigned {
+            btx_2: &btx_2,
+            pubkey: &setup.B_b,
+            swaplock_script: &swaplock_script,
+        })?;
+        btx_2_signed.validate(tx::btc::refund::VerifySigA {
+            pubkey: &setup.B_a,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        btx_1.build(tx::btc::funding::FinalizePsbt {
+            psbt: &params.psbt,
+        })
+    }
+}
+
+impl<'a, B> Phase<adaptor::VerifyXmrLock<'a, B>> for AdaptorBtc where B: MoneroBackend {
+    type Ret = EncryptedSignature;
+
+    fn execute(setup: &btc::Setup, params: &adaptor::VerifyXmrLock<B>) -> Result<EncryptedSignature> {
+        setup.networks.btc.check(&params.address)?;
+        // Validate the transaction as seen by the chain, not the copy sent by the seller
+        let chain_tx = params.backend.get_transaction(&params.xtx.tx_hash)?
+            .ok_or(Error::NotEnoughConfirmations)?;
+        let xtx = LockTx::from_hex(chain_tx.tx_hex);
+
+        xtx.validate(tx::xmr::VerifyLock {
+            view: &setup.a,
+            spend: &setup.X,
+            amount: setup.amounts.xmr,
+        })?;
+
+        let confirmations = params.backend.confirmations(&params.xtx.tx_hash)?.unwrap_or(0);
+        if confirmations < params.min_confirmations {
+            return Err(Error::NotEnoughConfirmations);
+        }
+
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+
+        let mut buy = BuyTx::new();
+        buy.build(tx::btc::common::New {
+            prev_tx: &params.funding,
+            final_address: params.address.clone(),
+            witness_size: fee::adaptor_multisig_witness(&swaplock_script),
+            feerate: params.feerate,
+        })?;
+
+        buy.build(tx::btc::common::EncryptedSign {
+            privkey: &setup.b_b,
+            script: &swaplock_script,
+            prev_tx: &params.funding,
+            adaptor: &setup.X_0_secp,
+        })
+    }
+}
+
+impl<'a, W, B> Phase<adaptor::ReleaseXmr<'a, W, B>> for AdaptorBtc where W: Wallet, B: MoneroBackend {
+    type Ret = xmr::SweptXmr;
+
+    fn execute(setup: &btc::Setup, params: &adaptor::ReleaseXmr<W, B>) -> Result<xmr::SweptXmr> {
+        setup.networks.xmr.check(&params.dest)?;
+        params.dest.check_standard()?;
+        let buy = params.buy.to_transaction()?;
+        let input = buy.input.get(0).ok_or(Error::MissingInput)?;
+        let (_, sig_b) = extract_adaptor_multisig(&input.witness)
+            .ok_or(Error::InvalidSignature { tx: TxKind::Buy, input: 0, sig: "sig_b" })?;
+        let x_0 = recover(&params.sig_b, &sig_b, &setup.X_0_secp)
+            .map_err(|e| e.on_signature(TxKind::Buy, 0, "sig_b"))?;
+        let x_0 = dleq::ed25519_scalar(&x_0)?;
+
+        let x = x_0 + setup.x_1;
+        if xmr::keys::public_key(&x) != setup.X {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let mut sweep = SweepTx::new();
+        sweep.build(tx::xmr::NewSweep {
+            spend: &x,
+            view: &setup.a,
+            dest_spend: &params.dest.spend,
+            dest_view: &params.dest.view,
+        })?;
+
+        let signed = sweep.build(tx::xmr::Sign {
+            wallet: params.wallet,
+        })?;
+
+        sweep.build(tx::xmr::Broadcast {
+            backend: params.backend,
+            signed: &signed,
+        })?;
+
+        Ok(xmr::SweptXmr {
+            x,
+            sweep: signed,
+        })
+    }
+}
+
+impl<'a> Phase<adaptor::SpendRefund<'a>> for AdaptorBtc {
+    type Ret = String;
+
+    #[allow(non_snake_case)]
+    fn execute(setup: &btc::Setup, params: &adaptor::SpendRefund) -> Result<String> {
+        let refund_script = create_adaptor_refund(&setup.B_a, &setup.B_b, setup.t_1.as_u32());
+        let x_1 = dleq::secp256k1_scalar(&setup.x_1)?;
+        let X_1_secp = btc::PublicKey::from_secret_key(&x_1);
+
+        let mut spend_refund = SpendRefundTx::from_hex(params.spend_refund.clone());
+        spend_refund.validate(tx::btc::common::VerifyEncryptedSig {
+            pubkey: &setup.B_a,
+            sig: &params.sig_a,
+            script: &refund_script,
+            prev_tx: params.btx_2_signed,
+            adaptor: &X_1_secp,
+        })?;
+
+        let sig_a = decrypt(&params.sig_a, &x_1);
+        let sig_b = spend_refund.build(tx::btc::common::Sign {
+            privkey: &setup.b_b,
+            script: &refund_script,
+            prev_tx: params.btx_2_signed,
+        })?;
+
+        spend_refund.build(tx::btc::common::FinalizeMultisig {
+            sig_a,
+            sig_b,
+            script: refund_script,
+        })
+    }
+}
+
+impl Protocol for AdaptorBtc {
+    type Output = btc::SetupParams;
+    type Input = xmr::ExportedSetupParams;
+    type Setup = btc::Setup;
+
+    fn setup<R: Rng + CryptoRng>(params: common::Params, rng: &mut R) -> Result<btc::SetupParams> {
+        Btc::setup(params, rng)
+    }
+
+    fn verify_setup(params: &btc::SetupParams, export: &xmr::ExportedSetupParams) -> Result<btc::Setup> {
+        Btc::verify_setup(params, export)
+    }
+}
@@ -0,0 +1,23 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Adaptor signature variant of the protocol. The setup is shared with the hash
+//! lock variant, but the swaplock and refund outputs are plain 2-of-2 multisig with
+//! timelocks and the Monero key shares are revealed by completing ECDSA adaptor
+//! signatures encrypted under the secp256k1 points proven equal to `X_0` and `X_1`
+
+pub mod btc;
+pub mod xmr;
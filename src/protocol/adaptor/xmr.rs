@@ -0,0 +1,233 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Implements the adaptor signature protocol and phases for the Monero side
+
+use crate::types::{Result, Error, TxKind};
+use crate::types::{btc, xmr, common, dleq, adaptor};
+use crate::types::btc::fee;
+use crate::types::xmr::{InitiateSwap, LockedXmr, ClaimRefund, SweptXmr};
+use crate::types::btc::adaptor::{decrypt, recover};
+use crate::types::btc::scripts::{create_adaptor_swaplock, create_adaptor_refund, extract_adaptor_multisig};
+use crate::transactions as tx;
+use crate::transactions::Transaction;
+use crate::transactions::btc::buy::BuyTx;
+use crate::transactions::btc::funding::FundingTx;
+use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::btc::spend_refund::SpendRefundTx;
+use crate::transactions::xmr::{SweepTx, Wallet};
+use crate::backend::xmr::MoneroBackend;
+use crate::transactions::{Builder, Validator};
+use crate::protocol::{Protocol, Phase};
+use crate::protocol::xmr::Xmr;
+
+use rand::{Rng, CryptoRng};
+
+pub struct AdaptorXmr;
+
+impl Phase<adaptor::VerifyTransactions> for AdaptorXmr {
+    type Ret = adaptor::VerifiedTransactions;
+
+    fn execute(setup: &xmr::Setup, params: &adaptor::VerifyTransactions) -> Result<adaptor::VerifiedTransactions> {
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+        let refund_script = create_adaptor_refund(&setup.B_a, &setup.B_b, setup.t_1.as_u32());
+        let transactions = &params.transactions.transactions;
+
+        let btx_1 = FundingTx::from_hex(transactions.btx_1.clone());
+        btx_1.validate(tx::btc::funding::VerifyFunding {
+            swaplock_script: &swaplock_script,
+            amount: setup.amounts.btc,
+            spent_outputs: &params.spent_outputs,
+        })?;
+        let mut btx_2 = RefundTx::from_hex(transactions.btx_2.clone(), &btx_1);
+        btx_2.validate(tx::btc::refund::VerifyStructure {
+            refund_script: &refund_script,
+            t_0: setup.t_0,
+            witness_size: fee::swaplock_multisig_witness(&swaplock_script),
+        })?;
+
+        btx_2.validate(tx::btc::refund::VerifySigB {
+            pubkey: &setup.B_b,
+            sig: &transactions.sig_b,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        let sig_a = btx_2.build(tx::btc::refund::Sign {
+            privkey: &setup.b_a,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        let btx_2_signed = btx_2.build(tx::btc::refund::Finalize {
+            sig_a,
+            sig_b: transactions.sig_b.clone(),
+            swaplock_script,
+        })?;
+
+        let mut spend_refund = SpendRefundTx::from_hex(params.transactions.spend_refund.clone());
+        let spend_refund_sig = spend_refund.build(tx::btc::common::EncryptedSign {
+            privkey: &setup.b_a,
+            script: &refund_script,
+            prev_tx: &btx_2,
+            adaptor: &setup.X_1_secp,
+        })?;
+
+        Ok(adaptor::VerifiedTransactions {
+            btx_2_signed,
+            spend_refund_sig,
+        })
+    }
+}
+
+impl<'a, W, B> Phase<InitiateSwap<'a, W, B>> for AdaptorXmr where W: Wallet, B: MoneroBackend {
+    type Ret = LockedXmr;
+
+    fn execute(setup: &xmr::Setup, params: &InitiateSwap<W, B>) -> Result<LockedXmr> {
+        Xmr::execute(setup, params)
+    }
+}
+
+impl Phase<adaptor::Swap> for AdaptorXmr {
+    type Ret = String;
+
+    #[allow(non_snake_case)]
+    fn execute(setup: &xmr::Setup, params: &adaptor::Swap) -> Result<String> {
+        setup.networks.btc.check(&params.address)?;
+        let swaplock_script = create_adaptor_swaplock(&setup.B_a, &setup.B_b, setup.t_0.as_u32());
+        let x_0 = dleq::secp256k1_scalar(&setup.x_0)?;
+        let X_0_secp = btc::PublicKey::from_secret_key(&x_0);
+
+        let mut buy = BuyTx::new();
+        buy.build(tx::btc::common::New {
+            prev_tx: &params.funding,
+            final_address: params.address.clone(),
+            witness_size: fee::adaptor_multisig_witness(&swaplock_script),
+            feerate: params.feerate,
+        })?;
+
+        buy.validate(tx::btc::common::VerifyEncryptedSig {
+            pubkey: &setup.B_b,
+            sig: &params.sig_b,
+            script: &swaplock_script,
+            prev_tx: &params.funding,
+            adaptor: &X_0_secp,
+        })?;
+
+        let sig_b = decrypt(&params.sig_b, &x_0);
+        let sig_a = buy.build(tx::btc::common::Sign {
+            privkey: &setup.b_a,
+            script: &swaplock_script,
+            prev_tx: &params.funding,
+        })?;
+
+        buy.build(tx::btc::common::FinalizeMultisig {
+            sig_a,
+            sig_b,
+            script: swaplock_script,
+        })
+    }
+}
+
+impl<'a> Phase<ClaimRefund<'a>> for AdaptorXmr {
+    type Ret = String;
+
+    fn execute(setup: &xmr::Setup, params: &ClaimRefund) -> Result<String> {
+        setup.networks.btc.check(&params.address)?;
+        let refund_script = create_adaptor_refund(&setup.B_a, &setup.B_b, setup.t_1.as_u32());
+
+        let mut claim_refund = tx::btc::claim_refund::ClaimRefundTx::new();
+        claim_refund.build(tx::btc::claim_refund::New {
+            refund_tx: params.btx_2_signed,
+            t_1: setup.t_1,
+            final_address: params.address.clone(),
+            witness_size: fee::refund_claim_witness(&refund_script),
+            feerate: params.feerate,
+        })?;
+
+        let sig = claim_refund.build(tx::btc::common::Sign {
+            privkey: &setup.b_a,
+            script: &refund_script,
+            prev_tx: params.btx_2_signed,
+        })?;
+
+        claim_refund.build(tx::btc::common::Finalize {
+            sig,
+            preimages: None,
+            script: refund_script,
+            secret: None,
+        })
+    }
+}
+
+impl<'a, W, B> Phase<adaptor::RecoverXmrAfterRefund<'a, W, B>> for AdaptorXmr where W: Wallet, B: MoneroBackend {
+    type Ret = SweptXmr;
+
+    fn execute(setup: &xmr::Setup, params: &adaptor::RecoverXmrAfterRefund<W, B>) -> Result<SweptXmr> {
+        setup.networks.xmr.check(&params.dest)?;
+        params.dest.check_standard()?;
+        let spend_refund = params.spend_refund.to_transaction()?;
+        let input = spend_refund.input.get(0).ok_or(Error::MissingInput)?;
+        let (sig_a, _) = extract_adaptor_multisig(&input.witness)
+            .ok_or(Error::InvalidSignature { tx: TxKind::SpendRefund, input: 0, sig: "sig_a" })?;
+        let x_1 = recover(&params.sig_a, &sig_a, &setup.X_1_secp)
+            .map_err(|e| e.on_signature(TxKind::SpendRefund, 0, "sig_a"))?;
+        let x_1 = dleq::ed25519_scalar(&x_1)?;
+
+        if xmr::keys::public_key(&x_1) != setup.X_1 {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let x = setup.x_0 + x_1;
+        if xmr::keys::public_key(&x) != setup.X {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let mut sweep = SweepTx::new();
+        sweep.build(tx::xmr::NewSweep {
+            spend: &x,
+            view: &setup.a,
+            dest_spend: &params.dest.spend,
+            dest_view: &params.dest.view,
+        })?;
+
+        let signed = sweep.build(tx::xmr::Sign {
+            wallet: params.wallet,
+        })?;
+
+        sweep.build(tx::xmr::Broadcast {
+            backend: params.backend,
+            signed: &signed,
+        })?;
+
+        Ok(SweptXmr {
+            x,
+            sweep: signed,
+        })
+    }
+}
+
+impl Protocol for AdaptorXmr {
+    type Output = xmr::SetupParams;
+    type Input = btc::ExportedSetupParams;
+    type Setup = xmr::Setup;
+
+    fn setup<R: Rng + CryptoRng>(params: common::Params, rng: &mut R) -> Result<xmr::SetupParams> {
+        Xmr::setup(params, rng)
+    }
+
+    fn verify_setup(params: &xmr::SetupParams, export: &btc::ExportedSetupParams) -> Result<xmr::Setup> {
+        Xmr::verify_setup(params, export)
+    }
+}
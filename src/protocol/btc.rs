@@ -16,13 +16,18 @@
 //! Implements the protocol and phases for the Bitcoin side
 
 use crate::types::{Result, Error};
-use crate::types::{btc, xmr, common};
-use crate::types::btc::{CreateTransactions, InitialTransactions, LockFunds, VerifyXmrLock, ReleaseXmr, SpendRefund};
-use crate::types::btc::scripts::{create_swaplock, create_refund};
+use crate::types::{btc, xmr, common, dleq};
+use crate::types::hashlock::HashLock;
+use crate::types::btc::fee;
+use crate::types::btc::{CreateTransactions, InitialTransactions, LockFunds, ExportFunding, LockFundsPsbt, VerifyXmrLock,
+    ReleaseXmr, SpendRefund};
+use crate::types::btc::scripts::{create_swaplock, create_refund, extract_swaplock_buy_preimages};
 use crate::transactions as tx;
 use crate::transactions::Transaction;
 use crate::transactions::btc::funding::FundingTx;
 use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::xmr::{LockTx, SweepTx, Wallet};
+use crate::backend::xmr::MoneroBackend;
 use crate::transactions::{Builder, Validator};
 use super::{Protocol, Phase};
 
@@ -37,19 +42,25 @@ impl Phase<CreateTransactions> for Btc {
 
     #[allow(non_snake_case)]
     fn execute(setup: &btc::Setup, params: &CreateTransactions) -> Result<InitialTransactions> {
-        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0, &setup.h_2, setup.t_0.as_u32());
-        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1, setup.t_0.as_u32());
+        setup.networks.btc.check(&params.change_address)?;
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
+        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1.hashes(), setup.t_1.as_u32());
 
         let mut btx_1 = FundingTx::new();
         btx_1.build(tx::btc::funding::New {
-            utxo: &params.utxo,
+            utxos: &params.utxos,
+            amount: setup.amounts.btc,
+            change_address: &params.change_address,
             swaplock_script: &swaplock_script,
+            feerate: params.feerate,
         })?;
 
         let mut btx_2 = RefundTx::new(&btx_1);
         btx_2.build(tx::btc::refund::New {
             refund_script: &refund_script,
             t_0: setup.t_0,
+            witness_size: fee::swaplock_multisig_witness(&swaplock_script),
+            feerate: params.feerate,
         })?;
 
         let sig_b = btx_2.build(tx::btc::refund::Sign {
@@ -58,8 +69,8 @@ impl Phase<CreateTransactions> for Btc {
         })?;
 
         Ok(InitialTransactions {
-            btx_1: btx_1.to_hex()?,
-            btx_2: btx_2.to_hex()?,
+            btx_1: btx_1.to_hex().ok_or(Error::MissingTransaction)?,
+            btx_2: btx_2.to_hex().ok_or(Error::MissingTransaction)?,
             sig_b,
         })
     }
@@ -70,26 +81,67 @@ impl<'a> Phase<LockFunds<'a>> for Btc {
 
     #[allow(non_snake_case)]
     fn execute(setup: &btc::Setup, params: &LockFunds) -> Result<String> {
-        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0, &setup.h_2, setup.t_0.as_u32());
-        let pubkey = btc::PublicKey::from_secret_key(&params.input.privkey);
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
 
         let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
-        let btx_2 = RefundTx::from_hex(params.btx_2_signed.clone(), &btx_1);
+        let btx_2 = RefundTx::from_hex(params.btx_2.clone(), &btx_1);
+        let btx_2_signed = RefundTx::from_hex(params.btx_2_signed.clone(), &btx_1);
 
-        btx_2.validate(tx::btc::refund::VerifySigA {
+        btx_2_signed.validate(tx::btc::refund::VerifySigned {
+            btx_2: &btx_2,
+            pubkey: &setup.B_b,
+            swaplock_script: &swaplock_script,
+        })?;
+        btx_2_signed.validate(tx::btc::refund::VerifySigA {
             pubkey: &setup.B_a,
             swaplock_script: &swaplock_script,
         })?;
 
-        let sig = btx_1.build(tx::btc::funding::Sign {
-            pubkey: &pubkey,
-            input: &params.input,
+        let sigs = btx_1.build(tx::btc::funding::Sign {
+            inputs: &params.inputs,
         })?;
 
-        Ok(btx_1.build(tx::btc::funding::Finalize {
-            sig,
-            pubkey,
-        })?)
+        Ok(btx_1.build(tx::btc::funding::Finalize { sigs })?)
+    }
+}
+
+impl Phase<ExportFunding> for Btc {
+    type Ret = String;
+
+    fn execute(setup: &btc::Setup, params: &ExportFunding) -> Result<String> {
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
+
+        let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
+        btx_1.build(tx::btc::funding::ExportPsbt {
+            inputs: &params.inputs,
+            swaplock_script: &swaplock_script,
+        })
+    }
+}
+
+impl Phase<LockFundsPsbt> for Btc {
+    type Ret = String;
+
+    fn execute(setup: &btc::Setup, params: &LockFundsPsbt) -> Result<String> {
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
+
+        let mut btx_1 = FundingTx::from_hex(params.btx_1.clone());
+        let btx_2 = RefundTx::from_hex(params.btx_2.clone(), &btx_1);
+        let btx_2_signed = RefundTx::from_hex(params.btx_2_signed.clone(), &btx_1);
+
+        btx_2_signed.validate(tx::btc::refund::VerifySigned {
+            btx_2: &btx_2,
+            pubkey: &setup.B_b,
+            swaplock_script: &swaplock_script,
+        })?;
+        btx_2_signed.validate(tx::btc::refund::VerifySigA {
+            pubkey: &setup.B_a,
+            swaplock_script: &swaplock_script,
+        })?;
+
+        btx_1.build(tx::btc::funding::FinalizePsbt {
+            psbt: &params.psbt,
+        })
     }
 }
 
@@ -97,12 +149,15 @@ impl<'a> Phase<SpendRefund<'a>> for Btc {
     type Ret = String;
 
     fn execute(setup: &btc::Setup, params: &SpendRefund) -> Result<String> {
-        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1, setup.t_1.as_u32());
+        setup.networks.btc.check(&params.address)?;
+        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1.hashes(), setup.t_1.as_u32());
 
         let mut spend_refund = tx::btc::spend_refund::SpendRefundTx::new();
         spend_refund.build(tx::btc::common::New {
             prev_tx: params.btx_2_signed,
             final_address: params.address.clone(),
+            witness_size: fee::refund_spend_witness(&refund_script),
+            feerate: params.feerate,
         })?;
 
         let sig = spend_refund.build(tx::btc::common::Sign {
@@ -111,9 +166,10 @@ impl<'a> Phase<SpendRefund<'a>> for Btc {
             prev_tx: params.btx_2_signed,
         })?;
 
+        let preimages = setup.h_1.preimages(&setup.x_1)?;
         let spend_refund_hex = spend_refund.build(tx::btc::common::Finalize {
             sig,
-            privkey: Some(&setup.x_1),
+            preimages: Some(&preimages),
             script: refund_script,
             secret: None,
         })?;
@@ -122,23 +178,68 @@ impl<'a> Phase<SpendRefund<'a>> for Btc {
     }
 }
 
-impl Phase<VerifyXmrLock> for Btc {
+impl<'a, B> Phase<VerifyXmrLock<'a, B>> for Btc where B: MoneroBackend {
     type Ret = [u8; 32];
 
-    fn execute(_: &btc::Setup, _: &VerifyXmrLock) -> Result<[u8; 32]> {
-        // TODO: Verify Xtx w/ A, X
-        // TODO: Send s
-        Ok([0u8; 32])
+    fn execute(setup: &btc::Setup, params: &VerifyXmrLock<B>) -> Result<[u8; 32]> {
+        // Validate the transaction as seen by the chain, not the copy sent by the seller
+        let chain_tx = params.backend.get_transaction(&params.xtx.tx_hash)?
+            .ok_or(Error::NotEnoughConfirmations)?;
+        let xtx = LockTx::from_hex(chain_tx.tx_hex);
+
+        // Rejects outputs locked by a non-zero unlock time before `s` is released
+        xtx.validate(tx::xmr::VerifyLock {
+            view: &setup.a,
+            spend: &setup.X,
+            amount: setup.amounts.xmr,
+        })?;
+
+        let confirmations = params.backend.confirmations(&params.xtx.tx_hash)?.unwrap_or(0);
+        match confirmations >= params.min_confirmations {
+            true => Ok(setup.s),
+            false => Err(Error::NotEnoughConfirmations),
+        }
     }
 }
 
-impl Phase<ReleaseXmr> for Btc {
-    type Ret = ();
+impl<'a, W, B> Phase<ReleaseXmr<'a, W, B>> for Btc where W: Wallet, B: MoneroBackend {
+    type Ret = xmr::SweptXmr;
+
+    fn execute(setup: &btc::Setup, params: &ReleaseXmr<W, B>) -> Result<xmr::SweptXmr> {
+        setup.networks.xmr.check(&params.dest)?;
+        params.dest.check_standard()?;
+        let buy = params.buy.to_transaction()?;
+        let input = buy.input.get(0).ok_or(Error::MissingInput)?;
+        let preimages = extract_swaplock_buy_preimages(&input.witness)
+            .ok_or(Error::InvalidKeyShare)?;
+        let x_0 = setup.h_0.recover(&preimages)?;
+
+        let x = x_0 + setup.x_1;
+        if xmr::keys::public_key(&x) != setup.X {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let mut sweep = SweepTx::new();
+        sweep.build(tx::xmr::NewSweep {
+            spend: &x,
+            view: &setup.a,
+            dest_spend: &params.dest.spend,
+            dest_view: &params.dest.view,
+        })?;
+
+        let signed = sweep.build(tx::xmr::Sign {
+            wallet: params.wallet,
+        })?;
 
-    fn execute(_: &btc::Setup, _: &ReleaseXmr) -> Result<()> {
-        // TODO: Compute x
-        // TODO: Spend Xtx
-        Ok(())
+        sweep.build(tx::xmr::Broadcast {
+            backend: params.backend,
+            signed: &signed,
+        })?;
+
+        Ok(xmr::SweptXmr {
+            x,
+            sweep: signed,
+        })
     }
 }
 
@@ -152,10 +253,14 @@ impl Protocol for Btc {
         let common::Params {
             t_0,
             t_1,
+            networks,
+            amounts,
         } = params;
 
         let a_1 = xmr::PrivateKey::random(rng);
-        let x_1 = xmr::PrivateKey::random(rng);
+        let x_1 = dleq::random_scalar(rng)?;
+        let X_1_secp = dleq::secp256k1_point(&x_1)?;
+        let proof_1 = dleq::Proof::prove(&x_1, rng)?;
 
         let mut bytes = [0u8; 32];
         rng.try_fill(&mut bytes)?;
@@ -165,9 +270,7 @@ impl Protocol for Btc {
         let mut s = [0u8; 32];
         rng.try_fill(&mut s[..])?;
 
-        let mut h_1 = [0u8; 32];
-        let hash = sha256::Hash::hash(&x_1.to_bytes());
-        h_1.copy_from_slice(&hash[..]);
+        let h_1 = HashLock::commit(&x_1, rng)?;
 
         let mut h_2 = [0u8; 32];
         let hash = sha256::Hash::hash(&s);
@@ -181,8 +284,12 @@ impl Protocol for Btc {
             s,
             h_1,
             h_2,
+            X_1_secp,
+            proof_1,
             t_0,
             t_1,
+            networks,
+            amounts,
         })
     }
 
@@ -198,6 +305,9 @@ impl Protocol for Btc {
             h_2,
             t_0,
             t_1,
+            networks,
+            amounts,
+            ..
         } = params;
 
         let xmr::ExportedSetupParams {
@@ -205,9 +315,14 @@ impl Protocol for Btc {
             X_0,
             B_a,
             h_0,
+            X_0_secp,
+            proof_0,
             ..
         } = export;
 
+        proof_0.verify(X_0, X_0_secp)?;
+        h_0.verify(X_0_secp)?;
+
         match t_0 == &export.t_0 {
             false => return Err(Error::MissmatchCommonParameters),
             true => (),
@@ -218,6 +333,19 @@ impl Protocol for Btc {
             true => (),
         };
 
+        t_0.check()?;
+        t_1.check()?;
+
+        match networks == &export.networks {
+            false => return Err(Error::MissmatchCommonParameters),
+            true => (),
+        };
+
+        match amounts == &export.amounts {
+            false => return Err(Error::MissmatchCommonParameters),
+            true => (),
+        };
+
         let a = a_0 + a_1;
         let X_1 = x_1 * &constants::ED25519_BASEPOINT_TABLE;
         let X = X_0 + X_1;
@@ -226,15 +354,18 @@ impl Protocol for Btc {
             a,
             x_1: *x_1,
             X,
+            X_0_secp: X_0_secp.clone(),
             B_a: B_a.clone(),
             b_b: b_b.clone(),
             B_b: B_b.clone(),
             s: *s,
-            h_0: *h_0,
-            h_1: *h_1,
+            h_0: h_0.clone(),
+            h_1: h_1.clone(),
             h_2: *h_2,
             t_0: *t_0,
             t_1: *t_1,
+            networks: *networks,
+            amounts: *amounts,
         })
     }
 }
@@ -49,13 +49,20 @@ pub trait Phase<T>: Protocol {
 
 pub mod btc;
 pub mod xmr;
+pub mod adaptor;
 
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
-    use super::{Protocol, btc::Btc, xmr::Xmr};
+    use super::{Protocol, Phase, btc::Btc, xmr::Xmr};
     use rand::rngs::OsRng;
-    use crate::types::{xmr, btc, common, RelativeLocktime};
+    use crate::types::{xmr, btc, common, dleq, Error, RelativeLocktime};
+    use crate::transactions::xmr::Wallet;
+    use crate::transactions::btc::buy::BuyTx;
+    use crate::transactions::btc::spend_refund::SpendRefundTx;
+    use crate::backend::xmr::{MoneroBackend, FakeMonero};
+    use crate::types::hashlock::HashLock;
+    use crate::types::amount::{Satoshi, Piconero};
     use curve25519_dalek::constants;
 
     fn setup() -> (OsRng, common::Params) {
@@ -63,6 +70,8 @@ mod tests {
         let params = common::Params {
             t_0: RelativeLocktime::Blocks(144),
             t_1: RelativeLocktime::Blocks(144),
+            networks: common::Networks::new(common::BtcNetwork::Testnet, xmr::address::Network::Stagenet),
+            amounts: common::Amounts::new(Satoshi(50_000), Piconero(1_000_000_000_000)),
         };
         (rng, params)
     }
@@ -109,6 +118,47 @@ mod tests {
         assert!(setup.X == X);
     }
 
+    #[test]
+    fn xmr_verify_setup_invalid_proof() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params.clone(), &mut rng).unwrap();
+        let other_params = Btc::setup(params, &mut rng).unwrap();
+        let mut exported: btc::ExportedSetupParams = (&btc_params).into();
+        exported.proof_1 = other_params.proof_1;
+        assert!(Xmr::verify_setup(&xmr_params, &exported).is_err());
+    }
+
+    #[test]
+    fn xmr_verify_setup_mismatched_hash_lock() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params, &mut rng).unwrap();
+        let mut exported: btc::ExportedSetupParams = (&btc_params).into();
+        // Hash locks of another share presented as the locks of x_1
+        let other = dleq::random_scalar(&mut rng).unwrap();
+        exported.h_1 = HashLock::commit(&other, &mut rng).unwrap();
+        exported.h_1.X_secp = exported.X_1_secp.clone();
+        match Xmr::verify_setup(&xmr_params, &exported) {
+            Err(Error::InvalidProof) => (),
+            _ => panic!("hash locks must unlock with x_1"),
+        }
+    }
+
+    #[test]
+    fn btc_verify_setup_mismatched_hash_lock() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params, &mut rng).unwrap();
+        let mut exported: xmr::ExportedSetupParams = (&xmr_params).into();
+        let other = dleq::random_scalar(&mut rng).unwrap();
+        exported.h_0 = HashLock::commit(&other, &mut rng).unwrap();
+        match Btc::verify_setup(&btc_params, &exported) {
+            Err(Error::InvalidProof) => (),
+            _ => panic!("hash locks must unlock with x_0"),
+        }
+    }
+
     #[test]
     fn btc_verify_setup() {
         let (mut rng, params) = setup();
@@ -124,4 +174,95 @@ mod tests {
         let setup = Btc::verify_setup(&btc_params, &exported).unwrap();
         assert!(setup.X == X);
     }
+
+    #[test]
+    fn verify_setup_network_mismatch() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let mut mainnet = params;
+        mainnet.networks.btc = common::BtcNetwork::Mainnet;
+        let btc_params = Btc::setup(mainnet, &mut rng).unwrap();
+        let exported: btc::ExportedSetupParams = (&btc_params).into();
+        assert!(Xmr::verify_setup(&xmr_params, &exported).is_err());
+    }
+
+    #[test]
+    fn verify_setup_amount_mismatch() {
+        let (mut rng, params) = setup();
+        let btc_params = Btc::setup(params.clone(), &mut rng).unwrap();
+        let mut underpaid = params;
+        underpaid.amounts.btc = Satoshi(49_999);
+        let xmr_params = Xmr::setup(underpaid, &mut rng).unwrap();
+        let exported: xmr::ExportedSetupParams = (&xmr_params).into();
+        assert!(Btc::verify_setup(&btc_params, &exported).is_err());
+    }
+
+    #[test]
+    fn btc_verify_xmr_lock_rejects_locked_outputs() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params, &mut rng).unwrap();
+        let exported: xmr::ExportedSetupParams = (&xmr_params).into();
+        let setup = Btc::verify_setup(&btc_params, &exported).unwrap();
+
+        let chain = FakeMonero::new();
+        let mut signed = chain.transfer(&setup.X, &xmr::keys::public_key(&setup.a), setup.amounts.xmr.0).unwrap();
+        // Version is followed by the unlock time varint, lock the outputs until height 100
+        let mut tx = hex::decode(&signed.tx_hex).unwrap();
+        tx[1] = 100;
+        signed.tx_hex = hex::encode(tx);
+        chain.submit(&signed).unwrap();
+        chain.mine(10);
+
+        let lock = btc::VerifyXmrLock {
+            xtx: xmr::LockedXmr { tx_hex: signed.tx_hex.clone(), tx_hash: signed.tx_hash },
+            backend: &chain,
+            min_confirmations: 1,
+        };
+        match Btc::execute(&setup, &lock) {
+            Err(Error::InvalidUnlockTime { unlock_time: 100, .. }) => (),
+            _ => panic!("s must not be released for locked outputs"),
+        }
+    }
+
+    #[test]
+    fn sweep_destination_network_mismatch() {
+        let (mut rng, params) = setup();
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params, &mut rng).unwrap();
+        let btc_setup = Btc::verify_setup(&btc_params, &(&xmr_params).into()).unwrap();
+        let xmr_setup = Xmr::verify_setup(&xmr_params, &(&btc_params).into()).unwrap();
+
+        let chain = FakeMonero::new();
+        let key = xmr::keys::public_key(&xmr::keys::hash_to_scalar(b"dest"));
+        let mainnet = xmr::address::Address::standard(xmr::address::Network::Mainnet, &key, &key);
+        let release = btc::ReleaseXmr { buy: BuyTx::new(), dest: mainnet, wallet: &chain, backend: &chain };
+        match Btc::execute(&btc_setup, &release) {
+            Err(Error::InvalidNetwork) => (),
+            _ => panic!("stagenet swap swept to a mainnet address"),
+        }
+        let recover = xmr::RecoverXmrAfterRefund {
+            spend_refund: SpendRefundTx::new(),
+            dest: mainnet,
+            wallet: &chain,
+            backend: &chain,
+        };
+        match Xmr::execute(&xmr_setup, &recover) {
+            Err(Error::InvalidNetwork) => (),
+            _ => panic!("stagenet swap swept to a mainnet address"),
+        }
+    }
+
+    #[test]
+    fn verify_setup_zero_locktime() {
+        let (mut rng, mut params) = setup();
+        params.t_1 = RelativeLocktime::Blocks(0);
+        let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
+        let btc_params = Btc::setup(params, &mut rng).unwrap();
+        match Btc::verify_setup(&btc_params, &(&xmr_params).into()) {
+            Err(Error::InvalidLocktime(_)) => (),
+            _ => panic!("zero locktime is not a valid BIP68 locktime"),
+        }
+        assert!(Xmr::verify_setup(&xmr_params, &(&btc_params).into()).is_err());
+    }
 }
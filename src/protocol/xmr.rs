@@ -16,17 +16,22 @@
 //! Implements the protocol and phases for the Monero side
 
 use crate::types::{Result, Error};
-use crate::types::{btc, xmr, common};
-use crate::types::xmr::{VerifyTransactions, VerifiedTransaction, InitiateSwap, Swap, ClaimRefund};
-use crate::types::btc::scripts::{create_swaplock, create_refund};
+use crate::types::{btc, xmr, common, dleq};
+use crate::types::hashlock::HashLock;
+use crate::types::btc::fee;
+use crate::types::xmr::{VerifyTransactions, VerifiedTransaction, InitiateSwap, LockedXmr, Swap, ClaimRefund,
+    RecoverXmrAfterRefund, SweptXmr};
+use crate::types::btc::scripts::{create_swaplock, create_refund, extract_refund_preimages};
 use crate::transactions as tx;
 use crate::transactions::btc::funding::FundingTx;
 use crate::transactions::btc::refund::RefundTx;
+use crate::transactions::Transaction;
+use crate::transactions::xmr::{LockTx, SweepTx, Wallet};
+use crate::backend::xmr::MoneroBackend;
 use crate::transactions::{Builder, Validator};
 use super::{Protocol, Phase};
 
 use rand::{Rng, CryptoRng};
-use bitcoin_hashes::{Hash, sha256};
 use curve25519_dalek::constants;
 
 pub struct Xmr;
@@ -36,14 +41,23 @@ impl Phase<VerifyTransactions> for Xmr {
 
     #[allow(non_snake_case)]
     fn execute(setup: &xmr::Setup, params: &VerifyTransactions) -> Result<VerifiedTransaction> {
-        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0, &setup.h_2, setup.t_0.as_u32());
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
+        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1.hashes(), setup.t_1.as_u32());
 
         let btx_1 = FundingTx::from_hex(params.transactions.btx_1.clone());
         let mut btx_2 = RefundTx::from_hex(params.transactions.btx_2.clone(), &btx_1);
 
-        // TODO: Verify Btx1
-        // TODO: verify all utxos are SegWit programs
-        // TODO: Verify Btx2
+        btx_1.validate(tx::btc::funding::VerifyFunding {
+            swaplock_script: &swaplock_script,
+            amount: setup.amounts.btc,
+            spent_outputs: &params.spent_outputs,
+        })?;
+
+        btx_2.validate(tx::btc::refund::VerifyStructure {
+            refund_script: &refund_script,
+            t_0: setup.t_0,
+            witness_size: fee::swaplock_multisig_witness(&swaplock_script),
+        })?;
 
         btx_2.validate(tx::btc::refund::VerifySigB {
             //secp: &secp,
@@ -70,14 +84,33 @@ impl Phase<VerifyTransactions> for Xmr {
     }
 }
 
-impl Phase<InitiateSwap> for Xmr {
-    type Ret = ();
+impl<'a, W, B> Phase<InitiateSwap<'a, W, B>> for Xmr where W: Wallet, B: MoneroBackend {
+    type Ret = LockedXmr;
+
+    #[allow(non_snake_case)]
+    fn execute(setup: &xmr::Setup, params: &InitiateSwap<W, B>) -> Result<LockedXmr> {
+        let A = &setup.a * &constants::ED25519_BASEPOINT_TABLE;
+
+        let mut xtx = LockTx::new();
+        xtx.build(tx::xmr::New {
+            spend: &setup.X,
+            view: &A,
+            amount: setup.amounts.xmr,
+        })?;
+
+        let signed = xtx.build(tx::xmr::Sign {
+            wallet: params.wallet,
+        })?;
+
+        xtx.build(tx::xmr::Broadcast {
+            backend: params.backend,
+            signed: &signed,
+        })?;
 
-    fn execute(_: &xmr::Setup, _: &InitiateSwap) -> Result<()> {
-        // TODO: Create Xtx
-        // TODO: Sign Xtx
-        // TODO: Broadcast Xtx
-        Ok(())
+        Ok(LockedXmr {
+            tx_hex: signed.tx_hex,
+            tx_hash: signed.tx_hash,
+        })
     }
 }
 
@@ -85,12 +118,15 @@ impl Phase<Swap> for Xmr {
     type Ret = String;
 
     fn execute(setup: &xmr::Setup, params: &Swap) -> Result<String> {
-        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0, &setup.h_2, setup.t_0.as_u32());
+        setup.networks.btc.check(&params.address)?;
+        let swaplock_script = create_swaplock(&setup.B_a, &setup.B_b, &setup.h_0.hashes(), &setup.h_2, setup.t_0.as_u32());
 
         let mut buy = tx::btc::buy::BuyTx::new();
         buy.build(tx::btc::common::New {
             prev_tx: &params.funding,
             final_address: params.address.clone(),
+            witness_size: fee::swaplock_buy_witness(&swaplock_script),
+            feerate: params.feerate,
         })?;
 
         let sig = buy.build(tx::btc::common::Sign {
@@ -99,10 +135,11 @@ impl Phase<Swap> for Xmr {
             prev_tx: &params.funding,
         })?;
 
+        let preimages = setup.h_0.preimages(&setup.x_0)?;
         let buy_hex = buy.build(tx::btc::common::Finalize {
             sig,
             script: swaplock_script,
-            privkey: Some(&setup.x_0),
+            preimages: Some(&preimages),
             secret: Some(params.s),
         })?;
 
@@ -114,13 +151,16 @@ impl<'a> Phase<ClaimRefund<'a>> for Xmr {
     type Ret = String;
 
     fn execute(setup: &xmr::Setup, params: &ClaimRefund) -> Result<String> {
-        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1, setup.t_1.as_u32());
+        setup.networks.btc.check(&params.address)?;
+        let refund_script = create_refund(&setup.B_a, &setup.B_b, &setup.h_1.hashes(), setup.t_1.as_u32());
 
         let mut claim_refund = tx::btc::claim_refund::ClaimRefundTx::new();
         claim_refund.build(tx::btc::claim_refund::New {
             refund_tx: params.btx_2_signed,
             t_1: setup.t_1,
             final_address: params.address.clone(),
+            witness_size: fee::refund_claim_witness(&refund_script),
+            feerate: params.feerate,
         })?;
 
         let sig = claim_refund.build(tx::btc::common::Sign {
@@ -131,7 +171,7 @@ impl<'a> Phase<ClaimRefund<'a>> for Xmr {
 
         let claim_refund_hex = claim_refund.build(tx::btc::common::Finalize {
             sig,
-            privkey: None,
+            preimages: None,
             script: refund_script,
             secret: None,
         })?;
@@ -140,6 +180,52 @@ impl<'a> Phase<ClaimRefund<'a>> for Xmr {
     }
 }
 
+impl<'a, W, B> Phase<RecoverXmrAfterRefund<'a, W, B>> for Xmr where W: Wallet, B: MoneroBackend {
+    type Ret = SweptXmr;
+
+    #[allow(non_snake_case)]
+    fn execute(setup: &xmr::Setup, params: &RecoverXmrAfterRefund<W, B>) -> Result<SweptXmr> {
+        setup.networks.xmr.check(&params.dest)?;
+        params.dest.check_standard()?;
+        let spend_refund = params.spend_refund.to_transaction()?;
+        let input = spend_refund.input.get(0).ok_or(Error::MissingInput)?;
+        let preimages = extract_refund_preimages(&input.witness)
+            .ok_or(Error::InvalidKeyShare)?;
+        let x_1 = setup.h_1.recover(&preimages)?;
+
+        if xmr::keys::public_key(&x_1) != setup.X_1 {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let x = setup.x_0 + x_1;
+        if xmr::keys::public_key(&x) != setup.X {
+            return Err(Error::InvalidKeyShare);
+        }
+
+        let mut sweep = SweepTx::new();
+        sweep.build(tx::xmr::NewSweep {
+            spend: &x,
+            view: &setup.a,
+            dest_spend: &params.dest.spend,
+            dest_view: &params.dest.view,
+        })?;
+
+        let signed = sweep.build(tx::xmr::Sign {
+            wallet: params.wallet,
+        })?;
+
+        sweep.build(tx::xmr::Broadcast {
+            backend: params.backend,
+            signed: &signed,
+        })?;
+
+        Ok(SweptXmr {
+            x,
+            sweep: signed,
+        })
+    }
+}
+
 impl Protocol for Xmr {
     type Output = xmr::SetupParams;
     type Input = btc::ExportedSetupParams;
@@ -150,19 +236,21 @@ impl Protocol for Xmr {
         let common::Params {
             t_0,
             t_1,
+            networks,
+            amounts,
         } = params;
 
         let a_0 = xmr::PrivateKey::random(rng);
-        let x_0 = xmr::PrivateKey::random(rng);
+        let x_0 = dleq::random_scalar(rng)?;
+        let X_0_secp = dleq::secp256k1_point(&x_0)?;
+        let proof_0 = dleq::Proof::prove(&x_0, rng)?;
 
         let mut bytes = [0u8; 32];
         rng.try_fill(&mut bytes)?;
         let b_a = btc::PrivateKey::parse(&bytes)?;
         let B_a = btc::PublicKey::from_secret_key(&b_a);
 
-        let mut h_0 = [0u8; 32];
-        let hash = sha256::Hash::hash(&x_0.to_bytes());
-        h_0.copy_from_slice(&hash[..]);
+        let h_0 = HashLock::commit(&x_0, rng)?;
 
         Ok(xmr::SetupParams {
             a_0,
@@ -170,8 +258,12 @@ impl Protocol for Xmr {
             b_a,
             B_a,
             h_0,
+            X_0_secp,
+            proof_0,
             t_0,
             t_1,
+            networks,
+            amounts,
         })
     }
 
@@ -185,6 +277,9 @@ impl Protocol for Xmr {
             h_0,
             t_0,
             t_1,
+            networks,
+            amounts,
+            ..
         } = params;
 
         let btc::ExportedSetupParams {
@@ -193,9 +288,14 @@ impl Protocol for Xmr {
             B_b,
             h_1,
             h_2,
+            X_1_secp,
+            proof_1,
             ..
         } = export;
 
+        proof_1.verify(X_1, X_1_secp)?;
+        h_1.verify(X_1_secp)?;
+
         match t_0 == &export.t_0 {
             false => return Err(Error::MissmatchCommonParameters),
             true => (),
@@ -206,6 +306,19 @@ impl Protocol for Xmr {
             true => (),
         };
 
+        t_0.check()?;
+        t_1.check()?;
+
+        match networks == &export.networks {
+            false => return Err(Error::MissmatchCommonParameters),
+            true => (),
+        };
+
+        match amounts == &export.amounts {
+            false => return Err(Error::MissmatchCommonParameters),
+            true => (),
+        };
+
         let a = a_0 + a_1;
         let X_0 = x_0 * &constants::ED25519_BASEPOINT_TABLE;
         let X = X_0 + X_1;
@@ -214,14 +327,18 @@ impl Protocol for Xmr {
             a,
             x_0: *x_0,
             X,
+            X_1: *X_1,
+            X_1_secp: X_1_secp.clone(),
             b_a: b_a.clone(),
             B_a: B_a.clone(),
             B_b: B_b.clone(),
-            h_0: *h_0,
-            h_1: *h_1,
+            h_0: h_0.clone(),
+            h_1: h_1.clone(),
             h_2: *h_2,
             t_0: *t_0,
             t_1: *t_1,
+            networks: *networks,
+            amounts: *amounts,
         })
     }
 }
@@ -0,0 +1,95 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Passphrase encryption of the stored records. The key is derived with Argon2id and
+//! a random salt, records are encrypted and authenticated with ChaCha20-Poly1305, the
+//! header is bound as associated data.
+
+use crate::types::{Result, Error};
+
+use rand::Rng;
+use rand::rngs::OsRng;
+use argon2::Argon2;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+
+const MAGIC: &[u8] = b"MSWP\x02";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+/// Derive the record key with the default Argon2id parameters
+fn key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|_| Error::DecryptionFailed)?;
+    Ok(key)
+}
+
+/// Encrypt data under a passphrase
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut rng = OsRng::new()?;
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.try_fill(&mut salt)?;
+    rng.try_fill(&mut nonce)?;
+    let cipher = ChaCha20Poly1305::new(&key(passphrase, &salt)?);
+
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &data })
+        .map_err(|_| Error::EncryptionFailed)?;
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+/// Decrypt data, fails if the passphrase is wrong or the data was modified
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LEN + TAG_LEN || &data[..MAGIC.len()] != MAGIC {
+        return Err(Error::DecryptionFailed);
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = &header[MAGIC.len() + SALT_LEN..];
+    let cipher = ChaCha20Poly1305::new(&key(passphrase, salt)?);
+    cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encrypt, decrypt, MAGIC};
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let data = encrypt("passphrase", b"swap snapshot").unwrap();
+        assert_eq!(b"swap snapshot".to_vec(), decrypt("passphrase", &data).unwrap());
+        assert!(decrypt("wrong", &data).is_err());
+
+        let mut tampered = data.clone();
+        let last = tampered.len() - 20;
+        tampered[last] ^= 1;
+        assert!(decrypt("passphrase", &tampered).is_err());
+
+        // The header is authenticated
+        let mut tampered = data.clone();
+        tampered[MAGIC.len()] ^= 1;
+        assert!(decrypt("passphrase", &tampered).is_err());
+    }
+}
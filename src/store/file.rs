@@ -0,0 +1,177 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! File store, one file per swap in a directory. Records are written to a temporary
+//! file, synced and renamed over the previous record so a crash never leaves a partial
+//! record. On unix the records are only readable by their owner.
+
+use crate::types::Result;
+use super::{SwapStore, SwapRecord, check_id, encryption};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+const EXTENSION: &str = "swap";
+
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl FileStore {
+    /// Open a store in `dir`, created if needed, records are encrypted when a passphrase
+    /// is given
+    pub fn new<P: Into<PathBuf>>(dir: P, passphrase: Option<&str>) -> Result<FileStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileStore {
+            dir,
+            passphrase: passphrase.map(String::from),
+        })
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, EXTENSION))
+    }
+
+    fn read(&self, id: &str) -> Result<Option<SwapRecord>> {
+        let data = match fs::read(self.path(id)) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let data = match &self.passphrase {
+            Some(passphrase) => encryption::decrypt(passphrase, &data)?,
+            None => data,
+        };
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+}
+
+/// Create or truncate a record file, with mode 0600 on unix
+fn create(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let file = options.open(path)?;
+    // The mode only applies to new files, restrict a leftover temporary file too
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+impl SwapStore for FileStore {
+    fn put(&mut self, id: &str, snapshot: &str) -> Result<()> {
+        check_id(id)?;
+        let record = SwapRecord::update(self.read(id)?, snapshot);
+        let data = serde_json::to_vec(&record)?;
+        let data = match &self.passphrase {
+            Some(passphrase) => encryption::encrypt(passphrase, &data)?,
+            None => data,
+        };
+
+        let tmp = self.dir.join(format!("{}.{}.tmp", id, EXTENSION));
+        {
+            let mut file = create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, self.path(id))?;
+        // Persist the rename
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<SwapRecord>> {
+        check_id(id)?;
+        self.read(id)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let mut ids = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(EXTENSION) {
+                if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        check_id(id)?;
+        match fs::remove_file(self.path(id)) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::super::SwapStore;
+    use super::FileStore;
+
+    #[test]
+    fn encrypted_store() {
+        let dir = env::temp_dir().join(format!("monero-swap-lib-store-{}", std::process::id()));
+        let mut store = FileStore::new(&dir, Some("passphrase")).unwrap();
+        store.put("swap-1", "{}").unwrap();
+        let created_at = store.get("swap-1").unwrap().unwrap().created_at;
+        store.put("swap-1", "{\"state\":1}").unwrap();
+
+        let record = store.get("swap-1").unwrap().unwrap();
+        assert_eq!("{\"state\":1}", record.snapshot);
+        assert_eq!(created_at, record.created_at);
+        assert_eq!(vec!["swap-1".to_string()], store.list().unwrap());
+
+        // Stored data is not readable without the passphrase
+        assert!(!String::from_utf8_lossy(&fs::read(dir.join("swap-1.swap")).unwrap()).contains("state"));
+        assert!(FileStore::new(&dir, Some("wrong")).unwrap().get("swap-1").is_err());
+        assert!(FileStore::new(&dir, None).unwrap().get("swap-1").is_err());
+
+        store.delete("swap-1").unwrap();
+        assert!(store.get("swap-1").unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn owner_only_records() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("monero-swap-lib-mode-{}", std::process::id()));
+        let mut store = FileStore::new(&dir, None).unwrap();
+        store.put("swap-1", "{}").unwrap();
+        let mode = fs::metadata(dir.join("swap-1.swap")).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
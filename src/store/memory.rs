@@ -0,0 +1,81 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! In-memory store for tests
+
+use crate::types::Result;
+use super::{SwapStore, SwapRecord, check_id};
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    records: BTreeMap<String, SwapRecord>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl SwapStore for MemoryStore {
+    fn put(&mut self, id: &str, snapshot: &str) -> Result<()> {
+        check_id(id)?;
+        let record = SwapRecord::update(self.records.remove(id), snapshot);
+        self.records.insert(id.to_string(), record);
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<SwapRecord>> {
+        check_id(id)?;
+        Ok(self.records.get(id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.records.keys().cloned().collect())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        check_id(id)?;
+        self.records.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::state::{SwapState, SwapTransactions, BuyerSnapshot};
+    use super::super::SwapStore;
+    use super::MemoryStore;
+
+    #[test]
+    fn save_and_load() {
+        let mut store = MemoryStore::new();
+        let mut transactions = SwapTransactions::default();
+        transactions.btx_1 = Some("00".into());
+        store.save("swap-1", &BuyerSnapshot::new(SwapState::Initialized, None, None, transactions)).unwrap();
+
+        let snapshot: BuyerSnapshot = store.load("swap-1").unwrap().unwrap();
+        assert_eq!(SwapState::Initialized, snapshot.state);
+        assert_eq!(Some("00".into()), snapshot.transactions.btx_1);
+        assert_eq!(vec!["swap-1".to_string()], store.list().unwrap());
+
+        assert!(store.get("../swap-1").is_err());
+        store.delete("swap-1").unwrap();
+        assert!(store.get("swap-1").unwrap().is_none());
+    }
+}
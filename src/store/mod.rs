@@ -0,0 +1,96 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Persistent storage of swaps in progress. A swap is saved as a JSON snapshot of its
+//! state, setup and transactions with creation and update timestamps, every update
+//! replaces the previous record atomically.
+
+use crate::types::{Result, Error};
+use crate::types::state::Snapshot;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod encryption;
+pub mod file;
+pub mod memory;
+
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+
+/// Stored swap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwapRecord {
+    /// JSON snapshot of the swap
+    pub snapshot: String,
+    /// Seconds since the UNIX epoch
+    pub created_at: u64,
+    /// Seconds since the UNIX epoch
+    pub updated_at: u64,
+}
+
+impl SwapRecord {
+    /// Create a record or update the previous one, keeping its creation time
+    pub fn update(previous: Option<SwapRecord>, snapshot: &str) -> SwapRecord {
+        let now = now();
+        SwapRecord {
+            snapshot: snapshot.to_string(),
+            created_at: previous.map_or(now, |record| record.created_at),
+            updated_at: now,
+        }
+    }
+}
+
+/// Storage backend of the swaps, indexed by a swap identifier
+pub trait SwapStore {
+    /// Save the snapshot of a swap, replacing the previous one atomically
+    fn put(&mut self, id: &str, snapshot: &str) -> Result<()>;
+
+    /// Get the stored swap
+    fn get(&self, id: &str) -> Result<Option<SwapRecord>>;
+
+    /// Identifiers of all the stored swaps
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// Remove a swap, e.g. once it reached a final state
+    fn delete(&mut self, id: &str) -> Result<()>;
+
+    fn save<P, S>(&mut self, id: &str, snapshot: &Snapshot<P, S>) -> Result<()>
+        where Self: Sized, P: serde::Serialize, S: serde::Serialize {
+        self.put(id, &snapshot.to_json()?)
+    }
+
+    fn load<P, S>(&self, id: &str) -> Result<Option<Snapshot<P, S>>>
+        where Self: Sized, P: serde::de::DeserializeOwned, S: serde::de::DeserializeOwned {
+        match self.get(id)? {
+            Some(record) => Ok(Some(Snapshot::from_json(&record.snapshot)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Identifiers are used as file names, only alphanumeric characters, `-` and `_` are
+/// allowed
+pub(crate) fn check_id(id: &str) -> Result<()> {
+    match !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        true => Ok(()),
+        false => Err(Error::InvalidSwapId),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
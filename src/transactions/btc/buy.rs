@@ -13,12 +13,14 @@
 // copies or substantial portions of the Software.
 //
 
-use crate::types::{Result, Error};
-use crate::types::constants::FEE_KB_SATOSHI;
-use crate::types::btc::scripts::redeem_swaplock_buy;
-use crate::transactions::{Builder, Transaction};
+use crate::types::{Result, Error, TxKind};
+use crate::types::btc::fee;
+use crate::types::btc::adaptor::{self, EncryptedSignature};
+use crate::types::btc::scripts::{redeem_swaplock_buy, redeem_adaptor_multisig};
+use crate::transactions::{Builder, Validator, Transaction};
 use crate::transactions::btc::funding::Funding;
-use crate::transactions::btc::common::{New, Sign, Finalize};
+use crate::transactions::btc::common::{New, Sign, Finalize, EncryptedSign, VerifyEncryptedSig, FinalizeMultisig,
+    sighash_all, output_amount};
 
 use secp256k1::Signature;
 use bitcoin::consensus::encode::serialize_hex;
@@ -50,7 +52,10 @@ impl<'a, T> Builder<New<'a, T>> for BuyTx where T: Funding {
 
     fn build(&mut self, params: New<T>) -> Result<()> {
         let funding = params.prev_tx.to_transaction()?;
-        let out_amount = funding.output[0].value - FEE_KB_SATOSHI / 2;
+        let script_pubkey = params.final_address.script_pubkey();
+        let weight = fee::weight(&[params.witness_size], &[script_pubkey.len()]);
+        let fee = fee::fee(weight, params.feerate)?;
+        let out_amount = output_amount(TxKind::Buy, funding.output[0].value, fee)?;
         let buy_tx = bitcoin::Transaction {
             version: 2,
             lock_time: 0,
@@ -65,7 +70,7 @@ impl<'a, T> Builder<New<'a, T>> for BuyTx where T: Funding {
             }],
             output: vec![bitcoin::TxOut {
                 value: out_amount,
-                script_pubkey: params.final_address.script_pubkey(),
+                script_pubkey,
             }],
         };
 
@@ -103,11 +108,44 @@ impl<'a> Builder<Finalize<'a>> for BuyTx {
         buy.input[0].witness = redeem_swaplock_buy(
             params.script,
             params.sig,
-            params.privkey.ok_or(Error::MissingValue)?,
-            &params.secret.ok_or(Error::MissingValue)?,
+            params.preimages.ok_or(Error::MissingValue("preimages"))?,
+            &params.secret.ok_or(Error::MissingValue("secret"))?,
         );
         let buy = serialize_hex(&buy);
         self.tx_hex = Some(buy.clone());
         Ok(buy)
     }
 }
+
+impl<'a, F> Builder<EncryptedSign<'a, F>> for BuyTx where F: Funding {
+    type Ret = EncryptedSignature;
+
+    fn build(&mut self, params: EncryptedSign<F>) -> Result<EncryptedSignature> {
+        let msg = sighash_all(&self.to_transaction()?, params.script, params.prev_tx)?;
+        adaptor::encrypted_sign(&msg, params.privkey, params.adaptor)
+    }
+}
+
+impl<'a, F> Validator<VerifyEncryptedSig<'a, F>> for BuyTx where F: Funding {
+    fn validate(&self, params: VerifyEncryptedSig<F>) -> Result<()> {
+        let msg = sighash_all(&self.to_transaction()?, params.script, params.prev_tx)?;
+        adaptor::encrypted_verify(&msg, params.pubkey, params.adaptor, params.sig)
+            .map_err(|e| e.on_signature(TxKind::Buy, 0, "encrypted sig_b"))
+    }
+}
+
+impl Builder<FinalizeMultisig> for BuyTx {
+    type Ret = String;
+
+    fn build(&mut self, params: FinalizeMultisig) -> Result<String> {
+        let mut tx = self.to_transaction()?;
+        tx.input[0].witness = redeem_adaptor_multisig(
+            params.script,
+            params.sig_a,
+            params.sig_b,
+        );
+        let tx = serialize_hex(&tx);
+        self.tx_hex = Some(tx.clone());
+        Ok(tx)
+    }
+}
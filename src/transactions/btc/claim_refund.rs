@@ -13,13 +13,13 @@
 // copies or substantial portions of the Software.
 //
 
-use crate::types::Result;
+use crate::types::{Result, TxKind};
 use crate::transactions::{Builder, Transaction};
 use crate::transactions::btc::refund::RefundTx;
-use crate::transactions::btc::common::{Sign, Finalize};
+use crate::transactions::btc::common::{Sign, Finalize, output_amount};
 use crate::transactions::btc::refund::Refund;
 use crate::types::btc::scripts::redeem_refund;
-use crate::types::constants::FEE_KB_SATOSHI;
+use crate::types::btc::fee;
 use crate::types::{RelativeLocktime};
 
 use secp256k1::Signature;
@@ -52,6 +52,10 @@ pub struct New<'a> {
     pub(crate) refund_tx: &'a RefundTx<'a>,
     pub(crate) t_1: RelativeLocktime,
     pub(crate) final_address: Address,
+    /// Expected size of the witness spending the refund output
+    pub(crate) witness_size: usize,
+    /// Feerate in satoshi per virtual byte
+    pub(crate) feerate: u64,
 }
 
 impl<'a> Builder<New<'a>> for ClaimRefundTx {
@@ -59,7 +63,10 @@ impl<'a> Builder<New<'a>> for ClaimRefundTx {
 
     fn build(&mut self, params: New) -> Result<()> {
         let refund = params.refund_tx.to_transaction()?;
-        let out_amount = refund.output[0].value - FEE_KB_SATOSHI / 2;
+        let script_pubkey = params.final_address.script_pubkey();
+        let weight = fee::weight(&[params.witness_size], &[script_pubkey.len()]);
+        let fee = fee::fee(weight, params.feerate)?;
+        let out_amount = output_amount(TxKind::ClaimRefund, refund.output[0].value, fee)?;
         let claim_refund = bitcoin::Transaction {
             version: 2,
             lock_time: 0,
@@ -74,7 +81,7 @@ impl<'a> Builder<New<'a>> for ClaimRefundTx {
             }],
             output: vec![bitcoin::TxOut {
                 value: out_amount,
-                script_pubkey: params.final_address.script_pubkey(),
+                script_pubkey,
             }],
         };
 
@@ -88,6 +95,8 @@ impl<'a, R> Builder<Sign<'a, R>> for ClaimRefundTx where R: Refund {
 
     fn build(&mut self, params: Sign<R>) -> Result<Signature> {
         let spend_refund = self.to_transaction()?;
+        // Never sign a claim that could not spend the timelocked branch of the refund
+        RelativeLocktime::check_spend(TxKind::ClaimRefund, 0, params.script, spend_refund.input[0].sequence)?;
 
         let bip143_comp = SighashComponents::new(&spend_refund);
         // Generate Segwit sighash for SIG_ALL
@@ -15,12 +15,15 @@
 
 //! Common structures between some transactions
 
-use crate::types::{btc, xmr};
+use crate::types::{btc, Error, Result, TxKind};
+use crate::types::amount::Satoshi;
+use crate::types::btc::adaptor::EncryptedSignature;
 use crate::transactions::Transaction;
 
-use secp256k1::Signature;
+use secp256k1::{Message, Signature};
 use bitcoin::blockdata::script::Script;
 use bitcoin::util::address::Address;
+use bitcoin::util::bip143::SighashComponents;
 
 /// Generate a new transaction base on previous outputs and one output to an address
 pub struct New<'a, T: Transaction> {
@@ -28,6 +31,10 @@ pub struct New<'a, T: Transaction> {
     pub(crate) prev_tx: &'a T,
     /// Final destination for building output
     pub(crate) final_address: Address,
+    /// Expected size of the witness spending the previous output
+    pub(crate) witness_size: usize,
+    /// Feerate in satoshi per virtual byte
+    pub(crate) feerate: u64,
 }
 
 /// Signing step for a transaction
@@ -40,11 +47,68 @@ pub struct Sign<'a, T: Transaction> {
     pub(crate) prev_tx: &'a T,
 }
 
-/// Finalizing step for a transaction with a signature, a script, a Monero private key
-/// revealed in the script, and a secret value for hash locks
+/// Finalizing step for a transaction with a signature, a script, the preimages of the
+/// Monero key share hash locks revealed in the script, and a secret value for hash locks
 pub struct Finalize<'a> {
     pub(crate) sig: Signature,
     pub(crate) script: Script,
-    pub(crate) privkey: Option<&'a xmr::PrivateKey>,
+    pub(crate) preimages: Option<&'a [[u8; 32]]>,
     pub(crate) secret: Option<[u8; 32]>,
 }
+
+/// Encrypted signing step for a transaction, the signature is encrypted under an
+/// adaptor point and can only be completed with its discrete logarithm
+pub struct EncryptedSign<'a, T: Transaction> {
+    /// Private key used to sign
+    pub(crate) privkey: &'a btc::PrivateKey,
+    /// The script to sign
+    pub(crate) script: &'a Script,
+    /// The previous transaction
+    pub(crate) prev_tx: &'a T,
+    /// The adaptor point
+    pub(crate) adaptor: &'a btc::PublicKey,
+}
+
+/// Verify an encrypted signature on a transaction
+pub struct VerifyEncryptedSig<'a, T: Transaction> {
+    /// Public key of the signer
+    pub(crate) pubkey: &'a btc::PublicKey,
+    /// The encrypted signature
+    pub(crate) sig: &'a EncryptedSignature,
+    /// The signed script
+    pub(crate) script: &'a Script,
+    /// The previous transaction
+    pub(crate) prev_tx: &'a T,
+    /// The adaptor point
+    pub(crate) adaptor: &'a btc::PublicKey,
+}
+
+/// Finalizing step for a transaction spending the cooperative 2-of-2 branch
+pub struct FinalizeMultisig {
+    pub(crate) sig_a: Signature,
+    pub(crate) sig_b: Signature,
+    pub(crate) script: Script,
+}
+
+/// Compute the value of the single output of a transaction spending `in_amount` and paying
+/// `fee`, fails if the fee exceeds the input or if the remaining output would be dust
+pub(crate) fn output_amount(tx: TxKind, in_amount: u64, fee: u64) -> Result<u64> {
+    let out_amount = Satoshi(in_amount).checked_sub(Satoshi(fee))
+        .ok_or(Error::AmountBelowFee { tx, amount: in_amount, fee })?;
+    if out_amount.is_dust() {
+        return Err(Error::OutputBelowDust { tx, amount: out_amount.as_u64() });
+    }
+    Ok(out_amount.as_u64())
+}
+
+/// Segwit sighash for SIG_ALL of the first input spending the first output of
+/// the previous transaction
+pub(crate) fn sighash_all<T: Transaction>(tx: &bitcoin::Transaction, script: &Script, prev_tx: &T) -> Result<Message> {
+    let bip143_comp = SighashComponents::new(tx);
+    let sig_hash = bip143_comp.sighash_all(
+        &tx.input[0],
+        script,
+        prev_tx.to_transaction()?.output[0].value
+    );
+    Ok(Message::parse_slice(&sig_hash[..])?)
+}
@@ -13,14 +13,16 @@
 // copies or substantial portions of the Software.
 //
 
-use crate::types::Result;
-use crate::types::constants::FEE_KB_SATOSHI;
-use crate::types::btc::{Utxo, Input, scripts, PublicKey};
-use crate::transactions::{Builder, Transaction};
+use crate::types::{Result, Error, TxKind};
+use crate::types::amount::Satoshi;
+use crate::types::btc::{Utxo, Input, ExternalInput, scripts, fee, coinselect, PublicKey};
+use crate::types::btc::psbt::Psbt;
+use crate::transactions::{Builder, Validator, Transaction};
 
 use secp256k1::Signature;
 use bitcoin::util::bip143::SighashComponents;
 use bitcoin::blockdata::script::Script;
+use bitcoin::util::address::Address;
 use bitcoin::consensus::encode::serialize_hex;
 
 pub trait Funding: Transaction { }
@@ -48,36 +50,63 @@ impl Transaction for FundingTx {
 impl Funding for FundingTx { }
 
 pub struct New<'a> {
-    pub(crate) utxo: &'a Utxo,
+    /// Candidate coins, the selected ones are spent
+    pub(crate) utxos: &'a [Utxo],
+    /// Amount locked in the swaplock output
+    pub(crate) amount: Satoshi,
+    /// Address receiving the change, if any
+    pub(crate) change_address: &'a Address,
     pub(crate) swaplock_script: &'a Script,
+    /// Feerate in satoshi per virtual byte
+    pub(crate) feerate: u64,
 }
 
 impl<'a> Builder<New<'a>> for FundingTx {
     type Ret = ();
 
     fn build(&mut self, params: New) -> Result<()> {
-        let in_amount: u64 = params.utxo.amount;
-        // TODO: estimate vsize of btx_1
-        let fee = FEE_KB_SATOSHI;
-        let out_amount = in_amount - fee;
+        if params.amount.is_dust() {
+            return Err(Error::OutputBelowDust { tx: TxKind::Funding, amount: params.amount.as_u64() });
+        }
+        let script_pubkey = params.swaplock_script.clone().to_v0_p2wsh();
+        let change_script = params.change_address.script_pubkey();
+        let selection = coinselect::select_coins(
+            params.utxos,
+            params.amount.as_u64(),
+            &[script_pubkey.len()],
+            change_script.len(),
+            params.feerate,
+        )?;
 
-        // TODO: verify all utxos are SegWit programs
-        let btx_1 = bitcoin::Transaction {
-            version: 2,
-            lock_time: 0,
-            input: vec![bitcoin::TxIn {
+        let input = selection.indexes.iter()
+            .map(|index| bitcoin::TxIn {
                 previous_output: bitcoin::OutPoint {
-                    txid: params.utxo.txid,
-                    vout: params.utxo.vout,
+                    txid: params.utxos[*index].txid,
+                    vout: params.utxos[*index].vout,
                 },
                 script_sig: bitcoin::Script::new(),
                 sequence: std::u32::MAX,
                 witness: vec![],
-            }],
-            output: vec![bitcoin::TxOut {
-                value: out_amount,
-                script_pubkey: params.swaplock_script.clone().to_v0_p2wsh(),
-            }],
+            })
+            .collect();
+
+        // The swaplock output is always the first one
+        let mut output = vec![bitcoin::TxOut {
+            value: params.amount.as_u64(),
+            script_pubkey,
+        }];
+        if let Some(change) = selection.change {
+            output.push(bitcoin::TxOut {
+                value: change,
+                script_pubkey: change_script,
+            });
+        }
+
+        let btx_1 = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input,
+            output,
         };
 
         self.tx_hex = Some(serialize_hex(&btx_1));
@@ -86,34 +115,89 @@ impl<'a> Builder<New<'a>> for FundingTx {
     }
 }
 
+/// Structural checks of an unsigned funding transaction received from the buyer: the swaplock
+/// output is first and locks at least the agreed amount, at most one change output follows and
+/// all inputs spend SegWit programs so the txid signed in the refund can not be malleated
+pub struct VerifyFunding<'a> {
+    pub(crate) swaplock_script: &'a Script,
+    /// Agreed amount locked in the swaplock output
+    pub(crate) amount: Satoshi,
+    /// Outputs spent by the transaction, in the order of the inputs
+    pub(crate) spent_outputs: &'a [bitcoin::TxOut],
+}
+
+impl<'a> Validator<VerifyFunding<'a>> for FundingTx {
+    fn validate(&self, params: VerifyFunding) -> Result<()> {
+        let btx_1d = self.to_transaction()?;
+
+        let swaplock = btx_1d.output.get(0).ok_or(Error::ScriptMismatch { tx: TxKind::Funding, index: 0 })?;
+        if swaplock.script_pubkey != params.swaplock_script.to_v0_p2wsh() {
+            return Err(Error::ScriptMismatch { tx: TxKind::Funding, index: 0 });
+        }
+        if Satoshi(swaplock.value) < params.amount {
+            return Err(Error::InsufficientAmount {
+                tx: TxKind::Funding,
+                expected: params.amount.as_u64(),
+                found: swaplock.value,
+            });
+        }
+        // Only a change output may follow the swaplock
+        if btx_1d.output.len() > 2 {
+            return Err(Error::UnexpectedOutput { tx: TxKind::Funding, index: 2 });
+        }
+
+        if btx_1d.input.is_empty() {
+            return Err(Error::MissingInput);
+        }
+        if params.spent_outputs.len() != btx_1d.input.len() {
+            return Err(Error::MissingValue("spent outputs"));
+        }
+        for (input, (txin, spent)) in btx_1d.input.iter().zip(params.spent_outputs.iter()).enumerate() {
+            if !txin.script_sig.is_empty() || !scripts::is_witness_program(&spent.script_pubkey) {
+                return Err(Error::NonWitnessInput { tx: TxKind::Funding, input });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sign all the P2WPKH inputs, the signing keys are matched on the outpoints
 pub struct Sign<'a> {
-    pub(crate) pubkey: &'a PublicKey,
-    pub(crate) input: &'a Input<'a>,
+    pub(crate) inputs: &'a [Input<'a>],
 }
 
 impl<'a> Builder<Sign<'a>> for FundingTx {
-    type Ret = Signature;
+    /// Signatures and public keys in the order of the transaction inputs
+    type Ret = Vec<(Signature, PublicKey)>;
 
-    fn build(&mut self, params: Sign<'a>) -> Result<Signature> {
+    fn build(&mut self, params: Sign<'a>) -> Result<Vec<(Signature, PublicKey)>> {
         let btx_1d = self.to_transaction()?;
         let bip143 = SighashComponents::new(&btx_1d);
-        // Generate Segwit sighash for SIG_ALL
-        let sig_hash = bip143.sighash_all(
-            &btx_1d.input[0],
-            &scripts::redeem_p2pkh(params.pubkey),
-            params.input.amount,
-        );
-        let msg = secp256k1::Message::parse_slice(&sig_hash[..])?;
-
-        let mut s = secp256k1::sign(&msg, params.input.privkey)?.0;
-        s.normalize_s();
-        Ok(s)
+
+        btx_1d.input.iter().map(|txin| {
+            let input = params.inputs.iter()
+                .find(|input| input.txid == txin.previous_output.txid && input.vout == txin.previous_output.vout)
+                .ok_or(Error::MissingValue("input signing key"))?;
+            let pubkey = PublicKey::from_secret_key(input.privkey);
+            // Generate Segwit sighash for SIG_ALL
+            let sig_hash = bip143.sighash_all(
+                txin,
+                &scripts::redeem_p2pkh(&pubkey),
+                input.amount,
+            );
+            let msg = secp256k1::Message::parse_slice(&sig_hash[..])?;
+
+            let mut s = secp256k1::sign(&msg, input.privkey)?.0;
+            s.normalize_s();
+            Ok((s, pubkey))
+        }).collect()
     }
 }
 
+/// Finalize with one signature and public key per input, in the order of the inputs
 pub struct Finalize {
-    pub(crate) sig: Signature,
-    pub(crate) pubkey: PublicKey,
+    pub(crate) sigs: Vec<(Signature, PublicKey)>,
 }
 
 impl Builder<Finalize> for FundingTx {
@@ -122,13 +206,173 @@ impl Builder<Finalize> for FundingTx {
     fn build(&mut self, params: Finalize) -> Result<String> {
         let mut btx_1d = self.to_transaction()?;
 
-        btx_1d.input[0].witness = vec![
-            scripts::serialize_sig(params.sig),
-            params.pubkey.serialize_compressed().to_vec(),
-        ];
+        if params.sigs.len() != btx_1d.input.len() {
+            return Err(Error::TransactionNotComplete(TxKind::Funding));
+        }
+
+        for (txin, (sig, pubkey)) in btx_1d.input.iter_mut().zip(params.sigs.into_iter()) {
+            txin.witness = vec![
+                scripts::serialize_sig(sig),
+                pubkey.serialize_compressed().to_vec(),
+            ];
+        }
 
         let tx = serialize_hex(&btx_1d);
         self.tx_hex = Some(tx.clone());
         Ok(tx)
     }
 }
+
+/// Export the unsigned transaction as a base64 PSBT for an external signer, the swaplock
+/// output is annotated with its witness script
+pub struct ExportPsbt<'a> {
+    pub(crate) inputs: &'a [ExternalInput],
+    pub(crate) swaplock_script: &'a Script,
+}
+
+impl<'a> Builder<ExportPsbt<'a>> for FundingTx {
+    type Ret = String;
+
+    fn build(&mut self, params: ExportPsbt) -> Result<String> {
+        let btx_1d = self.to_transaction()?;
+        let mut psbt = Psbt::from_unsigned_tx(btx_1d.clone())?;
+
+        for (txin, input) in btx_1d.input.iter().zip(psbt.inputs.iter_mut()) {
+            let coin = params.inputs.iter()
+                .find(|coin| coin.utxo.txid == txin.previous_output.txid && coin.utxo.vout == txin.previous_output.vout)
+                .ok_or(Error::MissingValue("input coin"))?;
+            input.witness_utxo = Some(bitcoin::TxOut {
+                value: coin.utxo.amount,
+                script_pubkey: coin.address.script_pubkey(),
+            });
+            // SIGHASH_ALL
+            input.sighash_type = Some(1);
+        }
+        psbt.outputs[0].witness_script = Some(params.swaplock_script.clone());
+
+        Ok(psbt.to_base64())
+    }
+}
+
+/// Finalize the transaction with a PSBT signed by an external wallet, the PSBT must spend the
+/// same P2WPKH inputs to the same outputs
+pub struct FinalizePsbt<'a> {
+    pub(crate) psbt: &'a str,
+}
+
+impl<'a> Builder<FinalizePsbt<'a>> for FundingTx {
+    type Ret = String;
+
+    fn build(&mut self, params: FinalizePsbt) -> Result<String> {
+        let psbt = Psbt::from_base64(params.psbt)?;
+        let mut btx_1d = self.to_transaction()?;
+        if psbt.unsigned_tx.txid() != btx_1d.txid() {
+            return Err(Error::InvalidPsbt);
+        }
+
+        let unsigned = btx_1d.clone();
+        let bip143 = SighashComponents::new(&unsigned);
+        for (index, (txin, input)) in btx_1d.input.iter_mut().zip(psbt.inputs.iter()).enumerate() {
+            let (pubkey, sig) = match &input.final_witness {
+                Some(stack) if stack.len() == 2 => {
+                    if stack[1].len() != 33 {
+                        return Err(Error::InvalidPsbt);
+                    }
+                    let mut pubkey = [0u8; 33];
+                    pubkey.copy_from_slice(&stack[1]);
+                    (PublicKey::parse_compressed(&pubkey)?, stack[0].clone())
+                },
+                Some(_) => return Err(Error::InvalidPsbt),
+                None => input.partial_sigs.first().cloned().ok_or(Error::TransactionNotComplete(TxKind::Funding))?,
+            };
+
+            let utxo = input.witness_utxo.as_ref().ok_or(Error::InvalidPsbt)?;
+            if utxo.script_pubkey != scripts::p2wpkh_script_pubkey(&pubkey) {
+                return Err(Error::ScriptMismatch { tx: TxKind::Funding, index });
+            }
+            // Generate Segwit sighash for SIG_ALL
+            let sig_hash = bip143.sighash_all(
+                txin,
+                &scripts::redeem_p2pkh(&pubkey),
+                utxo.value,
+            );
+            let msg = secp256k1::Message::parse_slice(&sig_hash[..])?;
+            let invalid = Error::InvalidSignature { tx: TxKind::Funding, input: index, sig: "psbt sig" };
+            let signature = match scripts::parse_sig(&sig) {
+                Some(signature) => signature,
+                None => return Err(invalid),
+            };
+            if !secp256k1::verify(&msg, &signature, &pubkey) {
+                return Err(invalid);
+            }
+
+            txin.witness = vec![sig, pubkey.serialize_compressed().to_vec()];
+        }
+
+        let tx = serialize_hex(&btx_1d);
+        self.tx_hex = Some(tx.clone());
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FundingTx, VerifyFunding};
+    use crate::types::Error;
+    use crate::types::amount::Satoshi;
+    use crate::types::btc::{PrivateKey, PublicKey, scripts};
+    use crate::transactions::Validator;
+    use bitcoin::{Script, TxIn, TxOut, OutPoint};
+    use bitcoin::consensus::encode::serialize_hex;
+
+    fn funding(outputs: Vec<TxOut>) -> FundingTx {
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::new(),
+                sequence: std::u32::MAX,
+                witness: vec![],
+            }],
+            output: outputs,
+        };
+        FundingTx::from_hex(serialize_hex(&tx))
+    }
+
+    #[test]
+    fn verify_funding() {
+        let pubkey = PublicKey::from_secret_key(&PrivateKey::parse(&[1; 32]).unwrap());
+        let swaplock = scripts::create_swaplock(&pubkey, &pubkey, &[[2; 32]], &[4; 32], 10);
+        let p2wpkh = scripts::p2wpkh_script_pubkey(&pubkey);
+        let lock = TxOut { value: 50_000, script_pubkey: swaplock.to_v0_p2wsh() };
+        let change = TxOut { value: 1_000, script_pubkey: p2wpkh.clone() };
+        let spent = vec![TxOut { value: 60_000, script_pubkey: p2wpkh.clone() }];
+        let verify = |tx: &FundingTx, spent: &[TxOut]| tx.validate(VerifyFunding {
+            swaplock_script: &swaplock,
+            amount: Satoshi(50_000),
+            spent_outputs: spent,
+        });
+
+        assert!(verify(&funding(vec![lock.clone(), change.clone()]), &spent).is_ok());
+
+        let low = TxOut { value: 49_999, ..lock.clone() };
+        match verify(&funding(vec![low]), &spent) {
+            Err(Error::InsufficientAmount { found: 49_999, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        match verify(&funding(vec![change.clone(), lock.clone()]), &spent) {
+            Err(Error::ScriptMismatch { index: 0, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        match verify(&funding(vec![lock.clone(), change.clone(), change]), &spent) {
+            Err(Error::UnexpectedOutput { index: 2, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        let legacy = vec![TxOut { value: 60_000, script_pubkey: swaplock.to_p2sh() }];
+        match verify(&funding(vec![lock]), &legacy) {
+            Err(Error::NonWitnessInput { input: 0, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
+}
@@ -13,13 +13,14 @@
 // copies or substantial portions of the Software.
 //
 
-use crate::types::{Result, Error};
-use crate::types::btc;
+use crate::types::{Result, Error, TxKind};
+use crate::types::btc::{self, fee};
 use crate::transactions::{Builder, Validator, Transaction};
-use crate::types::constants::FEE_KB_SATOSHI;
 use crate::types::RelativeLocktime;
+use crate::types::constants::{MIN_RELAY_FEERATE, MAX_FEERATE};
 use crate::transactions::btc::funding::FundingTx;
 use crate::types::btc::scripts::redeem_swaplock_multisig;
+use crate::transactions::btc::common::output_amount;
 
 use secp256k1::Signature;
 use bitcoin::blockdata::script::Script;
@@ -54,6 +55,10 @@ impl<'a> Refund for RefundTx<'a> { }
 pub struct New<'a> {
     pub(crate) refund_script: &'a Script,
     pub(crate) t_0: RelativeLocktime,
+    /// Expected size of the witness spending the swaplock output
+    pub(crate) witness_size: usize,
+    /// Feerate in satoshi per virtual byte
+    pub(crate) feerate: u64,
 }
 
 impl<'a, 'b> Builder<New<'a>> for RefundTx<'b> {
@@ -62,7 +67,10 @@ impl<'a, 'b> Builder<New<'a>> for RefundTx<'b> {
     fn build(&mut self, params: New) -> Result<()> {
         let btx_1d = self.btx_1.to_transaction()?;
         let btx_2_in_amount = btx_1d.output[0].value;
-        let btx_2_refund_amount = btx_2_in_amount - FEE_KB_SATOSHI / 2;
+        let script_pubkey = params.refund_script.clone().to_v0_p2wsh();
+        let weight = fee::weight(&[params.witness_size], &[script_pubkey.len()]);
+        let fee = fee::fee(weight, params.feerate)?;
+        let btx_2_refund_amount = output_amount(TxKind::Refund, btx_2_in_amount, fee)?;
 
         let btx_2 = bitcoin::Transaction {
             version: 2,
@@ -78,7 +86,7 @@ impl<'a, 'b> Builder<New<'a>> for RefundTx<'b> {
             }],
             output: vec![bitcoin::TxOut {
                 value: btx_2_refund_amount,
-                script_pubkey: params.refund_script.clone().to_v0_p2wsh(),
+                script_pubkey,
             }],
         };
 
@@ -98,6 +106,8 @@ impl<'a, 'b> Builder<Sign<'a>> for RefundTx<'b> {
     fn build(&mut self, params: Sign) -> Result<Signature> {
         let btx_1d = self.btx_1.to_transaction()?;
         let btx_2d = self.to_transaction()?;
+        // Never sign a refund that could not spend the timelocked branch of the swaplock
+        RelativeLocktime::check_spend(TxKind::Refund, 0, params.swaplock_script, btx_2d.input[0].sequence)?;
 
         let bip143_comp = SighashComponents::new(&btx_2d);
         // Generate Segwit sighash for SIG_ALL
@@ -136,6 +146,61 @@ impl<'a> Builder<Finalize> for RefundTx<'a> {
     }
 }
 
+/// Structural checks of the refund received from the buyer before co-signing it: version 2, no
+/// lock time, a single input spending `btx_1:0` with the agreed `t_0`, a single output locked in
+/// the refund script and a fee between the minimum relay feerate and `MAX_FEERATE`
+pub struct VerifyStructure<'a> {
+    pub(crate) refund_script: &'a Script,
+    pub(crate) t_0: RelativeLocktime,
+    /// Expected size of the witness spending the swaplock output
+    pub(crate) witness_size: usize,
+}
+
+impl<'a, 'b> Validator<VerifyStructure<'b>> for RefundTx<'a> {
+    fn validate(&self, params: VerifyStructure) -> Result<()> {
+        let btx_1d = self.btx_1.to_transaction()?;
+        let btx_2d = self.to_transaction()?;
+
+        if btx_2d.version != 2 {
+            return Err(Error::InvalidVersion(TxKind::Refund));
+        }
+        // The sequence of the input is not final, nLockTime is enforced
+        if btx_2d.lock_time != 0 {
+            return Err(Error::NonZeroLockTime { tx: TxKind::Refund, lock_time: btx_2d.lock_time });
+        }
+
+        let txin = btx_2d.input.get(0).ok_or(Error::MissingInput)?;
+        if btx_2d.input.len() > 1 {
+            return Err(Error::InvalidOutpoint { tx: TxKind::Refund, input: 1 });
+        }
+        if txin.previous_output.txid != btx_1d.txid() || txin.previous_output.vout != 0 {
+            return Err(Error::InvalidOutpoint { tx: TxKind::Refund, input: 0 });
+        }
+        if txin.sequence != params.t_0.as_u32() {
+            return Err(Error::InvalidSequence { tx: TxKind::Refund, input: 0 });
+        }
+
+        let script_pubkey = params.refund_script.to_v0_p2wsh();
+        let txout = btx_2d.output.get(0).ok_or(Error::ScriptMismatch { tx: TxKind::Refund, index: 0 })?;
+        if txout.script_pubkey != script_pubkey {
+            return Err(Error::ScriptMismatch { tx: TxKind::Refund, index: 0 });
+        }
+        if btx_2d.output.len() > 1 {
+            return Err(Error::UnexpectedOutput { tx: TxKind::Refund, index: 1 });
+        }
+
+        let in_amount = btx_1d.output.get(0).ok_or(Error::MissingInput)?.value;
+        let fee = in_amount.checked_sub(txout.value)
+            .ok_or(Error::InvalidFee { tx: TxKind::Refund, fee: 0 })?;
+        let weight = fee::weight(&[params.witness_size], &[script_pubkey.len()]);
+        if fee < fee::fee(weight, MIN_RELAY_FEERATE)? || fee > fee::fee(weight, MAX_FEERATE)? {
+            return Err(Error::InvalidFee { tx: TxKind::Refund, fee });
+        }
+
+        Ok(())
+    }
+}
+
 pub struct VerifySigA<'a> {
     pub(crate) pubkey: &'a btc::PublicKey,
     pub(crate) swaplock_script: &'a Script,
@@ -143,26 +208,49 @@ pub struct VerifySigA<'a> {
 
 impl<'a, 'b> Validator<VerifySigA<'b>> for RefundTx<'a> {
     fn validate(&self, params: VerifySigA) -> Result<()> {
+        self.verify_witness_sig(1, "sig_a", params.pubkey, params.swaplock_script)
+    }
+}
+
+/// Checks that the refund co-signed by the seller is the refund created by the buyer, i.e. it has
+/// the same txid, and that the buyer's signature in its witness is valid
+pub struct VerifySigned<'a> {
+    pub(crate) btx_2: &'a RefundTx<'a>,
+    pub(crate) pubkey: &'a btc::PublicKey,
+    pub(crate) swaplock_script: &'a Script,
+}
+
+impl<'a, 'b> Validator<VerifySigned<'b>> for RefundTx<'a> {
+    fn validate(&self, params: VerifySigned) -> Result<()> {
+        if self.to_transaction()?.txid() != params.btx_2.to_transaction()?.txid() {
+            return Err(Error::TransactionMismatch(TxKind::Refund));
+        }
+        self.verify_witness_sig(2, "sig_b", params.pubkey, params.swaplock_script)
+    }
+}
+
+impl<'a> RefundTx<'a> {
+    /// Verify the signature at `index` in the multisig witness of the swaplock input
+    fn verify_witness_sig(&self, index: usize, sig: &'static str, pubkey: &btc::PublicKey,
+                          swaplock_script: &Script) -> Result<()> {
         let btx_1d = self.btx_1.to_transaction()?;
         let btx_2d = self.to_transaction()?;
-        let sig_a = {
-            let sig = &btx_2d.input[0].witness[1];
-            Signature::parse_der(&sig[..sig.len()-1])?
-        };
+        let invalid = || Error::InvalidSignature { tx: TxKind::Refund, input: 0, sig };
+        let txin = btx_2d.input.get(0).ok_or(Error::MissingInput)?;
+        let signature = txin.witness.get(index)
+            .and_then(|sig| btc::scripts::parse_sig(sig))
+            .ok_or_else(invalid)?;
+        let in_amount = btx_1d.output.get(0).ok_or(Error::MissingInput)?.value;
 
         let msg = {
             let bip143_comp = SighashComponents::new(&btx_2d);
-            let sig_hash = bip143_comp.sighash_all(
-                &btx_2d.input[0],
-                params.swaplock_script,
-                btx_1d.output[0].value
-            );
+            let sig_hash = bip143_comp.sighash_all(txin, swaplock_script, in_amount);
             secp256k1::Message::parse_slice(&sig_hash[..])?
         };
 
-        match secp256k1::verify(&msg, &sig_a, params.pubkey) {
+        match secp256k1::verify(&msg, &signature, pubkey) {
             true => Ok(()),
-            false => Err(Error::InvalidSignature),
+            false => Err(invalid()),
         }
     }
 }
@@ -178,17 +266,202 @@ impl<'a, 'b> Validator<VerifySigB<'b>> for RefundTx<'a> {
         let btx_1d = self.btx_1.to_transaction()?;
         let btx_2d = self.to_transaction()?;
 
+        let txin = btx_2d.input.get(0).ok_or(Error::MissingInput)?;
+        let in_amount = btx_1d.output.get(0).ok_or(Error::MissingInput)?.value;
+
         let bip143_comp = SighashComponents::new(&btx_2d);
-        let sig_hash = bip143_comp.sighash_all(
-            &btx_2d.input[0],
-            params.swaplock_script,
-            btx_1d.output[0].value
-        );
+        let sig_hash = bip143_comp.sighash_all(txin, params.swaplock_script, in_amount);
         let msg = secp256k1::Message::parse_slice(&sig_hash[..])?;
 
         match secp256k1::verify(&msg, params.sig, params.pubkey) {
             true => Ok(()),
-            false => Err(Error::InvalidSignature),
+            false => Err(Error::InvalidSignature { tx: TxKind::Refund, input: 0, sig: "sig_b" }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RefundTx, New, Sign, Finalize, VerifyStructure, VerifySigA, VerifySigned};
+    use crate::types::{Error, RelativeLocktime};
+    use crate::types::btc::{PrivateKey, PublicKey, scripts, fee};
+    use crate::transactions::btc::funding::FundingTx;
+    use crate::transactions::{Builder, Validator, Transaction};
+    use bitcoin::{Script, TxIn, TxOut, OutPoint};
+    use crate::types::constants::DUST_LIMIT;
+    use bitcoin::consensus::encode::serialize_hex;
+
+    #[test]
+    fn verify_structure() {
+        let pubkey = PublicKey::from_secret_key(&PrivateKey::parse(&[1; 32]).unwrap());
+        let swaplock = scripts::create_swaplock(&pubkey, &pubkey, &[[2; 32]], &[4; 32], 10);
+        let refund = scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], 6);
+        let btx_1 = FundingTx::from_hex(serialize_hex(&bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::new(),
+                sequence: std::u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 10_000_000, script_pubkey: swaplock.to_v0_p2wsh() }],
+        }));
+        let witness_size = fee::swaplock_multisig_witness(&swaplock);
+
+        let mut btx_2 = RefundTx::new(&btx_1);
+        btx_2.build(New {
+            refund_script: &refund,
+            t_0: RelativeLocktime::Blocks(10),
+            witness_size,
+            feerate: 2,
+        }).unwrap();
+        let verify = |btx_2: &RefundTx, refund_script: &Script, t_0| btx_2.validate(VerifyStructure {
+            refund_script,
+            t_0,
+            witness_size,
+        });
+        assert!(verify(&btx_2, &refund, RelativeLocktime::Blocks(10)).is_ok());
+
+        match verify(&btx_2, &refund, RelativeLocktime::Blocks(9)) {
+            Err(Error::InvalidSequence { input: 0, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        let other = scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], 5);
+        match verify(&btx_2, &other, RelativeLocktime::Blocks(10)) {
+            Err(Error::ScriptMismatch { index: 0, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+
+        // A refund that can not be mined before a far lock time is rejected
+        let mut tx = btx_2.to_transaction().unwrap();
+        tx.lock_time = 500_000_000 + 10 * 365 * 24 * 3600;
+        let locked = RefundTx::from_hex(serialize_hex(&tx), &btx_1);
+        match verify(&locked, &refund, RelativeLocktime::Blocks(10)) {
+            Err(Error::NonZeroLockTime { lock_time, .. }) => assert_eq!(tx.lock_time, lock_time),
+            e => panic!("unexpected result {:?}", e),
+        }
+
+        // Paying most of the funds to the miners is rejected
+        let mut tx = btx_2.to_transaction().unwrap();
+        tx.output[0].value = 1_000;
+        let greedy = RefundTx::from_hex(serialize_hex(&tx), &btx_1);
+        match verify(&greedy, &refund, RelativeLocktime::Blocks(10)) {
+            Err(Error::InvalidFee { fee: 9_999_000, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
+
+    #[test]
+    fn reject_dust_output() {
+        let pubkey = PublicKey::from_secret_key(&PrivateKey::parse(&[1; 32]).unwrap());
+        let swaplock = scripts::create_swaplock(&pubkey, &pubkey, &[[2; 32]], &[4; 32], 10);
+        let refund = scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], 6);
+        let witness_size = fee::swaplock_multisig_witness(&swaplock);
+        let weight = fee::weight(&[witness_size], &[refund.to_v0_p2wsh().len()]);
+        let expected_fee = fee::fee(weight, 2).unwrap();
+
+        let build = |value: u64| {
+            let btx_1 = FundingTx::from_hex(serialize_hex(&bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: Script::new(),
+                    sequence: std::u32::MAX,
+                    witness: vec![],
+                }],
+                output: vec![TxOut { value, script_pubkey: swaplock.to_v0_p2wsh() }],
+            }));
+            let mut btx_2 = RefundTx::new(&btx_1);
+            let res = btx_2.build(New {
+                refund_script: &refund,
+                t_0: RelativeLocktime::Blocks(10),
+                witness_size,
+                feerate: 2,
+            });
+            res
+        };
+        match build(expected_fee - 1) {
+            Err(Error::AmountBelowFee { amount, fee, .. }) => assert_eq!((amount, fee), (expected_fee - 1, expected_fee)),
+            e => panic!("unexpected result {:?}", e),
+        }
+        match build(expected_fee + 100) {
+            Err(Error::OutputBelowDust { amount: 100, .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        assert!(build(expected_fee + DUST_LIMIT).is_ok());
+    }
+
+    #[test]
+    fn verify_signed() {
+        let b_a = PrivateKey::parse(&[1; 32]).unwrap();
+        let b_b = PrivateKey::parse(&[5; 32]).unwrap();
+        let (pubkey_a, pubkey_b) = (PublicKey::from_secret_key(&b_a), PublicKey::from_secret_key(&b_b));
+        let swaplock = scripts::create_swaplock(&pubkey_a, &pubkey_b, &[[2; 32]], &[4; 32], 10);
+        let refund = scripts::create_refund(&pubkey_a, &pubkey_b, &[[3; 32]], 6);
+        let btx_1 = FundingTx::from_hex(serialize_hex(&bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::new(),
+                sequence: std::u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 10_000_000, script_pubkey: swaplock.to_v0_p2wsh() }],
+        }));
+
+        let mut btx_2 = RefundTx::new(&btx_1);
+        btx_2.build(New {
+            refund_script: &refund,
+            t_0: RelativeLocktime::Blocks(10),
+            witness_size: fee::swaplock_multisig_witness(&swaplock),
+            feerate: 2,
+        }).unwrap();
+        let unsigned = RefundTx::from_hex(btx_2.to_hex().unwrap(), &btx_1);
+        let sig_a = btx_2.build(Sign { privkey: &b_a, swaplock_script: &swaplock }).unwrap();
+        let sig_b = btx_2.build(Sign { privkey: &b_b, swaplock_script: &swaplock }).unwrap();
+        btx_2.build(Finalize { sig_a, sig_b, swaplock_script: swaplock.clone() }).unwrap();
+
+        let verify_a = |btx_2: &RefundTx| btx_2.validate(VerifySigA { pubkey: &pubkey_a, swaplock_script: &swaplock });
+        let verify_b = |btx_2: &RefundTx| btx_2.validate(VerifySigned {
+            btx_2: &unsigned,
+            pubkey: &pubkey_b,
+            swaplock_script: &swaplock,
+        });
+        assert!(verify_a(&btx_2).is_ok());
+        assert!(verify_b(&btx_2).is_ok());
+
+        // The seller replaced the buyer's signature
+        let mut swapped = RefundTx::from_hex(unsigned.to_hex().unwrap(), &btx_1);
+        swapped.build(Finalize { sig_a, sig_b: sig_a, swaplock_script: swaplock.clone() }).unwrap();
+        match verify_b(&swapped) {
+            Err(Error::InvalidSignature { sig: "sig_b", .. }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+
+        // The seller signed another refund
+        let mut tx = btx_2.to_transaction().unwrap();
+        tx.output[0].value -= 1_000;
+        match verify_b(&RefundTx::from_hex(serialize_hex(&tx), &btx_1)) {
+            Err(Error::TransactionMismatch(_)) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+
+        // Truncated witnesses are rejected without panicking
+        for witness in vec![vec![], vec![vec![]], vec![vec![], vec![]], vec![vec![], vec![1]]] {
+            let mut tx = btx_2.to_transaction().unwrap();
+            tx.input[0].witness = witness;
+            let truncated = RefundTx::from_hex(serialize_hex(&tx), &btx_1);
+            match verify_a(&truncated) {
+                Err(Error::InvalidSignature { sig: "sig_a", .. }) => (),
+                e => panic!("unexpected result {:?}", e),
+            }
+            match verify_b(&truncated) {
+                Err(Error::InvalidSignature { sig: "sig_b", .. }) => (),
+                e => panic!("unexpected result {:?}", e),
+            }
         }
     }
 }
@@ -13,12 +13,14 @@
 // copies or substantial portions of the Software.
 //
 
-use crate::types::Result;
-use crate::transactions::{Builder, Transaction};
-use crate::types::constants::FEE_KB_SATOSHI;
-use crate::types::btc::scripts::redeem_refund;
+use crate::types::{Result, TxKind};
+use crate::transactions::{Builder, Validator, Transaction};
+use crate::types::btc::fee;
+use crate::types::btc::adaptor::{self, EncryptedSignature};
+use crate::types::btc::scripts::{redeem_refund, redeem_adaptor_multisig};
 use crate::transactions::btc::refund::Refund;
-use crate::transactions::btc::common::{New, Sign, Finalize};
+use crate::transactions::btc::common::{New, Sign, Finalize, EncryptedSign, VerifyEncryptedSig, FinalizeMultisig,
+    sighash_all, output_amount};
 
 use secp256k1::Signature;
 use bitcoin::consensus::encode::serialize_hex;
@@ -50,7 +52,10 @@ impl<'a, T> Builder<New<'a, T>> for SpendRefundTx where T: Refund {
 
     fn build(&mut self, params: New<T>) -> Result<()> {
         let refund = params.prev_tx.to_transaction()?;
-        let out_amount = refund.output[0].value - FEE_KB_SATOSHI / 2;
+        let script_pubkey = params.final_address.script_pubkey();
+        let weight = fee::weight(&[params.witness_size], &[script_pubkey.len()]);
+        let fee = fee::fee(weight, params.feerate)?;
+        let out_amount = output_amount(TxKind::SpendRefund, refund.output[0].value, fee)?;
         let spend_refund = bitcoin::Transaction {
             version: 2,
             lock_time: 0,
@@ -65,7 +70,7 @@ impl<'a, T> Builder<New<'a, T>> for SpendRefundTx where T: Refund {
             }],
             output: vec![bitcoin::TxOut {
                 value: out_amount,
-                script_pubkey: params.final_address.script_pubkey(),
+                script_pubkey,
             }],
         };
 
@@ -103,10 +108,43 @@ impl<'a> Builder<Finalize<'a>> for SpendRefundTx {
         spend_refund.input[0].witness = redeem_refund(
             params.script,
             params.sig,
-            params.privkey,
+            params.preimages,
         );
         let spend_refund = serialize_hex(&spend_refund);
         self.tx_hex = Some(spend_refund.clone());
         Ok(spend_refund)
     }
 }
+
+impl<'a, R> Builder<EncryptedSign<'a, R>> for SpendRefundTx where R: Refund {
+    type Ret = EncryptedSignature;
+
+    fn build(&mut self, params: EncryptedSign<R>) -> Result<EncryptedSignature> {
+        let msg = sighash_all(&self.to_transaction()?, params.script, params.prev_tx)?;
+        adaptor::encrypted_sign(&msg, params.privkey, params.adaptor)
+    }
+}
+
+impl<'a, R> Validator<VerifyEncryptedSig<'a, R>> for SpendRefundTx where R: Refund {
+    fn validate(&self, params: VerifyEncryptedSig<R>) -> Result<()> {
+        let msg = sighash_all(&self.to_transaction()?, params.script, params.prev_tx)?;
+        adaptor::encrypted_verify(&msg, params.pubkey, params.adaptor, params.sig)
+            .map_err(|e| e.on_signature(TxKind::SpendRefund, 0, "encrypted sig_a"))
+    }
+}
+
+impl Builder<FinalizeMultisig> for SpendRefundTx {
+    type Ret = String;
+
+    fn build(&mut self, params: FinalizeMultisig) -> Result<String> {
+        let mut tx = self.to_transaction()?;
+        tx.input[0].witness = redeem_adaptor_multisig(
+            params.script,
+            params.sig_a,
+            params.sig_b,
+        );
+        let tx = serialize_hex(&tx);
+        self.tx_hex = Some(tx.clone());
+        Ok(tx)
+    }
+}
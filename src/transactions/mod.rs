@@ -16,26 +16,19 @@
 //! Collections of structures to create and validate transactions
 
 use bitcoin::consensus::encode::deserialize;
-use crate::types::Result;
+use crate::types::{Result, Error};
 
 pub mod btc;
-//pub mod xmr;
+pub mod xmr;
 
 /// Represente a transaction that can be send and receive over the network
 pub trait Transaction {
     fn to_hex(&self) -> Option<String>;
 
-    fn to_transaction(&self) -> Option<bitcoin::Transaction> {
-        match self.to_hex() {
-            Some(tx) => match hex::decode(&tx) {
-                Ok(res) => match deserialize(&res[..]) {
-                    Ok(res) => Some(res),
-                    Err(_) => None,
-                },
-                Err(_) => None,
-            },
-            None => None,
-        }
+    /// Decode the transaction, fail if it is not set or can not be decoded
+    fn to_transaction(&self) -> Result<bitcoin::Transaction> {
+        let tx = self.to_hex().ok_or(Error::MissingTransaction)?;
+        Ok(deserialize(&hex::decode(&tx)?[..])?)
     }
 }
 
@@ -13,4 +13,498 @@
 // copies or substantial portions of the Software.
 //
 
-//TODO: implement monero
+
+//! Monero lock transaction (Xtx) sending the swap amount to the shared address
+
+use crate::types::{Result, Error, TxKind};
+use crate::types::xmr;
+use crate::types::amount::Piconero;
+use crate::types::xmr::keys::{self, read_varint};
+use crate::transactions::{Builder, Validator};
+use crate::backend::xmr::MoneroBackend;
+
+/// A wallet able to create and sign RingCT transactions. The ring selection,
+/// range proofs and signatures are delegated to the wallet implementation
+/// (e.g. `monero-wallet-rpc` with `do_not_relay`), transactions are relayed
+/// with a `MoneroBackend`
+pub trait Wallet {
+    /// Create and sign a transaction sending `amount` piconero to the address
+    /// defined by the public spend and view keys, the transaction is not relayed
+    fn transfer(&self, spend: &xmr::PublicKey, view: &xmr::PublicKey, amount: u64) -> Result<SignedTransfer>;
+
+    /// Create and sign a transaction sending all the funds controlled by the
+    /// private spend and view keys to the destination keys, the transaction is
+    /// not relayed
+    fn sweep(&self, spend: &xmr::PrivateKey, view: &xmr::PrivateKey, dest_spend: &xmr::PublicKey,
+             dest_view: &xmr::PublicKey) -> Result<SignedTransfer>;
+}
+
+/// Signed transaction returned by a wallet
+#[derive(Debug, Clone)]
+pub struct SignedTransfer {
+    /// Serialized transaction
+    pub tx_hex: String,
+    /// Transaction hash
+    pub tx_hash: [u8; 32],
+    /// Transaction public key `R` used to derive the outputs
+    pub tx_pubkey: xmr::PublicKey,
+}
+
+/// RingCT types as defined in Monero `rctTypes.h`
+const RCT_TYPE_NULL: u8 = 0;
+const RCT_TYPE_BULLETPROOF2: u8 = 4;
+const RCT_TYPE_BULLETPROOF_PLUS: u8 = 6;
+
+/// Extra field tags as defined in Monero `tx_extra.h`
+const TX_EXTRA_TAG_PADDING: u8 = 0x00;
+const TX_EXTRA_TAG_PUBKEY: u8 = 0x01;
+const TX_EXTRA_NONCE: u8 = 0x02;
+const TX_EXTRA_TAG_ADDITIONAL_PUBKEYS: u8 = 0x04;
+
+/// Output of a Monero transaction
+#[derive(Debug, Clone)]
+pub struct TxOut {
+    /// Clear amount, always zero in RingCT outputs
+    pub amount: u64,
+    /// One-time output key
+    pub key: xmr::PublicKey,
+}
+
+/// Encrypted amount of an output
+#[derive(Debug, Clone)]
+pub enum EcdhInfo {
+    /// Full mask and amount before Bulletproof2
+    Standard { mask: [u8; 32], amount: [u8; 32] },
+    /// Compact 8 bytes amount from Bulletproof2
+    Compact { amount: [u8; 8] },
+}
+
+/// Parts of a Monero transaction needed to scan and validate outputs, the
+/// prunable part (ring signatures and range proofs) is not decoded
+#[derive(Debug, Clone)]
+pub struct MoneroTransaction {
+    pub version: u64,
+    pub unlock_time: u64,
+    /// Key images of the spent inputs
+    pub key_images: Vec<[u8; 32]>,
+    pub outputs: Vec<TxOut>,
+    pub extra: Vec<u8>,
+    pub rct_type: u8,
+    pub fee: u64,
+    pub ecdh_info: Vec<EcdhInfo>,
+    pub out_pk: Vec<xmr::PublicKey>,
+}
+
+fn read_bytes<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let end = pos.checked_add(len).ok_or(Error::InvalidMoneroTransaction)?;
+    let bytes = buf.get(*pos..end).ok_or(Error::InvalidMoneroTransaction)?;
+    *pos = end;
+    Ok(bytes)
+}
+
+fn read_u8(buf: &[u8], pos: &mut usize) -> Result<u8> {
+    Ok(read_bytes(buf, pos, 1)?[0])
+}
+
+impl MoneroTransaction {
+    /// Decode the prefix and the RingCT base of a serialized transaction
+    pub fn deserialize(buf: &[u8]) -> Result<MoneroTransaction> {
+        let mut pos = 0;
+        let version = read_varint(buf, &mut pos)?;
+        if version != 2 {
+            return Err(Error::InvalidMoneroTransaction);
+        }
+        let unlock_time = read_varint(buf, &mut pos)?;
+
+        let vin = read_varint(buf, &mut pos)?;
+        let mut key_images = vec![];
+        for _ in 0..vin {
+            match read_u8(buf, &mut pos)? {
+                // txin_gen
+                0xff => { read_varint(buf, &mut pos)?; },
+                // txin_to_key
+                0x02 => {
+                    read_varint(buf, &mut pos)?;
+                    let offsets = read_varint(buf, &mut pos)?;
+                    for _ in 0..offsets {
+                        read_varint(buf, &mut pos)?;
+                    }
+                    let mut key_image = [0u8; 32];
+                    key_image.copy_from_slice(read_bytes(buf, &mut pos, 32)?);
+                    key_images.push(key_image);
+                },
+                _ => return Err(Error::InvalidMoneroTransaction),
+            }
+        }
+
+        let vout = read_varint(buf, &mut pos)?;
+        let mut outputs = vec![];
+        for _ in 0..vout {
+            let amount = read_varint(buf, &mut pos)?;
+            let key = match read_u8(buf, &mut pos)? {
+                // txout_to_key
+                0x02 => keys::decompress(read_bytes(buf, &mut pos, 32)?)?,
+                // txout_to_tagged_key, view tag is ignored
+                0x03 => {
+                    let key = keys::decompress(read_bytes(buf, &mut pos, 32)?)?;
+                    read_u8(buf, &mut pos)?;
+                    key
+                },
+                _ => return Err(Error::InvalidMoneroTransaction),
+            };
+            outputs.push(TxOut { amount, key });
+        }
+
+        let extra_len = read_varint(buf, &mut pos)? as usize;
+        let extra = read_bytes(buf, &mut pos, extra_len)?.to_vec();
+
+        let rct_type = read_u8(buf, &mut pos)?;
+        let mut fee = 0;
+        let mut ecdh_info = vec![];
+        let mut out_pk = vec![];
+        if rct_type != RCT_TYPE_NULL {
+            if rct_type > RCT_TYPE_BULLETPROOF_PLUS {
+                return Err(Error::InvalidMoneroTransaction);
+            }
+            fee = read_varint(buf, &mut pos)?;
+            // RCTTypeSimple carries pseudo outputs in the base
+            if rct_type == 2 {
+                read_bytes(buf, &mut pos, 32 * vin as usize)?;
+            }
+            for _ in 0..outputs.len() {
+                if rct_type >= RCT_TYPE_BULLETPROOF2 {
+                    let mut amount = [0u8; 8];
+                    amount.copy_from_slice(read_bytes(buf, &mut pos, 8)?);
+                    ecdh_info.push(EcdhInfo::Compact { amount });
+                } else {
+                    let mut mask = [0u8; 32];
+                    mask.copy_from_slice(read_bytes(buf, &mut pos, 32)?);
+                    let mut amount = [0u8; 32];
+                    amount.copy_from_slice(read_bytes(buf, &mut pos, 32)?);
+                    ecdh_info.push(EcdhInfo::Standard { mask, amount });
+                }
+            }
+            for _ in 0..outputs.len() {
+                out_pk.push(keys::decompress(read_bytes(buf, &mut pos, 32)?)?);
+            }
+        }
+
+        Ok(MoneroTransaction {
+            version,
+            unlock_time,
+            key_images,
+            outputs,
+            extra,
+            rct_type,
+            fee,
+            ecdh_info,
+            out_pk,
+        })
+    }
+
+    /// Transaction public key `R` stored in the extra field
+    pub fn tx_pubkey(&self) -> Option<xmr::PublicKey> {
+        let mut pos = 0;
+        while pos < self.extra.len() {
+            match read_u8(&self.extra, &mut pos).ok()? {
+                TX_EXTRA_TAG_PADDING => continue,
+                TX_EXTRA_TAG_PUBKEY => return keys::decompress(read_bytes(&self.extra, &mut pos, 32).ok()?).ok(),
+                TX_EXTRA_NONCE => {
+                    let len = read_varint(&self.extra, &mut pos).ok()? as usize;
+                    read_bytes(&self.extra, &mut pos, len).ok()?;
+                },
+                TX_EXTRA_TAG_ADDITIONAL_PUBKEYS => {
+                    let count = read_varint(&self.extra, &mut pos).ok()? as usize;
+                    read_bytes(&self.extra, &mut pos, count.checked_mul(32)?).ok()?;
+                },
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Find the output sent to the spend key `X` with the view key `a` and the
+    /// transaction public key `R`, return its index and the decoded amount
+    /// after checking the amount commitment
+    pub fn scan(&self, view: &xmr::PrivateKey, tx_pubkey: &xmr::PublicKey, spend: &xmr::PublicKey)
+        -> Result<(usize, u64)> {
+        let derivation = keys::derivation(view, tx_pubkey);
+        for (i, output) in self.outputs.iter().enumerate() {
+            if keys::derive_output_key(&derivation, i as u64, spend) != output.key {
+                continue;
+            }
+            if self.rct_type == RCT_TYPE_NULL {
+                return Ok((i, output.amount));
+            }
+            let shared = keys::derivation_to_scalar(&derivation, i as u64);
+            let (mask, amount) = match self.ecdh_info.get(i).ok_or(Error::InvalidMoneroTransaction)? {
+                EcdhInfo::Compact { amount } => {
+                    (keys::commitment_mask(&shared), keys::decode_amount(&shared, amount))
+                },
+                EcdhInfo::Standard { mask, amount } => {
+                    let mask = xmr::PrivateKey::from_bytes_mod_order(*mask)
+                        - keys::hash_to_scalar(shared.as_bytes());
+                    let amount = xmr::PrivateKey::from_bytes_mod_order(*amount)
+                        - keys::hash_to_scalar(keys::hash_to_scalar(shared.as_bytes()).as_bytes());
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(&amount.as_bytes()[..8]);
+                    (mask, u64::from_le_bytes(bytes))
+                },
+            };
+            let commitment = self.out_pk.get(i).ok_or(Error::InvalidMoneroTransaction)?;
+            if &keys::commit(&mask, amount)? != commitment {
+                return Err(Error::InvalidAmountCommitment);
+            }
+            return Ok((i, amount));
+        }
+        Err(Error::XmrOutputNotFound)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LockTx {
+    pub(crate) tx_hex: Option<String>,
+    pub(crate) spend: Option<xmr::PublicKey>,
+    pub(crate) view: Option<xmr::PublicKey>,
+    pub(crate) amount: Piconero,
+}
+
+impl LockTx {
+    pub fn new() -> LockTx {
+        LockTx::default()
+    }
+
+    pub fn from_hex(hex: String) -> LockTx {
+        LockTx { tx_hex: Some(hex), spend: None, view: None, amount: Piconero(0) }
+    }
+
+    pub fn to_hex(&self) -> Option<String> {
+        self.tx_hex.clone()
+    }
+
+    pub fn to_transaction(&self) -> Option<MoneroTransaction> {
+        let bytes = hex::decode(self.tx_hex.as_ref()?).ok()?;
+        MoneroTransaction::deserialize(&bytes).ok()
+    }
+}
+
+/// Create the lock transaction to the shared address `(X, a·G)`
+pub struct New<'a> {
+    pub(crate) spend: &'a xmr::PublicKey,
+    pub(crate) view: &'a xmr::PublicKey,
+    pub(crate) amount: Piconero,
+}
+
+impl<'a> Builder<New<'a>> for LockTx {
+    type Ret = ();
+
+    fn build(&mut self, params: New) -> Result<()> {
+        self.spend = Some(*params.spend);
+        self.view = Some(*params.view);
+        self.amount = params.amount;
+        self.tx_hex = None;
+        Ok(())
+    }
+}
+
+/// Sign the lock transaction with a wallet
+pub struct Sign<'a, W: Wallet> {
+    pub(crate) wallet: &'a W,
+}
+
+impl<'a, W> Builder<Sign<'a, W>> for LockTx where W: Wallet {
+    type Ret = SignedTransfer;
+
+    fn build(&mut self, params: Sign<W>) -> Result<SignedTransfer> {
+        let spend = self.spend.as_ref().ok_or(Error::MissingValue("spend key"))?;
+        let view = self.view.as_ref().ok_or(Error::MissingValue("view key"))?;
+        let signed = params.wallet.transfer(spend, view, self.amount.as_u64())?;
+        self.tx_hex = Some(signed.tx_hex.clone());
+        Ok(signed)
+    }
+}
+
+/// Submit a signed transaction to the Monero network, it must be the transaction signed by the
+/// builder
+pub struct Broadcast<'a, B: MoneroBackend> {
+    pub(crate) backend: &'a B,
+    pub(crate) signed: &'a SignedTransfer,
+}
+
+impl<'a, B> Builder<Broadcast<'a, B>> for LockTx where B: MoneroBackend {
+    type Ret = ();
+
+    fn build(&mut self, params: Broadcast<B>) -> Result<()> {
+        let tx_hex = self.to_hex().ok_or(Error::TransactionNotComplete(TxKind::XmrLock))?;
+        if params.signed.tx_hex != tx_hex {
+            return Err(Error::TransactionMismatch(TxKind::XmrLock));
+        }
+        params.backend.submit(params.signed)
+    }
+}
+
+/// Verify the lock transaction pays at least `amount` to the shared address
+/// with spendable outputs, the transaction public key is read from the extra
+/// field
+pub struct VerifyLock<'a> {
+    pub(crate) view: &'a xmr::PrivateKey,
+    pub(crate) spend: &'a xmr::PublicKey,
+    pub(crate) amount: Piconero,
+}
+
+impl<'a> Validator<VerifyLock<'a>> for LockTx {
+    fn validate(&self, params: VerifyLock) -> Result<()> {
+        let xtx = self.to_transaction().ok_or(Error::InvalidMoneroTransaction)?;
+        if xtx.unlock_time != 0 {
+            return Err(Error::InvalidUnlockTime { tx: TxKind::XmrLock, unlock_time: xtx.unlock_time });
+        }
+        let tx_pubkey = xtx.tx_pubkey().ok_or(Error::InvalidMoneroTransaction)?;
+        let (_, amount) = xtx.scan(params.view, &tx_pubkey, params.spend)?;
+        match Piconero(amount) >= params.amount {
+            true => Ok(()),
+            false => Err(Error::InsufficientAmount {
+                tx: TxKind::XmrLock,
+                expected: params.amount.as_u64(),
+                found: amount,
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SweepTx {
+    pub(crate) tx_hex: Option<String>,
+    pub(crate) spend: Option<xmr::PrivateKey>,
+    pub(crate) view: Option<xmr::PrivateKey>,
+    pub(crate) dest_spend: Option<xmr::PublicKey>,
+    pub(crate) dest_view: Option<xmr::PublicKey>,
+}
+
+impl SweepTx {
+    pub fn new() -> SweepTx {
+        SweepTx::default()
+    }
+
+    pub fn to_hex(&self) -> Option<String> {
+        self.tx_hex.clone()
+    }
+}
+
+/// Create the transaction sweeping the shared address with the full keys `(x, a)`
+pub struct NewSweep<'a> {
+    pub(crate) spend: &'a xmr::PrivateKey,
+    pub(crate) view: &'a xmr::PrivateKey,
+    pub(crate) dest_spend: &'a xmr::PublicKey,
+    pub(crate) dest_view: &'a xmr::PublicKey,
+}
+
+impl<'a> Builder<NewSweep<'a>> for SweepTx {
+    type Ret = ();
+
+    fn build(&mut self, params: NewSweep) -> Result<()> {
+        self.spend = Some(*params.spend);
+        self.view = Some(*params.view);
+        self.dest_spend = Some(*params.dest_spend);
+        self.dest_view = Some(*params.dest_view);
+        self.tx_hex = None;
+        Ok(())
+    }
+}
+
+impl<'a, W> Builder<Sign<'a, W>> for SweepTx where W: Wallet {
+    type Ret = SignedTransfer;
+
+    fn build(&mut self, params: Sign<W>) -> Result<SignedTransfer> {
+        let signed = params.wallet.sweep(
+            self.spend.as_ref().ok_or(Error::MissingValue("spend key"))?,
+            self.view.as_ref().ok_or(Error::MissingValue("view key"))?,
+            self.dest_spend.as_ref().ok_or(Error::MissingValue("destination spend key"))?,
+            self.dest_view.as_ref().ok_or(Error::MissingValue("destination view key"))?,
+        )?;
+        self.tx_hex = Some(signed.tx_hex.clone());
+        Ok(signed)
+    }
+}
+
+impl<'a, B> Builder<Broadcast<'a, B>> for SweepTx where B: MoneroBackend {
+    type Ret = ();
+
+    fn build(&mut self, params: Broadcast<B>) -> Result<()> {
+        let tx_hex = self.to_hex().ok_or(Error::TransactionNotComplete(TxKind::XmrSweep))?;
+        if params.signed.tx_hex != tx_hex {
+            return Err(Error::TransactionMismatch(TxKind::XmrSweep));
+        }
+        params.backend.submit(params.signed)
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::types::Error;
+    use crate::types::amount::Piconero;
+    use crate::types::xmr::{self, keys};
+    use crate::transactions::{Builder, Validator};
+    use crate::backend::xmr::FakeMonero;
+    use super::{Wallet, LockTx, New, Sign, Broadcast, VerifyLock};
+
+    #[test]
+    fn lock_round_trip() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let chain = FakeMonero::new();
+        let x = xmr::PrivateKey::random(&mut rng);
+        let a = xmr::PrivateKey::random(&mut rng);
+        let (X, A) = (keys::public_key(&x), keys::public_key(&a));
+
+        let mut lock = LockTx::new();
+        lock.build(New { spend: &X, view: &A, amount: Piconero(1_000_000_000) }).unwrap();
+        let signed = lock.build(Sign { wallet: &chain }).unwrap();
+        // Only the transaction signed by the builder is submitted
+        let other = chain.transfer(&X, &A, 1_000_000_000).unwrap();
+        match lock.build(Broadcast { backend: &chain, signed: &other }) {
+            Err(Error::TransactionMismatch(_)) => (),
+            _ => panic!("another transaction is submitted"),
+        }
+        lock.build(Broadcast { backend: &chain, signed: &signed }).unwrap();
+
+        let xtx = lock.to_transaction().unwrap();
+        assert_eq!(xtx.tx_pubkey(), Some(signed.tx_pubkey));
+        assert_eq!(xtx.scan(&a, &signed.tx_pubkey, &X).unwrap(), (0, 1_000_000_000));
+
+        let received = LockTx::from_hex(signed.tx_hex.clone());
+        assert!(received.validate(VerifyLock { view: &a, spend: &X, amount: Piconero(1_000_000_000) }).is_ok());
+        match received.validate(VerifyLock { view: &a, spend: &X, amount: Piconero(1_000_000_001) }) {
+            Err(Error::InsufficientAmount { .. }) => (),
+            _ => panic!("lock amount is below the expected amount"),
+        }
+        // The output cannot be found without the shared view key
+        let b = xmr::PrivateKey::random(&mut rng);
+        match received.validate(VerifyLock { view: &b, spend: &X, amount: Piconero(1_000_000_000) }) {
+            Err(Error::XmrOutputNotFound) => (),
+            _ => panic!("output is not sent to the view key"),
+        }
+    }
+
+    #[test]
+    fn reject_locked_outputs() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let chain = FakeMonero::new();
+        let x = xmr::PrivateKey::random(&mut rng);
+        let a = xmr::PrivateKey::random(&mut rng);
+        let (X, A) = (keys::public_key(&x), keys::public_key(&a));
+
+        let signed = chain.transfer(&X, &A, 1_000_000_000).unwrap();
+        let mut tx = hex::decode(&signed.tx_hex).unwrap();
+        // Version is followed by the unlock time varint, lock the outputs until height 100
+        assert_eq!(tx[1], 0);
+        tx[1] = 100;
+
+        let xtx = LockTx::from_hex(hex::encode(tx));
+        match xtx.validate(VerifyLock { view: &a, spend: &X, amount: Piconero(1_000_000_000) }) {
+            Err(Error::InvalidUnlockTime { unlock_time: 100, .. }) => (),
+            _ => panic!("lock outputs must be spendable"),
+        }
+    }
+}
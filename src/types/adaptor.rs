@@ -0,0 +1,108 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Phase parameters of the adaptor signature swap variant. The Bitcoin scripts
+//! are plain 2-of-2 multisig with timelocks and the Monero key shares are only
+//! revealed through the completion of adaptor signatures
+
+use bitcoin::Address;
+
+use super::{btc, xmr, wire};
+use super::btc::adaptor::EncryptedSignature;
+use crate::transactions;
+use crate::backend;
+
+pub struct CreateTransactions {
+    /// Candidate coins to fund the swap
+    pub utxos: Vec<btc::Utxo>,
+    /// Address receiving the change of the funding transaction
+    pub change_address: Address,
+    /// Buyer's address receiving the refunded bitcoins
+    pub refund_address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InitialTransactions {
+    pub transactions: btc::InitialTransactions,
+    /// Unsigned spend refund transaction the seller encrypt-signs
+    #[serde(with = "wire::btc_tx")]
+    pub spend_refund: String,
+}
+
+pub struct VerifyTransactions {
+    pub transactions: InitialTransactions,
+    /// Outputs spent by `btx_1` as found on chain, in the order of its inputs
+    pub spent_outputs: Vec<bitcoin::TxOut>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifiedTransactions {
+    #[serde(with = "wire::btc_tx")]
+    pub btx_2_signed: String,
+    /// Seller's signature on the spend refund encrypted under the buyer's share `X_1`
+    pub spend_refund_sig: EncryptedSignature,
+}
+
+pub struct VerifyXmrLock<'a, B: backend::xmr::MoneroBackend> {
+    pub xtx: xmr::LockedXmr,
+    /// Backend following the lock transaction on the Monero chain
+    pub backend: &'a B,
+    pub min_confirmations: u64,
+    pub funding: transactions::btc::funding::FundingTx,
+    /// Seller's address receiving the bitcoins
+    pub address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+}
+
+pub struct Swap {
+    pub funding: transactions::btc::funding::FundingTx,
+    pub address: Address,
+    /// Buyer's signature on the buy transaction encrypted under the seller's share `X_0`
+    pub sig_b: EncryptedSignature,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+}
+
+pub struct ReleaseXmr<'a, W: transactions::xmr::Wallet, B: backend::xmr::MoneroBackend> {
+    pub buy: transactions::btc::buy::BuyTx,
+    pub sig_b: EncryptedSignature,
+    /// Standard Monero address receiving the swept funds
+    pub dest: xmr::address::Address,
+    pub wallet: &'a W,
+    /// Backend relaying the sweep transaction
+    pub backend: &'a B,
+}
+
+pub struct SpendRefund<'a> {
+    pub btx_2_signed: &'a transactions::btc::refund::RefundTx<'a>,
+    pub spend_refund: String,
+    pub sig_a: EncryptedSignature,
+}
+
+pub struct RecoverXmrAfterRefund<'a, W: transactions::xmr::Wallet, B: backend::xmr::MoneroBackend> {
+    pub spend_refund: transactions::btc::spend_refund::SpendRefundTx,
+    pub sig_a: EncryptedSignature,
+    /// Standard Monero address receiving the swept funds
+    pub dest: xmr::address::Address,
+    pub wallet: &'a W,
+    /// Backend relaying the sweep transaction
+    pub backend: &'a B,
+}
@@ -0,0 +1,95 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+//! Amounts in the smallest unit of each chain, arithmetic is checked and never wraps
+
+use super::constants::DUST_LIMIT;
+
+use std::fmt;
+
+/// Bitcoin amount in satoshi
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Satoshi(pub u64);
+
+impl Satoshi {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Satoshi) -> Option<Satoshi> {
+        self.0.checked_add(other.0).map(Satoshi)
+    }
+
+    pub fn checked_sub(self, other: Satoshi) -> Option<Satoshi> {
+        self.0.checked_sub(other.0).map(Satoshi)
+    }
+
+    /// Output of this value would not be relayed, see `DUST_LIMIT`
+    pub fn is_dust(self) -> bool {
+        self.0 < DUST_LIMIT
+    }
+}
+
+impl fmt::Display for Satoshi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sat", self.0)
+    }
+}
+
+/// Monero amount in piconero, 10^-12 XMR
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Piconero(pub u64);
+
+impl Piconero {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Piconero) -> Option<Piconero> {
+        self.0.checked_add(other.0).map(Piconero)
+    }
+
+    pub fn checked_sub(self, other: Piconero) -> Option<Piconero> {
+        self.0.checked_sub(other.0).map(Piconero)
+    }
+}
+
+impl fmt::Display for Piconero {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} piconero", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Satoshi, Piconero};
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(Some(Satoshi(5)), Satoshi(10).checked_sub(Satoshi(5)));
+        assert_eq!(None, Satoshi(4).checked_sub(Satoshi(5)));
+        assert_eq!(None, Satoshi(std::u64::MAX).checked_add(Satoshi(1)));
+        assert_eq!(None, Piconero(0).checked_sub(Piconero(1)));
+        assert_eq!(Some(Piconero(3)), Piconero(1).checked_add(Piconero(2)));
+    }
+
+    #[test]
+    fn dust() {
+        assert!(Satoshi(293).is_dust());
+        assert!(!Satoshi(294).is_dust());
+    }
+}
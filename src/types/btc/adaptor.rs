@@ -0,0 +1,185 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! ECDSA adaptor signatures: a signature encrypted under an adaptor point `Y = y·G`
+//! can be decrypted only with `y`, and publishing the decrypted signature reveals
+//! `y` to the holder of the encrypted signature
+
+use super::{PrivateKey, PublicKey};
+use crate::types::{Result, Error, wire};
+use crate::types::dleq::{secp_scalar, secp_mul, secp_neg, secp_add};
+
+use bitcoin_hashes::{Hash, sha256};
+use secp256k1::{Message, Signature};
+use secp256k1::curve::Scalar;
+
+/// Signature encrypted under an adaptor point
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedSignature {
+    /// `R = k·Y`
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) r: PublicKey,
+    /// `R' = k·G`
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) r_prime: PublicKey,
+    /// `s' = k^-1 (m + r·b)`
+    #[serde(with = "wire::bytes32")]
+    pub(crate) s: [u8; 32],
+    /// Proof that `R` and `R'` share the same nonce `k`
+    #[serde(with = "wire::secp_scalar_pair")]
+    pub(crate) proof: (Scalar, Scalar),
+}
+
+fn generator() -> Result<PublicKey> {
+    Ok(PublicKey::from_secret_key(&PrivateKey::parse(&Scalar::from_int(1).b32())?))
+}
+
+fn base_mul(k: &Scalar) -> Result<PublicKey> {
+    Ok(PublicKey::from_secret_key(&PrivateKey::parse(&k.b32())?))
+}
+
+/// The `x` coordinate of `R` modulo the group order
+fn x_coordinate(point: &PublicKey) -> Scalar {
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&point.serialize_compressed()[1..]);
+    secp_scalar(&x)
+}
+
+/// Deterministic nonce derived from the secret key, the message and the adaptor point
+fn nonce(tag: &[u8], privkey: &PrivateKey, msg: &Message, adaptor: &PublicKey) -> Scalar {
+    let mut counter = 0u32;
+    loop {
+        let mut data = tag.to_vec();
+        data.extend_from_slice(&privkey.serialize());
+        data.extend_from_slice(&msg.serialize());
+        data.extend_from_slice(&adaptor.serialize_compressed());
+        data.extend_from_slice(&counter.to_le_bytes());
+        let hash = sha256::Hash::hash(&data);
+        let mut k = [0u8; 32];
+        k.copy_from_slice(&hash[..]);
+        if PrivateKey::parse(&k).is_ok() {
+            return secp_scalar(&k);
+        }
+        counter += 1;
+    }
+}
+
+fn dleq_challenge(points: &[&PublicKey]) -> Scalar {
+    let mut data = b"monero-swap-lib adaptor".to_vec();
+    for point in points {
+        data.extend_from_slice(&point.serialize_compressed());
+    }
+    let hash = sha256::Hash::hash(&data);
+    let mut c = [0u8; 32];
+    c.copy_from_slice(&hash[..]);
+    secp_scalar(&c)
+}
+
+/// Encrypt a signature of `msg` with `privkey` under the adaptor point
+pub fn encrypted_sign(msg: &Message, privkey: &PrivateKey, adaptor: &PublicKey) -> Result<EncryptedSignature> {
+    let g = generator()?;
+    let k = nonce(b"monero-swap-lib adaptor nonce", privkey, msg, adaptor);
+    let r = secp_mul(adaptor, &k)?;
+    let r_prime = base_mul(&k)?;
+
+    let b = secp_scalar(&privkey.serialize());
+    let s = &k.inv() * &(&msg.0 + &(&x_coordinate(&r) * &b));
+
+    // Chaum-Pedersen proof of log_G(R') = log_Y(R)
+    let t = nonce(b"monero-swap-lib adaptor proof", privkey, msg, adaptor);
+    let c = dleq_challenge(&[&g, adaptor, &r_prime, &r, &base_mul(&t)?, &secp_mul(adaptor, &t)?]);
+    let z = &t + &(&c * &k);
+
+    Ok(EncryptedSignature {
+        r,
+        r_prime,
+        s: s.b32(),
+        proof: (c, z),
+    })
+}
+
+/// Verify an encrypted signature of `msg` for `pubkey` under the adaptor point
+pub fn encrypted_verify(msg: &Message, pubkey: &PublicKey, adaptor: &PublicKey, sig: &EncryptedSignature)
+    -> Result<()> {
+    let g = generator()?;
+    let (c, z) = &sig.proof;
+    let a = secp_add(&base_mul(z)?, &secp_neg(&secp_mul(&sig.r_prime, c)?)?)?;
+    let b = secp_add(&secp_mul(adaptor, z)?, &secp_neg(&secp_mul(&sig.r, c)?)?)?;
+    if &dleq_challenge(&[&g, adaptor, &sig.r_prime, &sig.r, &a, &b]) != c {
+        return Err(Error::SignatureCheck("adaptor dleq proof"));
+    }
+
+    // s'·R' = m·G + r·B
+    let lhs = secp_mul(&sig.r_prime, &secp_scalar(&sig.s))?;
+    let rhs = secp_add(&base_mul(&msg.0)?, &secp_mul(pubkey, &x_coordinate(&sig.r))?)?;
+    match lhs == rhs {
+        true => Ok(()),
+        false => Err(Error::SignatureCheck("encrypted signature")),
+    }
+}
+
+/// Decrypt an encrypted signature with the adaptor secret `y`, `s = s'·y^-1`
+pub fn decrypt(sig: &EncryptedSignature, y: &PrivateKey) -> Signature {
+    let y = secp_scalar(&y.serialize());
+    let mut sig = Signature {
+        r: x_coordinate(&sig.r),
+        s: &secp_scalar(&sig.s) * &y.inv(),
+    };
+    sig.normalize_s();
+    sig
+}
+
+/// Recover the adaptor secret `y = ±s'·s^-1` from an encrypted signature and its
+/// decrypted version
+pub fn recover(sig: &EncryptedSignature, decrypted: &Signature, adaptor: &PublicKey) -> Result<PrivateKey> {
+    if decrypted.r != x_coordinate(&sig.r) {
+        return Err(Error::SignatureCheck("decrypted signature nonce"));
+    }
+    let y = &secp_scalar(&sig.s) * &decrypted.s.inv();
+    for candidate in &[y.clone(), y.neg()] {
+        let candidate = PrivateKey::parse(&candidate.b32())?;
+        if &PublicKey::from_secret_key(&candidate) == adaptor {
+            return Ok(candidate);
+        }
+    }
+    Err(Error::InvalidKeyShare)
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use secp256k1::Message;
+    use crate::types::btc::{PrivateKey, PublicKey};
+    use super::{encrypted_sign, encrypted_verify, decrypt, recover};
+
+    #[test]
+    fn encrypt_decrypt_recover() {
+        let b = PrivateKey::parse(&[3; 32]).unwrap();
+        let y = PrivateKey::parse(&[5; 32]).unwrap();
+        let B = PublicKey::from_secret_key(&b);
+        let Y = PublicKey::from_secret_key(&y);
+        let msg = Message::parse(&[7; 32]);
+
+        let enc = encrypted_sign(&msg, &b, &Y).unwrap();
+        assert!(encrypted_verify(&msg, &B, &Y, &enc).is_ok());
+        assert!(encrypted_verify(&msg, &Y, &Y, &enc).is_err());
+
+        let sig = decrypt(&enc, &y);
+        assert!(secp256k1::verify(&msg, &sig, &B));
+        assert_eq!(y, recover(&enc, &sig, &Y).unwrap());
+    }
+}
@@ -0,0 +1,194 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Coin selection for the funding transaction, branch-and-bound search for a changeless
+//! solution with a largest-first fallback creating a change output
+
+use crate::types::{Result, Error};
+use crate::types::constants::DUST_LIMIT;
+use crate::types::btc::{Utxo, fee};
+
+/// Maximum number of explored branches before falling back
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Coins selected to fund a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Indexes of the selected coins in the candidate list
+    pub indexes: Vec<usize>,
+    /// Fee paid by the transaction
+    pub fee: u64,
+    /// Change amount, none if no change output is created
+    pub change: Option<u64>,
+}
+
+/// Depth-first search of a subset of effective values in `[target, upper]` minimizing the
+/// excess
+struct BranchAndBound<'a> {
+    values: &'a [u64],
+    target: u64,
+    upper: u64,
+    tries: usize,
+    best: Option<(Vec<usize>, u64)>,
+}
+
+impl<'a> BranchAndBound<'a> {
+    fn explore(&mut self, index: usize, selected: &mut Vec<usize>, value: u64, available: u64) {
+        if self.tries == 0 || value > self.upper || value + available < self.target {
+            return;
+        }
+        self.tries -= 1;
+        if value >= self.target {
+            let excess = value - self.target;
+            if self.best.as_ref().map_or(true, |(_, best)| excess < *best) {
+                self.best = Some((selected.clone(), excess));
+            }
+            return;
+        }
+        if index == self.values.len() {
+            return;
+        }
+        let current = self.values[index];
+        // Explore the inclusion branch first
+        selected.push(index);
+        self.explore(index + 1, selected, value + current, available - current);
+        selected.pop();
+        self.explore(index + 1, selected, value, available - current);
+    }
+}
+
+/// Fee of a transaction spending `inputs` P2WPKH coins to the given outputs
+fn tx_fee(inputs: usize, outputs: &[usize], feerate: u64) -> Result<u64> {
+    let witnesses = vec![fee::p2wpkh_witness(); inputs];
+    fee::fee(fee::weight(&witnesses, outputs), feerate)
+}
+
+/// Sum of amounts, fails with insufficient funds on overflow
+fn checked_sum<I: Iterator<Item = u64>>(amounts: I) -> Result<u64> {
+    amounts.fold(Some(0u64), |sum, amount| sum.and_then(|sum| sum.checked_add(amount)))
+        .ok_or(Error::InsufficientFunds)
+}
+
+/// Select P2WPKH coins paying `target` to outputs of the given script sizes, a change output
+/// of `change_script` size is added when no changeless solution exists and the change is
+/// above the dust limit
+pub fn select_coins(utxos: &[Utxo], target: u64, outputs: &[usize], change_script: usize, feerate: u64) -> Result<Selection> {
+    let mut with_change = outputs.to_vec();
+    with_change.push(change_script);
+
+    // Marginal costs of one input and of the change output
+    let input_fee = tx_fee(1, outputs, feerate)? - tx_fee(0, outputs, feerate)?;
+    let change_fee = tx_fee(0, &with_change, feerate)? - tx_fee(0, outputs, feerate)?;
+    // Creating a change now and spending it later
+    let cost_of_change = change_fee + input_fee;
+
+    let mut candidates: Vec<(usize, u64)> = utxos.iter()
+        .enumerate()
+        .filter(|(_, utxo)| utxo.amount > input_fee)
+        .map(|(index, utxo)| (index, utxo.amount - input_fee))
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let values: Vec<u64> = candidates.iter().map(|(_, value)| *value).collect();
+    let available = checked_sum(values.iter().cloned())?;
+    let bnb_target = target.checked_add(tx_fee(0, outputs, feerate)?).ok_or(Error::InsufficientFunds)?;
+    let mut bnb = BranchAndBound {
+        values: &values,
+        target: bnb_target,
+        upper: bnb_target.checked_add(cost_of_change).ok_or(Error::InsufficientFunds)?,
+        tries: BNB_MAX_TRIES,
+        best: None,
+    };
+    bnb.explore(0, &mut vec![], 0, available);
+
+    if let Some((selected, _)) = bnb.best {
+        let indexes: Vec<usize> = selected.iter().map(|i| candidates[*i].0).collect();
+        let total = checked_sum(indexes.iter().map(|i| utxos[*i].amount))?;
+        // Effective values round the input fees, check the fee of the whole transaction
+        let fee = tx_fee(indexes.len(), outputs, feerate)?;
+        if total >= target.checked_add(fee).ok_or(Error::InsufficientFunds)? {
+            // The excess below the cost of change is left to the miners
+            return Ok(Selection { indexes, fee: total - target, change: None });
+        }
+    }
+
+    // Fallback on largest first with a change output
+    let mut indexes = vec![];
+    let mut total = 0u64;
+    for (index, _) in candidates.iter() {
+        indexes.push(*index);
+        total = total.checked_add(utxos[*index].amount).ok_or(Error::InsufficientFunds)?;
+        let fee = tx_fee(indexes.len(), &with_change, feerate)?;
+        let required = target.checked_add(fee)
+            .and_then(|amount| amount.checked_add(DUST_LIMIT))
+            .ok_or(Error::InsufficientFunds)?;
+        if total >= required {
+            return Ok(Selection { indexes, fee, change: Some(total - target - fee) });
+        }
+        let fee = tx_fee(indexes.len(), outputs, feerate)?;
+        if total >= target.checked_add(fee).ok_or(Error::InsufficientFunds)? {
+            return Ok(Selection { indexes, fee: total - target, change: None });
+        }
+    }
+
+    Err(Error::InsufficientFunds)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{Hash, sha256d};
+    use crate::types::Error;
+    use crate::types::btc::Utxo;
+    use super::select_coins;
+
+    fn utxo(vout: u32, amount: u64) -> Utxo {
+        Utxo { txid: sha256d::Hash::hash(&[0u8]), vout, amount }
+    }
+
+    #[test]
+    fn changeless_solution() {
+        let utxos = vec![utxo(0, 100_000), utxo(1, 50_000), utxo(2, 30_148)];
+        // One P2WSH output, two inputs fee at 1 sat/vB is 190 sat
+        let selection = select_coins(&utxos, 79_958, &[34], 22, 1).unwrap();
+        assert_eq!(None, selection.change);
+        assert_eq!(vec![1, 2], selection.indexes);
+        assert_eq!(190, selection.fee);
+    }
+
+    #[test]
+    fn fallback_with_change() {
+        let utxos = vec![utxo(0, 100_000), utxo(1, 50_000)];
+        let selection = select_coins(&utxos, 120_000, &[34], 22, 2).unwrap();
+        let change = selection.change.unwrap();
+        assert_eq!(vec![0, 1], selection.indexes);
+        assert_eq!(150_000, 120_000 + selection.fee + change);
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let utxos = vec![utxo(0, 100_000), utxo(1, 50_000)];
+        assert!(select_coins(&utxos, 150_000, &[34], 22, 1).is_err());
+    }
+
+    #[test]
+    fn overflowing_amounts() {
+        let utxos = vec![utxo(0, u64::max_value()), utxo(1, u64::max_value())];
+        match select_coins(&utxos, u64::max_value(), &[34], 22, 1) {
+            Err(Error::InsufficientFunds) => (),
+            _ => panic!("overflowing sums must be rejected"),
+        }
+    }
+}
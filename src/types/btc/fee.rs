@@ -0,0 +1,130 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Weight estimation of the swap transactions and fee computation from a feerate
+//! in satoshi per virtual byte
+
+use crate::types::{Result, Error};
+use crate::types::constants::MIN_RELAY_FEERATE;
+use crate::types::hashlock::ROUNDS;
+
+use bitcoin::blockdata::script::Script;
+
+/// Upper bound of a DER encoded signature with its sighash flag
+pub const SIG_SIZE: usize = 73;
+/// Compressed public key
+pub const PUBKEY_SIZE: usize = 33;
+/// Secret `s` and Monero key shares revealed in witnesses
+pub const SECRET_SIZE: usize = 32;
+
+fn varint_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Size of a witness stack with items of the given sizes
+pub fn witness_size(items: &[usize]) -> usize {
+    varint_len(items.len()) + items.iter().map(|item| varint_len(*item) + item).sum::<usize>()
+}
+
+/// Witness of a P2WPKH input
+pub fn p2wpkh_witness() -> usize {
+    witness_size(&[SIG_SIZE, PUBKEY_SIZE])
+}
+
+/// Witness of the swaplock buy branch revealing `s` and the preimages of the `x_0` hash locks
+pub fn swaplock_buy_witness(swaplock: &Script) -> usize {
+    let mut items = vec![SIG_SIZE, SECRET_SIZE];
+    items.extend_from_slice(&[SECRET_SIZE; ROUNDS]);
+    items.extend_from_slice(&[1, swaplock.len()]);
+    witness_size(&items)
+}
+
+/// Witness of the swaplock 2-of-2 multisig branch after `t_0`
+pub fn swaplock_multisig_witness(swaplock: &Script) -> usize {
+    witness_size(&[0, SIG_SIZE, SIG_SIZE, 0, swaplock.len()])
+}
+
+/// Witness of the refund hash lock branch revealing the preimages of the `x_1` hash locks
+pub fn refund_spend_witness(refund: &Script) -> usize {
+    let mut items = vec![SIG_SIZE];
+    items.extend_from_slice(&[SECRET_SIZE; ROUNDS]);
+    items.extend_from_slice(&[1, refund.len()]);
+    witness_size(&items)
+}
+
+/// Witness of the refund CSV branch after `t_1`
+pub fn refund_claim_witness(refund: &Script) -> usize {
+    witness_size(&[SIG_SIZE, 0, refund.len()])
+}
+
+/// Witness of the cooperative 2-of-2 branch of the adaptor scripts
+pub fn adaptor_multisig_witness(script: &Script) -> usize {
+    witness_size(&[0, SIG_SIZE, SIG_SIZE, 1, script.len()])
+}
+
+/// Weight of a segwit transaction with the given input witness sizes and output
+/// script sizes, inputs have empty script sigs
+pub fn weight(witnesses: &[usize], outputs: &[usize]) -> usize {
+    let base = 4 // version
+        + varint_len(witnesses.len())
+        + witnesses.len() * (32 + 4 + 1 + 4) // outpoint, empty script sig, sequence
+        + varint_len(outputs.len())
+        + outputs.iter().map(|script| 8 + varint_len(*script) + script).sum::<usize>()
+        + 4; // lock time
+    // Marker and flag bytes are part of the witness data
+    let witness = 2 + witnesses.iter().sum::<usize>();
+    base * 4 + witness
+}
+
+/// Virtual size from a weight, `ceil(weight / 4)`
+pub fn vsize(weight: usize) -> usize {
+    (weight + 3) / 4
+}
+
+/// Fee to pay for a transaction weight with a feerate in satoshi per virtual byte
+pub fn fee(weight: usize, feerate: u64) -> Result<u64> {
+    if feerate < MIN_RELAY_FEERATE {
+        return Err(Error::FeerateTooLow);
+    }
+    Ok(vsize(weight) as u64 * feerate)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Script;
+    use super::{weight, vsize, fee, p2wpkh_witness, swaplock_buy_witness};
+
+    #[test]
+    fn p2wpkh_to_p2wsh_vsize() {
+        // 1 P2WPKH input to 1 P2WSH output
+        let weight = weight(&[p2wpkh_witness()], &[34]);
+        assert_eq!(487, weight);
+        assert_eq!(122, vsize(weight));
+    }
+
+    #[test]
+    fn swaplock_buy_fee() {
+        let swaplock = Script::from(vec![0u8; 179]);
+        let weight = weight(&[swaplock_buy_witness(&swaplock)], &[22]);
+        assert_eq!(vsize(weight) as u64 * 3, fee(weight, 3).unwrap());
+        assert!(fee(weight, 0).is_err());
+    }
+}
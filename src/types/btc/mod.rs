@@ -14,7 +14,9 @@
 //
 
 use crate::transactions;
-use crate::types::{xmr, RelativeLocktime};
+use crate::backend;
+use crate::types::{xmr, dleq, wire, common, RelativeLocktime};
+use crate::types::hashlock::HashLock;
 
 use secp256k1::Signature;
 use bitcoin_hashes::sha256d;
@@ -23,12 +25,16 @@ use curve25519_dalek::constants;
 use wasm_bindgen::prelude::*;
 
 pub mod scripts;
+pub mod fee;
+pub mod coinselect;
+pub mod psbt;
+pub mod adaptor;
 
 pub type PrivateKey = secp256k1::SecretKey;
 
 pub type PublicKey = secp256k1::PublicKey;
 
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Utxo {
     pub txid: sha256d::Hash,
     pub vout: u32,
@@ -36,62 +42,135 @@ pub struct Utxo {
 }
 
 pub struct Input<'a> {
+    pub txid: sha256d::Hash,
+    pub vout: u32,
     pub amount: u64,
     pub address: &'a Address,
     pub privkey: &'a PrivateKey,
 }
 
-pub struct CreateTransactions {
+/// Coin controlled by an external wallet, spent through a PSBT
+pub struct ExternalInput {
     pub utxo: Utxo,
+    pub address: Address,
+}
+
+pub struct CreateTransactions {
+    /// Candidate coins to fund the swap
+    pub utxos: Vec<Utxo>,
+    /// Address receiving the change of the funding transaction
+    pub change_address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct InitialTransactions {
+    #[serde(with = "wire::btc_tx")]
     pub btx_1: String,
+    #[serde(with = "wire::btc_tx")]
     pub btx_2: String,
+    #[serde(with = "wire::secp_signature")]
     pub sig_b: Signature,
 }
 
 pub struct LockFunds<'a> {
-    pub input: Input<'a>,
+    pub inputs: Vec<Input<'a>>,
+    pub btx_1: String,
+    /// Refund created by the buyer, the refund signed by the seller must be the same
+    pub btx_2: String,
+    pub btx_2_signed: String,
+}
+
+/// Export the funding transaction as a PSBT for an external wallet
+pub struct ExportFunding {
+    pub btx_1: String,
+    pub inputs: Vec<ExternalInput>,
+}
+
+/// Lock the funds with a funding transaction signed by an external wallet
+pub struct LockFundsPsbt {
+    /// Base64 PSBT signed by the external wallet
+    pub psbt: String,
     pub btx_1: String,
+    /// Refund created by the buyer, the refund signed by the seller must be the same
+    pub btx_2: String,
     pub btx_2_signed: String,
 }
 
 pub struct SpendRefund<'a> {
     pub btx_2_signed: &'a transactions::btc::refund::RefundTx<'a>,
     pub address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
 }
 
-pub struct VerifyXmrLock;
+pub struct VerifyXmrLock<'a, B: backend::xmr::MoneroBackend> {
+    pub xtx: xmr::LockedXmr,
+    /// Backend following the lock transaction on the Monero chain
+    pub backend: &'a B,
+    pub min_confirmations: u64,
+}
 
-pub struct ReleaseXmr;
+pub struct ReleaseXmr<'a, W: transactions::xmr::Wallet, B: backend::xmr::MoneroBackend> {
+    pub buy: transactions::btc::buy::BuyTx,
+    /// Standard Monero address receiving the swept funds
+    pub dest: xmr::address::Address,
+    pub wallet: &'a W,
+    /// Backend relaying the sweep transaction
+    pub backend: &'a B,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 #[allow(non_snake_case)]
 pub struct SetupParams {
+    #[serde(with = "wire::ed25519_scalar")]
     pub(crate) a_1: xmr::PrivateKey,
+    #[serde(with = "wire::ed25519_scalar")]
     pub(crate) x_1: xmr::PrivateKey,
+    #[serde(with = "wire::secp_privkey")]
     pub(crate) b_b: PrivateKey,
+    #[serde(with = "wire::secp_pubkey")]
     pub(crate) B_b: PublicKey,
+    #[serde(with = "wire::bytes32")]
     pub(crate) s: [u8; 32],
-    pub(crate) h_1: [u8; 32],
+    /// Hash locks of the buyer key share
+    pub(crate) h_1: HashLock,
+    #[serde(with = "wire::bytes32")]
     pub(crate) h_2: [u8; 32],
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_1_secp: PublicKey,
+    pub(crate) proof_1: dleq::Proof,
     pub(crate) t_0: RelativeLocktime,
     pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
 }
 
 #[wasm_bindgen(js_name = __wbg_btcexportedsetupparams_free)]
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 #[allow(non_snake_case)]
 pub struct ExportedSetupParams {
+    #[serde(with = "wire::ed25519_scalar")]
     pub(crate) a_1: xmr::PrivateKey,
+    #[serde(with = "wire::ed25519_point")]
     pub(crate) X_1: xmr::PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
     pub(crate) B_b: PublicKey,
-    pub(crate) h_1: [u8; 32],
+    /// Hash locks of the buyer key share
+    pub(crate) h_1: HashLock,
+    #[serde(with = "wire::bytes32")]
     pub(crate) h_2: [u8; 32],
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_1_secp: PublicKey,
+    pub(crate) proof_1: dleq::Proof,
     pub(crate) t_0: RelativeLocktime,
     pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
 }
 
 impl From<&SetupParams> for ExportedSetupParams {
@@ -105,8 +184,12 @@ impl From<&SetupParams> for ExportedSetupParams {
             s: _,
             h_1,
             h_2,
+            X_1_secp,
+            proof_1,
             t_0,
             t_1,
+            networks,
+            amounts,
         } = params;
 
         let X_1 = x_1 * &constants::ED25519_BASEPOINT_TABLE;
@@ -115,33 +198,58 @@ impl From<&SetupParams> for ExportedSetupParams {
             a_1: *a_1,
             X_1,
             B_b: B_b.clone(),
-            h_1: *h_1,
+            h_1: h_1.clone(),
             h_2: *h_2,
+            X_1_secp: X_1_secp.clone(),
+            proof_1: proof_1.clone(),
             t_0: *t_0,
             t_1: *t_1,
+            networks: *networks,
+            amounts: *amounts,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 #[allow(non_snake_case)]
 pub struct Setup {
+    #[serde(with = "wire::ed25519_scalar")]
     pub(crate) a: xmr::PrivateKey,
+    #[serde(with = "wire::ed25519_scalar")]
     pub(crate) x_1: xmr::PrivateKey,
+    #[serde(with = "wire::ed25519_point")]
     pub(crate) X: xmr::PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_0_secp: PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
     pub(crate) B_a: PublicKey,
+    #[serde(with = "wire::secp_privkey")]
     pub(crate) b_b: PrivateKey,
+    #[serde(with = "wire::secp_pubkey")]
     pub(crate) B_b: PublicKey,
+    #[serde(with = "wire::bytes32")]
     pub(crate) s: [u8; 32],
-    pub(crate) h_0: [u8; 32],
-    pub(crate) h_1: [u8; 32],
+    /// Hash locks of the seller key share
+    pub(crate) h_0: HashLock,
+    /// Hash locks of the buyer key share
+    pub(crate) h_1: HashLock,
+    #[serde(with = "wire::bytes32")]
     pub(crate) h_2: [u8; 32],
     pub(crate) t_0: RelativeLocktime,
     pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
 }
 
 impl Setup {
     pub fn get_s(&self) -> [u8; 32] {
         self.s
     }
+
+    /// Address of the shared wallet receiving the locked Monero, spend key `X` and view key
+    /// `a·G`, to cross-check with the counterparty
+    pub fn lock_address(&self) -> xmr::address::Address {
+        xmr::address::Address::standard(self.networks.xmr, &self.X, &xmr::keys::public_key(&self.a))
+    }
 }
@@ -0,0 +1,452 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Minimal BIP174 partially signed Bitcoin transactions, only the fields needed to let an
+//! external wallet sign the P2WPKH inputs of the funding transaction
+
+use crate::types::{Result, Error};
+use crate::types::btc::PublicKey;
+
+use bitcoin::{Transaction, TxOut};
+use bitcoin::blockdata::script::Script;
+use bitcoin::consensus::encode::{serialize, deserialize};
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Per input data
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PsbtInput {
+    /// Output spent by the input, required to sign SegWit inputs
+    pub witness_utxo: Option<TxOut>,
+    /// Signatures with their sighash flag indexed by public key
+    pub partial_sigs: Vec<(PublicKey, Vec<u8>)>,
+    /// Sighash type the signer must use
+    pub sighash_type: Option<u32>,
+    /// Complete witness set by a finalizer
+    pub final_witness: Option<Vec<Vec<u8>>>,
+}
+
+/// Per output data
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PsbtOutput {
+    /// Witness script of a P2WSH output
+    pub witness_script: Option<Script>,
+}
+
+/// Partially signed transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psbt {
+    pub unsigned_tx: Transaction,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+}
+
+fn write_compact_size(buf: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => buf.push(n as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        },
+        0x10000..=0xffff_ffff => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&(n as u32).to_le_bytes());
+        },
+        _ => {
+            buf.push(0xff);
+            buf.extend_from_slice(&(n as u64).to_le_bytes());
+        },
+    }
+}
+
+fn write_pair(buf: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    write_compact_size(buf, key.len());
+    buf.extend_from_slice(key);
+    write_compact_size(buf, value.len());
+    buf.extend_from_slice(value);
+}
+
+fn write_witness(stack: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = vec![];
+    write_compact_size(&mut buf, stack.len());
+    for item in stack {
+        write_compact_size(&mut buf, item.len());
+        buf.extend_from_slice(item);
+    }
+    buf
+}
+
+/// Keys of a known type carry exactly `len` bytes, type included
+fn check_key(key: &[u8], len: usize) -> Result<()> {
+    if key.len() != len {
+        return Err(Error::InvalidPsbt);
+    }
+    Ok(())
+}
+
+/// Cursor over the serialized PSBT
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(Error::InvalidPsbt)?;
+        let bytes = self.data.get(self.pos..end).ok_or(Error::InvalidPsbt)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn compact_size(&mut self) -> Result<usize> {
+        let n = match self.take(1)?[0] {
+            0xfd => {
+                let mut bytes = [0u8; 2];
+                bytes.copy_from_slice(self.take(2)?);
+                u64::from(u16::from_le_bytes(bytes))
+            },
+            0xfe => {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(self.take(4)?);
+                u64::from(u32::from_le_bytes(bytes))
+            },
+            0xff => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                u64::from_le_bytes(bytes)
+            },
+            n => u64::from(n),
+        };
+        Ok(n as usize)
+    }
+
+    /// Read the next key-value pair of a map, none at the map separator
+    fn pair(&mut self) -> Result<Option<(&'a [u8], &'a [u8])>> {
+        let key_len = self.compact_size()?;
+        if key_len == 0 {
+            return Ok(None);
+        }
+        let key = self.take(key_len)?;
+        let value_len = self.compact_size()?;
+        Ok(Some((key, self.take(value_len)?)))
+    }
+
+    /// Read all the pairs of a map up to its separator, keys must be unique
+    fn map(&mut self) -> Result<Vec<(&'a [u8], &'a [u8])>> {
+        let mut pairs: Vec<(&'a [u8], &'a [u8])> = vec![];
+        while let Some((key, value)) = self.pair()? {
+            if pairs.iter().any(|(k, _)| *k == key) {
+                return Err(Error::InvalidPsbt);
+            }
+            pairs.push((key, value));
+        }
+        Ok(pairs)
+    }
+
+    fn witness(&mut self) -> Result<Vec<Vec<u8>>> {
+        let count = self.compact_size()?;
+        let mut stack = vec![];
+        for _ in 0..count {
+            let len = self.compact_size()?;
+            stack.push(self.take(len)?.to_vec());
+        }
+        Ok(stack)
+    }
+}
+
+impl Psbt {
+    /// Create a PSBT from an unsigned transaction with empty maps
+    pub fn from_unsigned_tx(tx: Transaction) -> Result<Psbt> {
+        if tx.input.iter().any(|txin| !txin.script_sig.is_empty() || !txin.witness.is_empty()) {
+            return Err(Error::InvalidPsbt);
+        }
+        Ok(Psbt {
+            inputs: vec![PsbtInput::default(); tx.input.len()],
+            outputs: vec![PsbtOutput::default(); tx.output.len()],
+            unsigned_tx: tx,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        write_pair(&mut buf, &[PSBT_GLOBAL_UNSIGNED_TX], &serialize(&self.unsigned_tx));
+        buf.push(0x00);
+
+        for input in self.inputs.iter() {
+            if let Some(utxo) = &input.witness_utxo {
+                write_pair(&mut buf, &[PSBT_IN_WITNESS_UTXO], &serialize(utxo));
+            }
+            for (pubkey, sig) in input.partial_sigs.iter() {
+                let mut key = vec![PSBT_IN_PARTIAL_SIG];
+                key.extend_from_slice(&pubkey.serialize_compressed());
+                write_pair(&mut buf, &key, sig);
+            }
+            if let Some(sighash_type) = input.sighash_type {
+                write_pair(&mut buf, &[PSBT_IN_SIGHASH_TYPE], &sighash_type.to_le_bytes());
+            }
+            if let Some(stack) = &input.final_witness {
+                write_pair(&mut buf, &[PSBT_IN_FINAL_SCRIPTWITNESS], &write_witness(stack));
+            }
+            buf.push(0x00);
+        }
+
+        for output in self.outputs.iter() {
+            if let Some(script) = &output.witness_script {
+                write_pair(&mut buf, &[PSBT_OUT_WITNESS_SCRIPT], script.as_bytes());
+            }
+            buf.push(0x00);
+        }
+        buf
+    }
+
+    /// Decode a PSBT, unknown and unused keys are skipped but the keys of the known types
+    /// must be well formed
+    pub fn deserialize(data: &[u8]) -> Result<Psbt> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(Error::InvalidPsbt);
+        }
+
+        let mut unsigned_tx = None;
+        for (key, value) in reader.map()? {
+            if key[0] == PSBT_GLOBAL_UNSIGNED_TX {
+                check_key(key, 1)?;
+                unsigned_tx = Some(deserialize::<Transaction>(value)?);
+            }
+        }
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx.ok_or(Error::InvalidPsbt)?)?;
+
+        for input in psbt.inputs.iter_mut() {
+            for (key, value) in reader.map()? {
+                match key[0] {
+                    PSBT_IN_NON_WITNESS_UTXO | PSBT_IN_REDEEM_SCRIPT | PSBT_IN_WITNESS_SCRIPT
+                        | PSBT_IN_FINAL_SCRIPTSIG => check_key(key, 1)?,
+                    PSBT_IN_WITNESS_UTXO => {
+                        check_key(key, 1)?;
+                        input.witness_utxo = Some(deserialize::<TxOut>(value)?);
+                    },
+                    // Only compressed keys are used by SegWit inputs
+                    PSBT_IN_PARTIAL_SIG => {
+                        check_key(key, 34)?;
+                        let mut pubkey = [0u8; 33];
+                        pubkey.copy_from_slice(&key[1..]);
+                        input.partial_sigs.push((PublicKey::parse_compressed(&pubkey)?, value.to_vec()));
+                    },
+                    PSBT_IN_SIGHASH_TYPE => {
+                        check_key(key, 1)?;
+                        if value.len() != 4 {
+                            return Err(Error::InvalidPsbt);
+                        }
+                        input.sighash_type = Some(u32::from_le_bytes([value[0], value[1], value[2], value[3]]));
+                    },
+                    PSBT_IN_FINAL_SCRIPTWITNESS => {
+                        check_key(key, 1)?;
+                        let mut witness = Reader { data: value, pos: 0 };
+                        input.final_witness = Some(witness.witness()?);
+                        if witness.pos != value.len() {
+                            return Err(Error::InvalidPsbt);
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        for output in psbt.outputs.iter_mut() {
+            for (key, value) in reader.map()? {
+                match key[0] {
+                    PSBT_OUT_REDEEM_SCRIPT => check_key(key, 1)?,
+                    PSBT_OUT_WITNESS_SCRIPT => {
+                        check_key(key, 1)?;
+                        output.witness_script = Some(Script::from(value.to_vec()));
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        if reader.pos != data.len() {
+            return Err(Error::InvalidPsbt);
+        }
+        Ok(psbt)
+    }
+
+    pub fn to_base64(&self) -> String {
+        let data = self.serialize();
+        let mut out = String::new();
+        for chunk in data.chunks(3) {
+            let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_base64(s: &str) -> Result<Psbt> {
+        let s = s.trim().as_bytes();
+        if s.len() % 4 != 0 {
+            return Err(Error::InvalidPsbt);
+        }
+        let mut data = vec![];
+        for chunk in s.chunks(4) {
+            let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+            if padding > 2 {
+                return Err(Error::InvalidPsbt);
+            }
+            let mut n = 0u32;
+            for (i, c) in chunk[..4 - padding].iter().enumerate() {
+                let value = BASE64.iter().position(|b| b == c).ok_or(Error::InvalidPsbt)?;
+                n |= (value as u32) << (18 - 6 * i);
+            }
+            for i in 0..3 - padding {
+                data.push((n >> (16 - 8 * i)) as u8);
+            }
+        }
+        Psbt::deserialize(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Transaction, TxIn, TxOut, OutPoint, Script};
+    use bitcoin::consensus::encode::serialize;
+    use bitcoin_hashes::{Hash, sha256d};
+    use super::{Psbt, MAGIC, write_pair};
+
+    /// BIP174 valid vector, PSBT with one P2PKH input, outputs are empty
+    const VALID: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+    /// Serialize the global, input and output maps of a PSBT
+    fn encode(maps: &[&[(&[u8], &[u8])]]) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        for map in maps {
+            for (key, value) in map.iter() {
+                write_pair(&mut buf, key, value);
+            }
+            buf.push(0x00);
+        }
+        buf
+    }
+
+    fn unsigned_tx() -> Vec<u8> {
+        serialize(&Psbt::from_base64(VALID).unwrap().unsigned_tx)
+    }
+
+    #[test]
+    fn base64_roundtrip() {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: sha256d::Hash::hash(&[1u8]), vout: 1 },
+                script_sig: Script::new(),
+                sequence: std::u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 50_000, script_pubkey: Script::from(vec![0u8; 34]) }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut { value: 60_000, script_pubkey: Script::from(vec![0u8; 22]) });
+        psbt.inputs[0].sighash_type = Some(1);
+        psbt.inputs[0].final_witness = Some(vec![vec![1u8; 72], vec![2u8; 33]]);
+        psbt.outputs[0].witness_script = Some(Script::from(vec![0x51]));
+
+        let encoded = psbt.to_base64();
+        assert!(encoded.starts_with("cHNidP8"));
+        assert_eq!(psbt, Psbt::from_base64(&encoded).unwrap());
+    }
+
+    #[test]
+    fn reject_missing_unsigned_tx() {
+        assert!(Psbt::from_base64("cHNidP8AAA==").is_err());
+    }
+
+    #[test]
+    fn bip174_valid() {
+        let psbt = Psbt::from_base64(VALID).unwrap();
+        assert_eq!(1, psbt.inputs.len());
+        assert_eq!(2, psbt.outputs.len());
+        assert_eq!(
+            "f61b1742ca13176464adb3cb66050c00787bb3a4eead37e985f2df1e37718126",
+            format!("{}", psbt.unsigned_tx.input[0].previous_output.txid)
+        );
+        // The non-witness UTXO of the P2PKH input is skipped
+        assert_eq!(None, psbt.inputs[0].witness_utxo);
+    }
+
+    #[test]
+    fn bip174_invalid() {
+        let tx = unsigned_tx();
+        let sig = [0x30u8; 71];
+        let mut key = vec![0x02u8; 1];
+        key.extend_from_slice(&[0x02; 32]);
+
+        // Network transaction, not PSBT format
+        assert!(Psbt::deserialize(&tx).is_err());
+        // Missing outputs
+        let mut data = encode(&[&[(&[0x00], &tx)], &[]]);
+        data.push(0x00);
+        assert!(Psbt::deserialize(&data).is_err());
+        // Inputs and outputs without an unsigned transaction
+        assert!(Psbt::deserialize(&encode(&[&[], &[], &[], &[]])).is_err());
+        // Invalid global transaction typed key
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00, 0x01], &tx)], &[], &[], &[]])).is_err());
+        // Duplicate keys in an input
+        let sighash: &[u8] = &[0x01, 0x00, 0x00, 0x00];
+        let duplicates = encode(&[&[(&[0x00], &tx)], &[(&[0x03], sighash), (&[0x03], sighash)], &[], &[]]);
+        assert!(Psbt::deserialize(&duplicates).is_err());
+        // Invalid input witness UTXO typed key
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&[0x01, 0x00], &[0u8; 9])], &[], &[]])).is_err());
+        // Invalid pubkey length for input partial signature typed key
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&key, &sig)], &[], &[]])).is_err());
+        // Invalid input redeem script typed key
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&[0x04, 0x00], &[0x51])], &[], &[]])).is_err());
+        // Invalid input sighash type typed key and value
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&[0x03, 0x00], sighash)], &[], &[]])).is_err());
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&[0x03], &sighash[..2])], &[], &[]])).is_err());
+        // Invalid output witness script typed key
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[], &[(&[0x01, 0x00], &[0x51])], &[]])).is_err());
+        // Well formed maps are accepted
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[(&[0x03], sighash)], &[], &[]])).is_ok());
+    }
+
+    #[test]
+    fn bip174_invalid_filled_script_sig() {
+        let mut tx = Psbt::from_base64(VALID).unwrap().unsigned_tx;
+        tx.input[0].script_sig = Script::from(vec![0x51]);
+        let tx = serialize(&tx);
+        assert!(Psbt::deserialize(&encode(&[&[(&[0x00], &tx)], &[], &[], &[]])).is_err());
+    }
+}
@@ -14,7 +14,6 @@
 //
 
 use super::PublicKey;
-use crate::types::xmr;
 use secp256k1::Signature;
 use bitcoin::blockdata::script::{Builder, Script};
 use bitcoin::blockdata::opcodes::{all, OP_CSV};
@@ -37,13 +36,66 @@ pub fn redeem_p2pkh(pk: &PublicKey) -> Script {
         .into_script()
 }
 
-#[allow(non_snake_case)]
-pub fn create_swaplock(B_a: &PublicKey, B_b: &PublicKey, h_0: &[u8], h_2: &[u8], t_0: u32) -> Script {
+/// Output script paying to the public key hash of a SegWit v0 program
+pub fn p2wpkh_script_pubkey(pk: &PublicKey) -> Script {
     Builder::new()
-        .push_opcode(all::OP_IF)
-        .push_opcode(all::OP_SHA256)
-        .push_slice(h_0)
-        .push_opcode(all::OP_EQUALVERIFY)
+        .push_int(0)
+        .push_slice(&hash160::Hash::hash(&pk.serialize_compressed()[..])[..])
+        .into_script()
+}
+
+/// Output script is a SegWit program (BIP141): a version opcode followed by a single push of 2
+/// to 40 bytes. Spending it does not change the txid, unlike P2SH wrapped or legacy outputs.
+pub fn is_witness_program(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    if bytes.len() < 4 || bytes.len() > 42 {
+        return false;
+    }
+    // OP_0 or OP_1 (0x51) to OP_16 (0x60)
+    let version = bytes[0] == 0 || (bytes[0] >= 0x51 && bytes[0] <= 0x60);
+    version && bytes[1] as usize == bytes.len() - 2
+}
+
+/// Check one preimage per hash, the first hash checks the top of the stack
+fn push_hash_locks(mut builder: Builder, hashes: &[[u8; 32]]) -> Builder {
+    for hash in hashes {
+        builder = builder
+            .push_opcode(all::OP_SHA256)
+            .push_slice(hash)
+            .push_opcode(all::OP_EQUALVERIFY);
+    }
+    builder
+}
+
+/// Stack the preimages in the reverse order of their hash locks
+fn push_preimages(witness: &mut Vec<Vec<u8>>, preimages: &[[u8; 32]]) {
+    witness.extend(preimages.iter().rev().map(|preimage| preimage.to_vec()));
+}
+
+/// Preimages revealed between `start` and the `OP_TRUE` selecting the hash lock branch
+fn extract_preimages(witness: &[Vec<u8>], start: usize) -> Option<Vec<[u8; 32]>> {
+    let end = witness.len().checked_sub(2)?;
+    if end <= start || witness[end] != [1] {
+        return None;
+    }
+    witness[start..end].iter()
+        .rev()
+        .map(|item| match item.len() {
+            32 => {
+                let mut preimage = [0u8; 32];
+                preimage.copy_from_slice(item);
+                Some(preimage)
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Swaplock script, the buy branch checks the hash locks `h_0` of the seller key share and the
+/// hash `h_2` of the secret `s`
+#[allow(non_snake_case)]
+pub fn create_swaplock(B_a: &PublicKey, B_b: &PublicKey, h_0: &[[u8; 32]], h_2: &[u8], t_0: u32) -> Script {
+    push_hash_locks(Builder::new().push_opcode(all::OP_IF), h_0)
         .push_opcode(all::OP_SHA256)
         .push_slice(h_2)
         .push_opcode(all::OP_EQUALVERIFY)
@@ -72,23 +124,26 @@ pub fn redeem_swaplock_multisig(swaplock: Script, sig_a: Signature, sig_b: Signa
     ]
 }
 
-pub fn redeem_swaplock_buy(swaplock: Script, sig_a: Signature, share: &xmr::PrivateKey, s: &[u8]) -> Vec<Vec<u8>> {
-    vec![
+pub fn redeem_swaplock_buy(swaplock: Script, sig_a: Signature, preimages: &[[u8; 32]], s: &[u8]) -> Vec<Vec<u8>> {
+    let mut witness = vec![
         serialize_sig(sig_a), // Sig_a
         Vec::from(s), // Secret s
-        Vec::from(&share.to_bytes()[..]), // x_0 share
-        vec![1], // OP_TRUE
-        swaplock.into_bytes(), // swaplock script
-    ]
+    ];
+    push_preimages(&mut witness, preimages); // x_0 hash lock preimages
+    witness.push(vec![1]); // OP_TRUE
+    witness.push(swaplock.into_bytes()); // swaplock script
+    witness
+}
+
+/// Extract the preimages of the seller key share hash locks revealed in a swaplock buy witness
+pub fn extract_swaplock_buy_preimages(witness: &[Vec<u8>]) -> Option<Vec<[u8; 32]>> {
+    extract_preimages(witness, 2)
 }
 
+/// Refund script, the spend branch checks the hash locks `h_1` of the buyer key share
 #[allow(non_snake_case)]
-pub fn create_refund(B_a: &PublicKey, B_b: &PublicKey, h_1: &[u8], t_1: u32) -> Script {
-    Builder::new()
-        .push_opcode(all::OP_IF)
-        .push_opcode(all::OP_SHA256)
-        .push_slice(h_1)
-        .push_opcode(all::OP_EQUALVERIFY)
+pub fn create_refund(B_a: &PublicKey, B_b: &PublicKey, h_1: &[[u8; 32]], t_1: u32) -> Script {
+    push_hash_locks(Builder::new().push_opcode(all::OP_IF), h_1)
         .push_slice(&B_b.serialize_compressed())
         .push_opcode(all::OP_CHECKSIG)
         .push_opcode(all::OP_ELSE)
@@ -101,15 +156,16 @@ pub fn create_refund(B_a: &PublicKey, B_b: &PublicKey, h_1: &[u8], t_1: u32) ->
         .into_script()
 }
 
-pub fn redeem_refund(refund: Script, sig: Signature, share: Option<&xmr::PrivateKey>) -> Vec<Vec<u8>> {
+pub fn redeem_refund(refund: Script, sig: Signature, preimages: Option<&[[u8; 32]]>) -> Vec<Vec<u8>> {
     let sig = serialize_sig(sig);
-    match share {
-        Some(x_1) => vec![
-            sig, // Sig_b
-            Vec::from(&x_1.to_bytes()[..]), // x_1 share
-            vec![1], // OP_TRUE
-            refund.into_bytes(), // refund script
-        ],
+    match preimages {
+        Some(preimages) => {
+            let mut witness = vec![sig]; // Sig_b
+            push_preimages(&mut witness, preimages); // x_1 hash lock preimages
+            witness.push(vec![1]); // OP_TRUE
+            witness.push(refund.into_bytes()); // refund script
+            witness
+        },
         None => vec![
             sig, // Sig_a
             vec![], // OP_FALSE
@@ -118,13 +174,87 @@ pub fn redeem_refund(refund: Script, sig: Signature, share: Option<&xmr::Private
     }
 }
 
+#[allow(non_snake_case)]
+pub fn create_adaptor_swaplock(B_a: &PublicKey, B_b: &PublicKey, t_0: u32) -> Script {
+    Builder::new()
+        .push_opcode(all::OP_IF)
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_slice(&B_a.serialize_compressed())
+        .push_slice(&B_b.serialize_compressed())
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_opcode(all::OP_CHECKMULTISIG)
+        .push_opcode(all::OP_ELSE)
+        .push_int(t_0.into())
+        .push_opcode(OP_CSV)
+        .push_opcode(all::OP_DROP)
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_slice(&B_a.serialize_compressed())
+        .push_slice(&B_b.serialize_compressed())
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_opcode(all::OP_CHECKMULTISIG)
+        .push_opcode(all::OP_ENDIF)
+        .into_script()
+}
+
+#[allow(non_snake_case)]
+pub fn create_adaptor_refund(B_a: &PublicKey, B_b: &PublicKey, t_1: u32) -> Script {
+    Builder::new()
+        .push_opcode(all::OP_IF)
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_slice(&B_a.serialize_compressed())
+        .push_slice(&B_b.serialize_compressed())
+        .push_opcode(all::OP_PUSHNUM_2)
+        .push_opcode(all::OP_CHECKMULTISIG)
+        .push_opcode(all::OP_ELSE)
+        .push_int(t_1.into())
+        .push_opcode(OP_CSV)
+        .push_opcode(all::OP_DROP)
+        .push_slice(&B_a.serialize_compressed())
+        .push_opcode(all::OP_CHECKSIG)
+        .push_opcode(all::OP_ENDIF)
+        .into_script()
+}
+
+pub fn redeem_adaptor_multisig(script: Script, sig_a: Signature, sig_b: Signature) -> Vec<Vec<u8>> {
+    vec![
+        vec![], // 0 for multisig
+        serialize_sig(sig_a), // Sig_a
+        serialize_sig(sig_b), // Sig_b
+        vec![1], // OP_TRUE
+        script.into_bytes(), // swaplock or refund script
+    ]
+}
+
+/// Extract `Sig_a` and `Sig_b` from a cooperative adaptor multisig witness
+pub fn extract_adaptor_multisig(witness: &[Vec<u8>]) -> Option<(Signature, Signature)> {
+    match witness.len() == 5 && witness[3] == [1] {
+        true => Some((parse_sig(&witness[1])?, parse_sig(&witness[2])?)),
+        false => None,
+    }
+}
+
+/// Parse a DER signature followed by the SIGHASH_ALL flag
+pub(crate) fn parse_sig(bytes: &[u8]) -> Option<Signature> {
+    match bytes.split_last() {
+        Some((1, der)) => Signature::parse_der(der).ok(),
+        _ => None,
+    }
+}
+
+/// Extract the preimages of the buyer key share hash locks revealed in a refund spend witness
+pub fn extract_refund_preimages(witness: &[Vec<u8>]) -> Option<Vec<[u8; 32]>> {
+    extract_preimages(witness, 1)
+}
+
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
     use secp256k1::{Signature, PublicKeyFormat};
     use bitcoin::Script;
-    use crate::types::{xmr, btc};
-    use super::{create_swaplock, redeem_swaplock_multisig, redeem_swaplock_buy, create_refund, redeem_refund};
+    use crate::types::btc;
+    use super::{create_swaplock, redeem_swaplock_multisig, redeem_swaplock_buy, create_refund, redeem_refund,
+        extract_swaplock_buy_preimages, extract_refund_preimages, redeem_adaptor_multisig, extract_adaptor_multisig,
+        is_witness_program, p2wpkh_script_pubkey};
 
     #[test]
     fn swaplock() {
@@ -136,7 +266,7 @@ mod tests {
             &hex::decode("03580314ac61e993d67dc247aa742a89568f1018efdaa1d29b848aa933563442a8").unwrap(),
             Some(PublicKeyFormat::Compressed)
         ).unwrap();
-        let swaplock = create_swaplock(&B_a, &B_b, &[2; 32], &[4; 32], 144);
+        let swaplock = create_swaplock(&B_a, &B_b, &[[2; 32]], &[4; 32], 144);
         assert_eq!(swaplock.as_bytes(), &[99u8, 168, 32, 2, 2, 2, 2, 2, 2, 2, 2,
         2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
         136, 168, 32, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
@@ -168,24 +298,32 @@ mod tests {
     fn redeem_swaplock_with_buy() {
         let swaplock_script = Script::from(vec![0u8; 140]);
         let sig = Signature::parse_der(&[48, 6, 2, 1, 1, 2, 1, 1]).unwrap();
-        let key: [u8; 32] = [0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 1];
-        let share = xmr::PrivateKey::from_bytes_mod_order(key);
-        let redeem = redeem_swaplock_buy(swaplock_script, sig, &share, &[0; 32]);
+        let redeem = redeem_swaplock_buy(swaplock_script, sig, &[[1; 32], [2; 32]], &[0; 32]);
         assert_eq!(redeem, vec![
                    vec![48, 6, 2, 1, 1, 2, 1, 1, 1], // Add SIGHASH ALL (0x01) after sig
                    vec![0; 32], // Secret
-                   vec![0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 1], // Private key share
+                   vec![2; 32], // Preimage of the second hash lock
+                   vec![1; 32], // Preimage of the first hash lock, top of the stack
                    vec![1], // OP_TRUE for IF/ELSE
                    vec![0u8; 140], // Script
         ]);
     }
 
+    #[test]
+    fn extract_preimages_from_buy() {
+        let swaplock_script = Script::from(vec![0u8; 140]);
+        let sig = Signature::parse_der(&[48, 6, 2, 1, 1, 2, 1, 1]).unwrap();
+        let preimages = vec![[7; 32], [8; 32], [9; 32]];
+        let redeem = redeem_swaplock_buy(swaplock_script, sig.clone(), &preimages, &[0; 32]);
+        assert_eq!(Some(preimages), extract_swaplock_buy_preimages(&redeem));
+
+        let redeem = redeem_swaplock_multisig(Script::from(vec![0u8; 140]), sig.clone(), sig.clone());
+        assert_eq!(None, extract_swaplock_buy_preimages(&redeem));
+
+        let redeem = redeem_adaptor_multisig(Script::from(vec![0u8; 140]), sig.clone(), sig);
+        assert_eq!(None, extract_swaplock_buy_preimages(&redeem));
+    }
+
     #[test]
     fn refund() {
         let B_a = btc::PublicKey::parse_slice(
@@ -196,7 +334,7 @@ mod tests {
             &hex::decode("03580314ac61e993d67dc247aa742a89568f1018efdaa1d29b848aa933563442a8").unwrap(),
             Some(PublicKeyFormat::Compressed)
         ).unwrap();
-        let refund = create_refund(&B_a, &B_b, &[0; 32], 144);
+        let refund = create_refund(&B_a, &B_b, &[[0; 32]], 144);
         assert_eq!(refund.as_bytes(), &[99u8, 168, 32, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         136, 33, 3, 88, 3, 20, 172, 97, 233, 147, 214, 125, 194, 71, 170, 116,
@@ -223,20 +361,50 @@ mod tests {
     fn claim_refund() {
         let refund_script = Script::from(vec![0u8; 140]);
         let sig = Signature::parse_der(&[48, 6, 2, 1, 1, 2, 1, 1]).unwrap();
-        let key: [u8; 32] = [0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 0,
-                             0, 0, 0, 0, 0, 0, 0, 1];
-        let share = xmr::PrivateKey::from_bytes_mod_order(key);
-        let redeem = redeem_refund(refund_script, sig, Some(&share));
+        let redeem = redeem_refund(refund_script, sig, Some(&[[1; 32], [2; 32]][..]));
         assert_eq!(redeem, vec![
                    vec![48, 6, 2, 1, 1, 2, 1, 1, 1], // Add SIGHASH ALL (0x01) after sig
-                   vec![0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 0,
-                        0, 0, 0, 0, 0, 0, 0, 1], // Pricate key share
+                   vec![2; 32], // Preimage of the second hash lock
+                   vec![1; 32], // Preimage of the first hash lock, top of the stack
                    vec![1], // OP_TRUE for IF/ELSE
                    vec![0u8; 140], // Refund script
         ]);
     }
+
+    #[test]
+    fn redeem_adaptor_with_multisig() {
+        let script = Script::from(vec![0u8; 140]);
+        let sig = Signature::parse_der(&[48, 6, 2, 1, 1, 2, 1, 1]).unwrap();
+        let redeem = redeem_adaptor_multisig(script, sig.clone(), sig.clone());
+        assert_eq!(redeem, vec![
+                   vec![], // OP_FALSE for multisig
+                   vec![48, 6, 2, 1, 1, 2, 1, 1, 1], // Add SIGHASH ALL (0x01) after sig
+                   vec![48, 6, 2, 1, 1, 2, 1, 1, 1], // Add SIGHASH ALL (0x01) after sig
+                   vec![1], // OP_TRUE for IF/ELSE
+                   vec![0u8; 140],
+        ]);
+        assert_eq!(Some((sig.clone(), sig)), extract_adaptor_multisig(&redeem));
+    }
+
+    #[test]
+    fn extract_preimages_from_refund() {
+        let refund_script = Script::from(vec![0u8; 140]);
+        let sig = Signature::parse_der(&[48, 6, 2, 1, 1, 2, 1, 1]).unwrap();
+        let preimages = vec![[9; 32], [10; 32]];
+        let redeem = redeem_refund(refund_script.clone(), sig.clone(), Some(&preimages[..]));
+        assert_eq!(Some(preimages), extract_refund_preimages(&redeem));
+
+        let redeem = redeem_refund(refund_script, sig, None);
+        assert_eq!(None, extract_refund_preimages(&redeem));
+    }
+
+    #[test]
+    fn witness_programs() {
+        let pk = btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&[1; 32]).unwrap());
+        assert!(is_witness_program(&p2wpkh_script_pubkey(&pk)));
+        assert!(is_witness_program(&create_refund(&pk, &pk, &[[2; 32]], 6).to_v0_p2wsh()));
+        // P2SH and P2PKH are not
+        assert!(!is_witness_program(&Script::from(hex::decode("a914000000000000000000000000000000000000000087").unwrap())));
+        assert!(!is_witness_program(&Script::from(hex::decode("76a914000000000000000000000000000000000000000088ac").unwrap())));
+    }
 }
@@ -13,19 +13,129 @@
 // copies or substantial portions of the Software.
 //
 
-use super::RelativeLocktime;
+use super::{RelativeLocktime, Result, Error};
+use super::xmr::address;
+use super::amount::{Satoshi, Piconero};
+
+use bitcoin::Address;
+use bitcoin::util::address::Payload;
+
+use std::str::FromStr;
+
+/// Bitcoin network of the swap, signet shares the testnet address encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BtcNetwork {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl BtcNetwork {
+    /// Check an address is encoded for this network, base58 addresses do not distinguish
+    /// regtest from testnet
+    pub fn accepts(&self, address: &Address) -> bool {
+        use bitcoin::Network;
+
+        match (self, address.network) {
+            (BtcNetwork::Mainnet, Network::Bitcoin) => true,
+            (BtcNetwork::Testnet, Network::Testnet) | (BtcNetwork::Signet, Network::Testnet) => true,
+            (BtcNetwork::Regtest, Network::Regtest) => true,
+            (BtcNetwork::Regtest, Network::Testnet) => match address.payload {
+                Payload::WitnessProgram(_) => false,
+                _ => true,
+            },
+            _ => false,
+        }
+    }
+
+    /// Fail if the address is not encoded for this network
+    pub fn check(&self, address: &Address) -> Result<()> {
+        match self.accepts(address) {
+            true => Ok(()),
+            false => Err(Error::InvalidNetwork),
+        }
+    }
+}
+
+impl FromStr for BtcNetwork {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<BtcNetwork> {
+        match s {
+            "mainnet" => Ok(BtcNetwork::Mainnet),
+            "testnet" => Ok(BtcNetwork::Testnet),
+            "signet" => Ok(BtcNetwork::Signet),
+            "regtest" => Ok(BtcNetwork::Regtest),
+            _ => Err(Error::InvalidNetwork),
+        }
+    }
+}
+
+/// Networks of both chains, both parties must agree on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Networks {
+    pub btc: BtcNetwork,
+    pub xmr: address::Network,
+}
+
+impl Networks {
+    pub fn new(btc: BtcNetwork, xmr: address::Network) -> Networks {
+        Networks {
+            btc,
+            xmr,
+        }
+    }
+}
+
+/// Amounts traded in the swap, both parties must agree on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amounts {
+    /// Locked by the buyer in the swaplock output
+    pub btc: Satoshi,
+    /// Locked by the seller in the shared address
+    pub xmr: Piconero,
+}
+
+impl Amounts {
+    pub fn new(btc: Satoshi, xmr: Piconero) -> Amounts {
+        Amounts {
+            btc,
+            xmr,
+        }
+    }
+
+    /// Exchange rate in satoshi per monero (10^12 piconero), rounded down
+    pub fn sat_per_xmr(&self) -> Option<u64> {
+        if self.xmr.as_u64() == 0 {
+            return None;
+        }
+        let rate = self.btc.as_u64() as u128 * 1_000_000_000_000 / self.xmr.as_u64() as u128;
+        match rate > std::u64::MAX as u128 {
+            true => None,
+            false => Some(rate as u64),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Params {
     pub t_0: RelativeLocktime,
     pub t_1: RelativeLocktime,
+    pub networks: Networks,
+    pub amounts: Amounts,
 }
 
 impl Params {
-    pub fn new(t_0: RelativeLocktime, t_1: RelativeLocktime) -> Params {
-        Params {
+    /// Common parameters, fails if a locktime is outside the BIP68 range
+    pub fn new(t_0: RelativeLocktime, t_1: RelativeLocktime, networks: Networks, amounts: Amounts) -> Result<Params> {
+        t_0.check()?;
+        t_1.check()?;
+        Ok(Params {
             t_0,
             t_1,
-        }
+            networks,
+            amounts,
+        })
     }
 }
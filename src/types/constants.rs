@@ -13,6 +13,24 @@
 // copies or substantial portions of the Software.
 //
 
-// Btc/kB
-pub const FEE_KB: f64 = 0.00001;
-pub const FEE_KB_SATOSHI: u64 = 1000;
+/// Minimum relay feerate in satoshi per virtual byte
+pub const MIN_RELAY_FEERATE: u64 = 1;
+
+/// Maximum feerate in satoshi per virtual byte accepted on a transaction built by the
+/// counterparty
+pub const MAX_FEERATE: u64 = 1_000;
+
+/// Outputs below this value in satoshi are not created, P2WPKH dust at 3 sat/vB
+pub const DUST_LIMIT: u64 = 294;
+
+/// nSequence bit disabling the relative locktime of an input (BIP68)
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// nSequence bit selecting a time based relative locktime (BIP68)
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// nSequence bits holding the relative locktime value (BIP68)
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// Time based relative locktimes are in units of 2^9 = 512 seconds (BIP68)
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
@@ -0,0 +1,343 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Cross-group discrete logarithm equality proof between ed25519 and secp256k1
+//!
+//! Proves that an ed25519 point `X = x·G` and a secp256k1 point `X' = x·G'` share
+//! the same discrete logarithm `x < 2^252`. The scalar is decomposed in bits, each
+//! bit is committed in both groups with blinding factors summing to zero, and a
+//! two-member ring signature per bit proves that both commitments hide the same
+//! bit (see MRL-0010).
+
+use super::{Result, Error, xmr, btc, wire};
+
+use rand::{Rng, CryptoRng};
+use bitcoin_hashes::{Hash, sha256};
+use curve25519_dalek::constants;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use curve25519_dalek::traits::Identity;
+use secp256k1::curve::Scalar as SecpScalar;
+
+/// Number of bits proven, the scalar must be lower than `2^252`
+pub const BITS: usize = 252;
+
+/// Commitments and ring signature for one bit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitProof {
+    #[serde(with = "wire::ed25519_point")]
+    pub(crate) c: xmr::PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) d: btc::PublicKey,
+    #[serde(with = "wire::bytes32")]
+    pub(crate) e_0: [u8; 32],
+    #[serde(with = "wire::ed25519_scalar_pair")]
+    pub(crate) z: [xmr::PrivateKey; 2],
+    #[serde(with = "wire::bytes32_pair")]
+    pub(crate) z_secp: [[u8; 32]; 2],
+}
+
+/// Proof that an ed25519 point and a secp256k1 point share the same discrete
+/// logarithm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Proof {
+    pub(crate) bits: Vec<BitProof>,
+}
+
+/// Second ed25519 generator with unknown discrete logarithm relative to `G`
+fn ed25519_h() -> xmr::PublicKey {
+    let mut counter = 0u32;
+    loop {
+        let mut data = b"monero-swap-lib dleq ed25519".to_vec();
+        data.extend_from_slice(&counter.to_le_bytes());
+        let hash = sha256::Hash::hash(&data);
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hash[..]);
+        if let Some(point) = CompressedEdwardsY(bytes).decompress() {
+            let point = point.mul_by_cofactor();
+            if point != xmr::PublicKey::identity() {
+                return point;
+            }
+        }
+        counter += 1;
+    }
+}
+
+/// Second secp256k1 generator with unknown discrete logarithm relative to `G'`
+fn secp256k1_h() -> btc::PublicKey {
+    let mut counter = 0u32;
+    loop {
+        let mut data = b"monero-swap-lib dleq secp256k1".to_vec();
+        data.extend_from_slice(&counter.to_le_bytes());
+        let hash = sha256::Hash::hash(&data);
+        let mut bytes = [0u8; 33];
+        bytes[0] = 0x02;
+        bytes[1..].copy_from_slice(&hash[..]);
+        if let Ok(point) = btc::PublicKey::parse_compressed(&bytes) {
+            return point;
+        }
+        counter += 1;
+    }
+}
+
+pub(crate) fn secp_scalar(bytes: &[u8; 32]) -> SecpScalar {
+    let mut scalar = SecpScalar::default();
+    scalar.set_b32(bytes);
+    scalar
+}
+
+pub(crate) fn secp_mul(point: &btc::PublicKey, k: &SecpScalar) -> Result<btc::PublicKey> {
+    let mut point = point.clone();
+    point.tweak_mul_assign(&btc::PrivateKey::parse(&k.b32())?)?;
+    Ok(point)
+}
+
+pub(crate) fn secp_neg(point: &btc::PublicKey) -> Result<btc::PublicKey> {
+    let mut bytes = point.serialize_compressed();
+    bytes[0] ^= 0x01;
+    Ok(btc::PublicKey::parse_compressed(&bytes)?)
+}
+
+pub(crate) fn secp_add(a: &btc::PublicKey, b: &btc::PublicKey) -> Result<btc::PublicKey> {
+    Ok(btc::PublicKey::combine(&[a.clone(), b.clone()])?)
+}
+
+pub(crate) fn random_secp<R: Rng + CryptoRng>(rng: &mut R) -> Result<SecpScalar> {
+    loop {
+        let mut bytes = [0u8; 32];
+        rng.try_fill(&mut bytes)?;
+        if btc::PrivateKey::parse(&bytes).is_ok() {
+            return Ok(secp_scalar(&bytes));
+        }
+    }
+}
+
+/// Challenge lower than `2^248`, valid as a scalar in both groups, encoded in
+/// little endian
+fn challenge(index: usize, c: &xmr::PublicKey, d: &btc::PublicKey, r: &xmr::PublicKey,
+             r_secp: &btc::PublicKey) -> [u8; 32] {
+    let mut data = b"monero-swap-lib dleq".to_vec();
+    data.extend_from_slice(&(index as u32).to_le_bytes());
+    data.extend_from_slice(c.compress().as_bytes());
+    data.extend_from_slice(&d.serialize_compressed());
+    data.extend_from_slice(r.compress().as_bytes());
+    data.extend_from_slice(&r_secp.serialize_compressed());
+    let hash = sha256::Hash::hash(&data);
+    let mut e = [0u8; 32];
+    e.copy_from_slice(&hash[..]);
+    e[31] = 0;
+    e
+}
+
+fn challenge_secp(e: &[u8; 32]) -> SecpScalar {
+    let mut be = *e;
+    be.reverse();
+    secp_scalar(&be)
+}
+
+/// Members of the ring for bit `i`: `(C, D)` and `(C - 2^i·G, D - 2^i·G')`
+fn ring(c: &xmr::PublicKey, d: &btc::PublicKey, pow: &xmr::PrivateKey, pow_secp: &SecpScalar)
+    -> Result<[(xmr::PublicKey, btc::PublicKey); 2]> {
+    let g = pow * &constants::ED25519_BASEPOINT_TABLE;
+    let g_secp = btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&pow_secp.b32())?);
+    Ok([
+        (*c, d.clone()),
+        (c - g, secp_add(d, &secp_neg(&g_secp)?)?),
+    ])
+}
+
+/// Nonce commitments `z·H - e·P` in both groups
+fn ring_nonces(z: &xmr::PrivateKey, z_secp: &SecpScalar, e: &[u8; 32],
+               member: &(xmr::PublicKey, btc::PublicKey)) -> Result<(xmr::PublicKey, btc::PublicKey)> {
+    let e_ed = xmr::PrivateKey::from_bytes_mod_order(*e);
+    let r = z * ed25519_h() - e_ed * member.0;
+    let r_secp = secp_add(
+        &secp_mul(&secp256k1_h(), z_secp)?,
+        &secp_neg(&secp_mul(&member.1, &challenge_secp(e))?)?,
+    )?;
+    Ok((r, r_secp))
+}
+
+/// Compute the secp256k1 point `x·G'` of an ed25519 scalar
+pub fn secp256k1_point(x: &xmr::PrivateKey) -> Result<btc::PublicKey> {
+    Ok(btc::PublicKey::from_secret_key(&secp256k1_scalar(x)?))
+}
+
+/// Convert an ed25519 scalar lower than `2^252` into a secp256k1 secret key
+pub fn secp256k1_scalar(x: &xmr::PrivateKey) -> Result<btc::PrivateKey> {
+    let mut bytes = x.to_bytes();
+    if bytes[31] & 0xf0 != 0 {
+        return Err(Error::InvalidKeyShare);
+    }
+    bytes.reverse();
+    Ok(btc::PrivateKey::parse(&bytes)?)
+}
+
+/// Convert a secp256k1 secret key lower than `2^252` into an ed25519 scalar
+pub fn ed25519_scalar(x: &btc::PrivateKey) -> Result<xmr::PrivateKey> {
+    let mut bytes = x.serialize();
+    bytes.reverse();
+    if bytes[31] & 0xf0 != 0 {
+        return Err(Error::InvalidKeyShare);
+    }
+    Ok(xmr::PrivateKey::from_bytes_mod_order(bytes))
+}
+
+/// Generate a random scalar lower than `2^252` usable in the proof
+pub fn random_scalar<R: Rng + CryptoRng>(rng: &mut R) -> Result<xmr::PrivateKey> {
+    let mut bytes = [0u8; 32];
+    rng.try_fill(&mut bytes)?;
+    bytes[31] &= 0x0f;
+    Ok(xmr::PrivateKey::from_bytes_mod_order(bytes))
+}
+
+impl Proof {
+    /// Prove that `x·G` and `x·G'` share the same discrete logarithm
+    pub fn prove<R: Rng + CryptoRng>(x: &xmr::PrivateKey, rng: &mut R) -> Result<Proof> {
+        let bytes = x.to_bytes();
+        if bytes[31] & 0xf0 != 0 {
+            return Err(Error::InvalidProof);
+        }
+
+        let h = ed25519_h();
+        let h_secp = secp256k1_h();
+
+        // Blinding factors summing to zero in both groups
+        let mut r = vec![];
+        let mut s = vec![];
+        let mut r_sum = xmr::PrivateKey::zero();
+        let mut s_sum = SecpScalar::default();
+        for _ in 0..BITS - 1 {
+            let r_i = xmr::PrivateKey::random(rng);
+            let s_i = random_secp(rng)?;
+            r_sum += r_i;
+            s_sum += &s_i;
+            r.push(r_i);
+            s.push(s_i);
+        }
+        r.push(-r_sum);
+        s.push(s_sum.neg());
+
+        let mut bits = vec![];
+        let mut pow = xmr::PrivateKey::one();
+        let mut pow_secp = SecpScalar::from_int(1);
+        for i in 0..BITS {
+            let b = ((bytes[i / 8] >> (i % 8)) & 1) as usize;
+
+            let c = r[i] * h + match b {
+                1 => &pow * &constants::ED25519_BASEPOINT_TABLE,
+                _ => xmr::PublicKey::identity(),
+            };
+            let d = match b {
+                1 => secp_add(
+                    &secp_mul(&h_secp, &s[i])?,
+                    &btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&pow_secp.b32())?),
+                )?,
+                _ => secp_mul(&h_secp, &s[i])?,
+            };
+            let members = ring(&c, &d, &pow, &pow_secp)?;
+
+            // Real member b, simulated member 1 - b
+            let k = xmr::PrivateKey::random(rng);
+            let k_secp = random_secp(rng)?;
+            let mut e = [[0u8; 32]; 2];
+            let mut z = [xmr::PrivateKey::zero(); 2];
+            let mut z_secp = [[0u8; 32]; 2];
+
+            e[1 - b] = challenge(i, &c, &d, &(k * h), &secp_mul(&h_secp, &k_secp)?);
+            z[1 - b] = xmr::PrivateKey::random(rng);
+            let z_fake = random_secp(rng)?;
+            z_secp[1 - b] = z_fake.b32();
+            let (r_fake, r_fake_secp) = ring_nonces(&z[1 - b], &z_fake, &e[1 - b], &members[1 - b])?;
+            e[b] = challenge(i, &c, &d, &r_fake, &r_fake_secp);
+
+            z[b] = k + xmr::PrivateKey::from_bytes_mod_order(e[b]) * r[i];
+            z_secp[b] = (&k_secp + &(&challenge_secp(&e[b]) * &s[i])).b32();
+
+            bits.push(BitProof { c, d, e_0: e[0], z, z_secp });
+
+            pow += pow;
+            pow_secp = &pow_secp + &pow_secp;
+        }
+
+        Ok(Proof { bits })
+    }
+
+    /// Verify the proof against an ed25519 point and a secp256k1 point
+    pub fn verify(&self, point: &xmr::PublicKey, point_secp: &btc::PublicKey) -> Result<()> {
+        if self.bits.len() != BITS {
+            return Err(Error::InvalidProof);
+        }
+
+        let mut pow = xmr::PrivateKey::one();
+        let mut pow_secp = SecpScalar::from_int(1);
+        let mut c_sum = xmr::PublicKey::identity();
+        let mut d_sum = vec![];
+        for (i, bit) in self.bits.iter().enumerate() {
+            let members = ring(&bit.c, &bit.d, &pow, &pow_secp)?;
+
+            let (r_0, r_0_secp) = ring_nonces(&bit.z[0], &secp_scalar(&bit.z_secp[0]), &bit.e_0, &members[0])?;
+            let e_1 = challenge(i, &bit.c, &bit.d, &r_0, &r_0_secp);
+            let (r_1, r_1_secp) = ring_nonces(&bit.z[1], &secp_scalar(&bit.z_secp[1]), &e_1, &members[1])?;
+            if challenge(i, &bit.c, &bit.d, &r_1, &r_1_secp) != bit.e_0 {
+                return Err(Error::InvalidProof);
+            }
+
+            c_sum += bit.c;
+            d_sum.push(bit.d.clone());
+
+            pow += pow;
+            pow_secp = &pow_secp + &pow_secp;
+        }
+
+        if &c_sum != point {
+            return Err(Error::InvalidProof);
+        }
+        match &btc::PublicKey::combine(&d_sum)? == point_secp {
+            true => Ok(()),
+            false => Err(Error::InvalidProof),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use curve25519_dalek::constants;
+    use super::{Proof, random_scalar, secp256k1_point};
+
+    #[test]
+    fn prove_and_verify() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let proof = Proof::prove(&x, &mut rng).unwrap();
+        let point = &x * &constants::ED25519_BASEPOINT_TABLE;
+        let point_secp = secp256k1_point(&x).unwrap();
+        assert!(proof.verify(&point, &point_secp).is_ok());
+    }
+
+    #[test]
+    fn reject_other_scalar() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let y = random_scalar(&mut rng).unwrap();
+        let proof = Proof::prove(&x, &mut rng).unwrap();
+        let point = &x * &constants::ED25519_BASEPOINT_TABLE;
+        let point_secp = secp256k1_point(&y).unwrap();
+        assert!(proof.verify(&point, &point_secp).is_err());
+    }
+}
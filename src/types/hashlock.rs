@@ -0,0 +1,259 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Hash locks bound to a Monero key share with a cut-and-choose proof
+//!
+//! A single `sha256(x)` hash lock can not be checked against `X' = x·G'` before
+//! the swap, any preimage would unlock the script. Instead the share is split in
+//! `ROUNDS` random pairs `x = u + v` with `U = u·G'` and `V = X' - U`, both
+//! halves are hashed and a Fiat-Shamir challenge opens one half of each pair.
+//! The scripts lock the hashes of the unopened halves: revealing them gives back
+//! `x` from any honest pair, a cheating party must cheat on every pair and pass
+//! every challenge, with a probability of `2^-ROUNDS`.
+
+use super::{Result, Error, xmr, btc, wire};
+use super::dleq::{secp_scalar, secp_add, secp_neg, random_secp, secp256k1_scalar, ed25519_scalar};
+
+use rand::{Rng, CryptoRng};
+use bitcoin_hashes::{Hash, sha256};
+use secp256k1::curve::Scalar as SecpScalar;
+
+/// Number of pairs, each one adds a hash lock in the scripts
+pub const ROUNDS: usize = 80;
+
+/// One split of the share with its opened half
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct Round {
+    /// Point of the first half, the second half is `X' - U`
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) U: btc::PublicKey,
+    /// Hashes of the first and second halves
+    #[serde(with = "wire::bytes32_pair")]
+    pub(crate) hashes: [[u8; 32]; 2],
+    /// Half opened by the challenge, big endian secp256k1 scalar
+    #[serde(with = "wire::bytes32")]
+    pub(crate) opened: [u8; 32],
+}
+
+/// Hash locks of a Monero key share with the proof they unlock with the
+/// discrete logarithm of `X'`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct HashLock {
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_secp: btc::PublicKey,
+    pub(crate) rounds: Vec<Round>,
+}
+
+fn hash(bytes: &[u8]) -> [u8; 32] {
+    let mut h = [0u8; 32];
+    h.copy_from_slice(&sha256::Hash::hash(bytes)[..]);
+    h
+}
+
+/// Challenge bits selecting the opened half of each round
+#[allow(non_snake_case)]
+fn challenge(X_secp: &btc::PublicKey, points: &[&btc::PublicKey], hashes: &[[[u8; 32]; 2]]) -> [u8; 32] {
+    let mut data = b"monero-swap-lib hash lock".to_vec();
+    data.extend_from_slice(&X_secp.serialize_compressed());
+    for (U, hashes) in points.iter().zip(hashes.iter()) {
+        data.extend_from_slice(&U.serialize_compressed());
+        data.extend_from_slice(&hashes[0]);
+        data.extend_from_slice(&hashes[1]);
+    }
+    hash(&data)
+}
+
+fn bit(challenge: &[u8; 32], i: usize) -> usize {
+    ((challenge[i / 8] >> (i % 8)) & 1) as usize
+}
+
+fn public_key(bytes: &[u8; 32]) -> Option<btc::PublicKey> {
+    btc::PrivateKey::parse(bytes).ok().map(|k| btc::PublicKey::from_secret_key(&k))
+}
+
+impl HashLock {
+    /// Split the share `x` and open one half of each pair
+    #[allow(non_snake_case)]
+    pub fn commit<R: Rng + CryptoRng>(x: &xmr::PrivateKey, rng: &mut R) -> Result<HashLock> {
+        let x_secp = secp256k1_scalar(x)?;
+        let X_secp = btc::PublicKey::from_secret_key(&x_secp);
+        let x_secp = secp_scalar(&x_secp.serialize());
+
+        let mut points = vec![];
+        let mut hashes = vec![];
+        let mut halves = vec![];
+        for _ in 0..ROUNDS {
+            let u = random_secp(rng)?;
+            let v = &x_secp + &u.neg();
+            points.push(public_key(&u.b32()).ok_or(Error::InvalidKeyShare)?);
+            hashes.push([hash(&u.b32()), hash(&v.b32())]);
+            halves.push([u.b32(), v.b32()]);
+        }
+        let c = challenge(&X_secp, &points.iter().collect::<Vec<_>>(), &hashes);
+
+        let rounds = points.into_iter()
+            .zip(hashes.into_iter())
+            .zip(halves.iter())
+            .enumerate()
+            .map(|(i, ((U, hashes), halves))| Round { U, hashes, opened: halves[bit(&c, i)] })
+            .collect();
+
+        Ok(HashLock { X_secp, rounds })
+    }
+
+    /// Verify the opened halves against `X'`, the share of `X'` is lower than
+    /// `2^252` as proven by the cross-group proof
+    #[allow(non_snake_case)]
+    pub fn verify(&self, X_secp: &btc::PublicKey) -> Result<()> {
+        if &self.X_secp != X_secp || self.rounds.len() != ROUNDS {
+            return Err(Error::InvalidProof);
+        }
+        let c = self.challenge();
+        for (i, round) in self.rounds.iter().enumerate() {
+            let b = bit(&c, i);
+            if hash(&round.opened) != round.hashes[b] {
+                return Err(Error::InvalidProof);
+            }
+            let opened = public_key(&round.opened).ok_or(Error::InvalidProof)?;
+            let expected = match b {
+                0 => round.U.clone(),
+                _ => secp_add(X_secp, &secp_neg(&round.U)?).map_err(|_| Error::InvalidProof)?,
+            };
+            if opened != expected {
+                return Err(Error::InvalidProof);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hashes of the unopened halves locked in the script, in round order
+    pub fn hashes(&self) -> Vec<[u8; 32]> {
+        let c = self.challenge();
+        self.rounds.iter()
+            .enumerate()
+            .map(|(i, round)| round.hashes[1 - bit(&c, i)])
+            .collect()
+    }
+
+    /// Unopened halves `x - opened` unlocking the hashes, in round order
+    pub fn preimages(&self, x: &xmr::PrivateKey) -> Result<Vec<[u8; 32]>> {
+        let x_secp = secp_scalar(&secp256k1_scalar(x)?.serialize());
+        Ok(self.rounds.iter()
+            .map(|round| (&x_secp + &secp_scalar(&round.opened).neg()).b32())
+            .collect())
+    }
+
+    /// Recover the share from the halves revealed on chain, the first pair
+    /// whose revealed half matches its point gives back `x`
+    #[allow(non_snake_case)]
+    pub fn recover(&self, revealed: &[[u8; 32]]) -> Result<xmr::PrivateKey> {
+        let X_secp = &self.X_secp;
+        let c = self.challenge();
+        for (i, (round, half)) in self.rounds.iter().zip(revealed.iter()).enumerate() {
+            let point = match bit(&c, i) {
+                0 => secp_add(X_secp, &secp_neg(&round.U)?).ok(),
+                _ => Some(round.U.clone()),
+            };
+            if point.is_none() || public_key(half) != point {
+                continue;
+            }
+            let x: SecpScalar = &secp_scalar(half) + &secp_scalar(&round.opened);
+            if let Ok(x_secp) = btc::PrivateKey::parse(&x.b32()) {
+                if &btc::PublicKey::from_secret_key(&x_secp) == X_secp {
+                    return ed25519_scalar(&x_secp);
+                }
+            }
+        }
+        Err(Error::InvalidKeyShare)
+    }
+
+    fn challenge(&self) -> [u8; 32] {
+        let points: Vec<&btc::PublicKey> = self.rounds.iter().map(|round| &round.U).collect();
+        let hashes: Vec<[[u8; 32]; 2]> = self.rounds.iter().map(|round| round.hashes).collect();
+        challenge(&self.X_secp, &points, &hashes)
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use rand::rngs::OsRng;
+    use crate::types::Error;
+    use crate::types::dleq::{random_scalar, secp256k1_point};
+    use super::{HashLock, ROUNDS};
+
+    #[test]
+    fn commit_verify_and_recover() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let X_secp = secp256k1_point(&x).unwrap();
+        let lock = HashLock::commit(&x, &mut rng).unwrap();
+        assert!(lock.verify(&X_secp).is_ok());
+
+        let hashes = lock.hashes();
+        let preimages = lock.preimages(&x).unwrap();
+        assert_eq!(ROUNDS, hashes.len());
+        for (hash, preimage) in hashes.iter().zip(preimages.iter()) {
+            assert_eq!(hash, &super::hash(preimage));
+        }
+        assert_eq!(x, lock.recover(&preimages).unwrap());
+    }
+
+    #[test]
+    fn reject_other_share() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let y = random_scalar(&mut rng).unwrap();
+        let Y_secp = secp256k1_point(&y).unwrap();
+        let mut lock = HashLock::commit(&x, &mut rng).unwrap();
+        assert!(lock.verify(&Y_secp).is_err());
+        // Hash locks of x presented as locks of y
+        lock.X_secp = Y_secp.clone();
+        match lock.verify(&Y_secp) {
+            Err(Error::InvalidProof) => (),
+            _ => panic!("hash locks do not unlock with the share of the point"),
+        }
+    }
+
+    #[test]
+    fn reject_tampered_hash() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let X_secp = secp256k1_point(&x).unwrap();
+        let mut lock = HashLock::commit(&x, &mut rng).unwrap();
+        lock.rounds[0].hashes[0] = [0; 32];
+        lock.rounds[0].hashes[1] = [0; 32];
+        assert!(lock.verify(&X_secp).is_err());
+    }
+
+    #[test]
+    fn recover_from_one_honest_round() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let x = random_scalar(&mut rng).unwrap();
+        let lock = HashLock::commit(&x, &mut rng).unwrap();
+        let mut revealed = vec![[1; 32]; ROUNDS];
+        revealed[ROUNDS - 1] = lock.preimages(&x).unwrap()[ROUNDS - 1];
+        assert_eq!(x, lock.recover(&revealed).unwrap());
+        match lock.recover(&[[1; 32]; ROUNDS]) {
+            Err(Error::InvalidKeyShare) => (),
+            _ => panic!("no revealed half matches its point"),
+        }
+    }
+}
@@ -17,25 +17,225 @@
 //! transactions
 
 use std::convert::Into;
+use std::fmt;
+use std::time::Duration;
+
+use bitcoin::blockdata::opcodes::{self, OP_CSV};
+use bitcoin::blockdata::script::{Instruction, Script};
+
+use wasm_bindgen::prelude::*;
+
+use self::constants::{SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_TYPE_FLAG, SEQUENCE_LOCKTIME_MASK,
+    SEQUENCE_LOCKTIME_GRANULARITY};
 
 pub mod xmr;
 pub mod btc;
 pub mod common;
+pub mod amount;
 pub mod constants;
+pub mod dleq;
+pub mod hashlock;
+pub mod adaptor;
+pub mod wire;
+pub mod state;
+
+/// Transactions of the swap, used to give context to errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxKind {
+    /// Bitcoin funding transaction locking in the swaplock output, `btx_1`
+    Funding,
+    /// Bitcoin refund transaction, `btx_2`
+    Refund,
+    /// Bitcoin transaction spending the swaplock with the secret
+    Buy,
+    /// Bitcoin transaction spending the refund with both keys
+    SpendRefund,
+    /// Bitcoin transaction claiming the refund after the second timelock
+    ClaimRefund,
+    /// Monero transaction locking funds to the shared address
+    XmrLock,
+    /// Monero transaction sweeping the shared address
+    XmrSweep,
+}
+
+impl fmt::Display for TxKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::TxKind::*;
+
+        let name = match *self {
+            Funding => "funding",
+            Refund => "refund",
+            Buy => "buy",
+            SpendRefund => "spend refund",
+            ClaimRefund => "claim refund",
+            XmrLock => "xmr lock",
+            XmrSweep => "xmr sweep",
+        };
+        f.write_str(name)
+    }
+}
 
 /// Library and dependencies' errors
 #[derive(Debug)]
 pub enum Error {
     /// Bitcoind feerate error
     CannotEstimateFeerate,
-    /// Missing part in Bitcoin transaction
-    TransactionNotComplete,
+    /// Missing part in a transaction, e.g. signatures or witnesses
+    TransactionNotComplete(TxKind),
+    /// Bitcoin transaction has not been created or set
+    MissingTransaction,
+    /// Bitcoin transaction has no input
+    MissingInput,
     /// One or more common parameters in the setup missmatch
     MissmatchCommonParameters,
-    /// Missing value
-    MissingValue,
-    /// Invalid signature in Bitcoin transaction
-    InvalidSignature,
+    /// Named value is required but has not been set
+    MissingValue(&'static str),
+    /// Named signature on a transaction input does not verify
+    InvalidSignature {
+        /// Transaction carrying the signature
+        tx: TxKind,
+        /// Index of the signed input
+        input: usize,
+        /// Name of the signature, e.g. `sig_a`
+        sig: &'static str,
+    },
+    /// Named cryptographic check on a signature or proof failed
+    SignatureCheck(&'static str),
+    /// Script of a transaction input or output is not the expected one
+    ScriptMismatch {
+        /// Transaction carrying the script
+        tx: TxKind,
+        /// Index of the input or output
+        index: usize,
+    },
+    /// Transaction has an output that is not part of the protocol
+    UnexpectedOutput {
+        /// Transaction carrying the output
+        tx: TxKind,
+        /// Index of the output
+        index: usize,
+    },
+    /// Transaction input does not spend a SegWit program, its txid can be malleated
+    NonWitnessInput {
+        /// Transaction spending the input
+        tx: TxKind,
+        /// Index of the input
+        input: usize,
+    },
+    /// Transaction input does not spend the expected outpoint
+    InvalidOutpoint {
+        /// Transaction spending the outpoint
+        tx: TxKind,
+        /// Index of the input
+        input: usize,
+    },
+    /// Sequence of a transaction input does not encode the agreed relative locktime
+    InvalidSequence {
+        /// Transaction spending the input
+        tx: TxKind,
+        /// Index of the input
+        input: usize,
+    },
+    /// Relative locktime is out of the BIP68 range or can not be decoded
+    InvalidLocktime(&'static str),
+    /// Transaction version is not 2, relative locktimes are not enforced
+    InvalidVersion(TxKind),
+    /// Signed transaction received is not the transaction created locally
+    TransactionMismatch(TxKind),
+    /// Fee paid by the transaction is outside the accepted range
+    InvalidFee {
+        /// Transaction paying the fee
+        tx: TxKind,
+        /// Fee paid in satoshi
+        fee: u64,
+    },
+    /// Output amount would be below the dust limit and not relayed
+    OutputBelowDust {
+        /// Transaction creating the output
+        tx: TxKind,
+        /// Amount of the output in satoshi
+        amount: u64,
+    },
+    /// Spent amount does not cover the transaction fee
+    AmountBelowFee {
+        /// Transaction paying the fee
+        tx: TxKind,
+        /// Amount available in the spent output
+        amount: u64,
+        /// Fee required at the given feerate
+        fee: u64,
+    },
+    /// Monero transaction can not be decoded
+    InvalidMoneroTransaction,
+    /// No output in the Monero transaction is sent to the shared address
+    XmrOutputNotFound,
+    /// Decoded amount does not match the output commitment
+    InvalidAmountCommitment,
+    /// Monero transaction outputs are locked until a height or a time
+    InvalidUnlockTime {
+        /// Transaction with the locked outputs
+        tx: TxKind,
+        /// Unlock time found in the transaction
+        unlock_time: u64,
+    },
+    /// Bitcoin transaction is not final before its nLockTime
+    NonZeroLockTime {
+        /// Transaction with the lock time
+        tx: TxKind,
+        /// nLockTime found in the transaction
+        lock_time: u32,
+    },
+    /// Amount locked is lower than the agreed amount
+    InsufficientAmount {
+        /// Transaction locking the amount
+        tx: TxKind,
+        /// Agreed amount
+        expected: u64,
+        /// Amount found in the transaction
+        found: u64,
+    },
+    /// Network is unknown or an address is not encoded for the network of the swap
+    InvalidNetwork,
+    /// Monero address can not be decoded, names the failed check
+    InvalidMoneroAddress(&'static str),
+    /// Transaction does not have enough confirmations
+    NotEnoughConfirmations,
+    /// Revealed Monero key share does not match the exported public share
+    InvalidKeyShare,
+    /// Zero-knowledge proof in the setup is invalid
+    InvalidProof,
+    /// Feerate is below the minimum relay feerate
+    FeerateTooLow,
+    /// Available coins do not cover the amount and the fees
+    InsufficientFunds,
+    /// PSBT can not be decoded or does not match the funding transaction
+    InvalidPsbt,
+    /// Wire message is malformed
+    InvalidMessage,
+    /// Wire message version is not supported
+    UnsupportedVersion(u16),
+    /// Swap can not move to the requested state
+    InvalidTransition,
+    /// Swap identifier is not valid in a store
+    InvalidSwapId,
+    /// Stored record can not be decrypted, wrong passphrase or modified data
+    DecryptionFailed,
+    /// Record can not be encrypted
+    EncryptionFailed,
+    /// Storage I/O error
+    Io(std::io::Error),
+    /// Transaction rejected by the chain backend
+    TransactionRejected(String),
+    /// Chain backend communication error
+    Backend(String),
+    /// Released secret does not match its hash commitment
+    InvalidSecret,
+    /// Message not expected in the current state of the swap
+    UnexpectedMessage,
+    /// JSON encoding/decoding error
+    Json(serde_json::Error),
+    /// Binary encoding/decoding error
+    Binary(bincode::Error),
     /// Bitcoin encoding/decoding error
     BitcoinConsensus(bitcoin::consensus::encode::Error),
     /// Signing library secp256k1 error
@@ -46,9 +246,181 @@ pub enum Error {
     Serde(hex::FromHexError),
 }
 
+impl Error {
+    /// Stable identifier of the error kind, does not change with the context carried by the
+    /// variant
+    pub fn code(&self) -> &'static str {
+        use self::Error::*;
+
+        match *self {
+            CannotEstimateFeerate => "cannot_estimate_feerate",
+            TransactionNotComplete(_) => "transaction_not_complete",
+            MissingTransaction => "missing_transaction",
+            MissingInput => "missing_input",
+            MissmatchCommonParameters => "missmatch_common_parameters",
+            MissingValue(_) => "missing_value",
+            InvalidSignature { .. } => "invalid_signature",
+            SignatureCheck(_) => "signature_check",
+            ScriptMismatch { .. } => "script_mismatch",
+            UnexpectedOutput { .. } => "unexpected_output",
+            NonWitnessInput { .. } => "non_witness_input",
+            InvalidOutpoint { .. } => "invalid_outpoint",
+            InvalidSequence { .. } => "invalid_sequence",
+            InvalidLocktime(_) => "invalid_locktime",
+            InvalidVersion(_) => "invalid_version",
+            TransactionMismatch(_) => "transaction_mismatch",
+            InvalidFee { .. } => "invalid_fee",
+            OutputBelowDust { .. } => "output_below_dust",
+            AmountBelowFee { .. } => "amount_below_fee",
+            InvalidMoneroTransaction => "invalid_monero_transaction",
+            XmrOutputNotFound => "xmr_output_not_found",
+            InvalidAmountCommitment => "invalid_amount_commitment",
+            InvalidUnlockTime { .. } => "invalid_unlock_time",
+            NonZeroLockTime { .. } => "non_zero_lock_time",
+            InsufficientAmount { .. } => "insufficient_amount",
+            InvalidNetwork => "invalid_network",
+            InvalidMoneroAddress(_) => "invalid_monero_address",
+            NotEnoughConfirmations => "not_enough_confirmations",
+            InvalidKeyShare => "invalid_key_share",
+            InvalidProof => "invalid_proof",
+            FeerateTooLow => "feerate_too_low",
+            InsufficientFunds => "insufficient_funds",
+            InvalidPsbt => "invalid_psbt",
+            InvalidMessage => "invalid_message",
+            UnsupportedVersion(_) => "unsupported_version",
+            InvalidTransition => "invalid_transition",
+            InvalidSwapId => "invalid_swap_id",
+            DecryptionFailed => "decryption_failed",
+            EncryptionFailed => "encryption_failed",
+            Io(_) => "io",
+            TransactionRejected(_) => "transaction_rejected",
+            Backend(_) => "backend",
+            InvalidSecret => "invalid_secret",
+            UnexpectedMessage => "unexpected_message",
+            Json(_) => "json",
+            Binary(_) => "binary",
+            BitcoinConsensus(_) => "bitcoin_consensus",
+            Secp256k1(_) => "secp256k1",
+            Rand(_) => "rand",
+            Serde(_) => "hex",
+        }
+    }
+
+    /// Give a transaction context to a failed signature check, other errors are left untouched
+    pub(crate) fn on_signature(self, tx: TxKind, input: usize, sig: &'static str) -> Error {
+        match self {
+            Error::SignatureCheck(_) => Error::InvalidSignature { tx, input, sig },
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+
+        match self {
+            CannotEstimateFeerate => write!(f, "cannot estimate the feerate"),
+            TransactionNotComplete(tx) => write!(f, "{} tx is not complete", tx),
+            MissingTransaction => write!(f, "transaction has not been created or set"),
+            MissingInput => write!(f, "transaction has no input"),
+            MissmatchCommonParameters => write!(f, "common parameters missmatch"),
+            MissingValue(name) => write!(f, "missing value: {}", name),
+            InvalidSignature { tx, input, sig } => write!(f, "{} tx {} invalid on input {}", tx, sig, input),
+            SignatureCheck(check) => write!(f, "{} check failed", check),
+            ScriptMismatch { tx, index } => write!(f, "{} tx script mismatch at index {}", tx, index),
+            UnexpectedOutput { tx, index } => write!(f, "{} tx has an unexpected output at index {}", tx, index),
+            NonWitnessInput { tx, input } => write!(f, "{} tx input {} is not a segwit program", tx, input),
+            InvalidOutpoint { tx, input } => write!(f, "{} tx input {} spends an unexpected outpoint", tx, input),
+            InvalidSequence { tx, input } => write!(f, "{} tx input {} has an invalid sequence", tx, input),
+            InvalidLocktime(check) => write!(f, "invalid relative locktime: {}", check),
+            InvalidVersion(tx) => write!(f, "{} tx version is not 2", tx),
+            TransactionMismatch(tx) => write!(f, "signed {} tx is not the transaction created", tx),
+            InvalidFee { tx, fee } => write!(f, "{} tx fee {} is out of range", tx, fee),
+            OutputBelowDust { tx, amount } => write!(f, "{} tx output of {} is below dust", tx, amount),
+            AmountBelowFee { tx, amount, fee } =>
+                write!(f, "insufficient amount for fee in {} tx: {} < {}", tx, amount, fee),
+            InvalidMoneroTransaction => write!(f, "monero transaction can not be decoded"),
+            XmrOutputNotFound => write!(f, "no output sent to the shared address"),
+            InvalidAmountCommitment => write!(f, "decoded amount does not match the commitment"),
+            InvalidUnlockTime { tx, unlock_time } =>
+                write!(f, "{} tx outputs are locked until {}", tx, unlock_time),
+            NonZeroLockTime { tx, lock_time } =>
+                write!(f, "{} tx is not final before lock time {}", tx, lock_time),
+            InsufficientAmount { tx, expected, found } =>
+                write!(f, "{} tx amount too low: expected {}, found {}", tx, expected, found),
+            InvalidNetwork => write!(f, "unknown network or address on another network"),
+            InvalidMoneroAddress(check) => write!(f, "invalid monero address: {}", check),
+            NotEnoughConfirmations => write!(f, "not enough confirmations"),
+            InvalidKeyShare => write!(f, "monero key share does not match the public share"),
+            InvalidProof => write!(f, "invalid zero-knowledge proof"),
+            FeerateTooLow => write!(f, "feerate below the minimum relay feerate"),
+            InsufficientFunds => write!(f, "insufficient funds to cover the amount and fees"),
+            InvalidPsbt => write!(f, "invalid psbt"),
+            InvalidMessage => write!(f, "malformed wire message"),
+            UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            InvalidTransition => write!(f, "invalid swap state transition"),
+            InvalidSwapId => write!(f, "invalid swap identifier"),
+            DecryptionFailed => write!(f, "record decryption failed"),
+            EncryptionFailed => write!(f, "record encryption failed"),
+            Io(e) => write!(f, "i/o error: {}", e),
+            TransactionRejected(reason) => write!(f, "transaction rejected: {}", reason),
+            Backend(reason) => write!(f, "backend error: {}", reason),
+            InvalidSecret => write!(f, "secret does not match its hash"),
+            UnexpectedMessage => write!(f, "unexpected message"),
+            Json(e) => write!(f, "json error: {}", e),
+            Binary(e) => write!(f, "binary encoding error: {}", e),
+            BitcoinConsensus(e) => write!(f, "bitcoin encoding error: {}", e),
+            Secp256k1(e) => write!(f, "secp256k1 error: {:?}", e),
+            Rand(e) => write!(f, "random generation error: {}", e),
+            Serde(e) => write!(f, "hex error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::Error::*;
+
+        match self {
+            Io(e) => Some(e),
+            Json(e) => Some(e),
+            Binary(e) => Some(e),
+            BitcoinConsensus(e) => Some(e),
+            Rand(e) => Some(e),
+            Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Error exported to javascript, `code` is stable across versions and `message` is human
+/// readable
+#[wasm_bindgen]
+pub struct JsError {
+    code: &'static str,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl JsError {
+    #[wasm_bindgen(getter)]
+    pub fn code(&self) -> String {
+        self.code.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
 impl From<Error> for wasm_bindgen::JsValue {
     fn from(e: Error) -> wasm_bindgen::JsValue {
-        format!("{:?}", e).into()
+        JsError {
+            code: e.code(),
+            message: e.to_string(),
+        }.into()
     }
 }
 
@@ -58,12 +430,6 @@ impl From<rand::Error> for Error {
     }
 }
 
-impl From<std::option::NoneError> for Error {
-    fn from(_: std::option::NoneError) -> Error {
-        Error::MissingValue
-    }
-}
-
 impl From<secp256k1::Error> for Error {
     fn from(e: secp256k1::Error) -> Error {
         Error::Secp256k1(e)
@@ -76,6 +442,24 @@ impl From<bitcoin::consensus::encode::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Error {
+        Error::Binary(e)
+    }
+}
+
 impl From<hex::FromHexError> for Error {
     fn from(e: hex::FromHexError) -> Error {
         Error::Serde(e)
@@ -91,31 +475,158 @@ impl<T> Into<Result<T>> for Error {
 /// Shortcut definition to handle results with library defined errors
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Define the two types of Locktime in a bitcoin transaction
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Define the two types of Locktime in a bitcoin transaction, as encoded in nSequence by BIP68
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum RelativeLocktime {
-    /// Based on block's timestamp
+    /// Based on block's timestamp, in units of 512 seconds
     Time(u16),
     /// Based on block's height
     Blocks(u16),
 }
 
 impl RelativeLocktime {
+    /// Locktime expiring after `blocks` blocks, fails if it is zero or above the 16 bits of
+    /// BIP68
+    pub fn from_blocks(blocks: u32) -> Result<RelativeLocktime> {
+        match blocks {
+            0 => Err(Error::InvalidLocktime("zero blocks")),
+            blocks if blocks > SEQUENCE_LOCKTIME_MASK => Err(Error::InvalidLocktime("too many blocks")),
+            blocks => Ok(RelativeLocktime::Blocks(blocks as u16)),
+        }
+    }
+
+    /// Locktime expiring after at least `duration`, rounded up to the next 512 seconds unit,
+    /// fails if it is zero or above the 16 bits of BIP68 (about 388 days)
+    pub fn from_duration(duration: Duration) -> Result<RelativeLocktime> {
+        let granularity = 1u64 << SEQUENCE_LOCKTIME_GRANULARITY;
+        let partial = duration.as_secs() % granularity > 0 || duration.subsec_nanos() > 0;
+        let units = duration.as_secs() / granularity + if partial { 1 } else { 0 };
+        match units {
+            0 => Err(Error::InvalidLocktime("zero duration")),
+            units if units > u64::from(SEQUENCE_LOCKTIME_MASK) => Err(Error::InvalidLocktime("duration too long")),
+            units => Ok(RelativeLocktime::Time(units as u16)),
+        }
+    }
+
+    /// Fail if the locktime is zero, non-zero values always fit in the 16 bits of BIP68
+    pub fn check(&self) -> Result<()> {
+        match *self {
+            RelativeLocktime::Blocks(0) => Err(Error::InvalidLocktime("zero blocks")),
+            RelativeLocktime::Time(0) => Err(Error::InvalidLocktime("zero duration")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decode the relative locktime of an nSequence, bits not defined by BIP68 are ignored as
+    /// they are by consensus, fails if the relative locktime is disabled
+    pub fn from_sequence(sequence: u32) -> Result<RelativeLocktime> {
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Err(Error::InvalidLocktime("disabled"));
+        }
+        let value = (sequence & SEQUENCE_LOCKTIME_MASK) as u16;
+        match sequence & SEQUENCE_LOCKTIME_TYPE_FLAG {
+            0 => Ok(RelativeLocktime::Blocks(value)),
+            _ => Ok(RelativeLocktime::Time(value)),
+        }
+    }
+
+    /// Decode the argument of the `OP_CHECKSEQUENCEVERIFY` in a script, fails if the script
+    /// does not contain exactly one, if the argument is not a minimal push of a positive
+    /// number or if it disables the check
+    pub fn from_script(script: &Script) -> Result<RelativeLocktime> {
+        // Pushes are only decoded as numbers when they are the argument of the check
+        let mut previous = None;
+        let mut locktime = None;
+        for instruction in script.iter(true) {
+            match instruction {
+                Instruction::Op(op) if op == OP_CSV => {
+                    if locktime.is_some() {
+                        return Err(Error::InvalidLocktime("several checksequenceverify"));
+                    }
+                    let arg = previous.ok_or(Error::InvalidLocktime("missing checksequenceverify argument"))??;
+                    locktime = Some(RelativeLocktime::from_sequence(arg)?);
+                },
+                Instruction::Op(op) => previous = pushnum(op).map(Ok),
+                Instruction::PushBytes(bytes) => previous = Some(read_csv_arg(bytes)),
+                Instruction::Error(_) => return Err(Error::InvalidLocktime("invalid script")),
+            }
+        }
+        locktime.ok_or(Error::InvalidLocktime("missing checksequenceverify"))
+    }
+
     /// Get the raw value used in nSequence
     pub fn as_u32(&self) -> u32 {
         use self::RelativeLocktime::*;
 
         match *self {
-            Time(sec) => (0b1 << 22) | sec as u32,
+            Time(units) => SEQUENCE_LOCKTIME_TYPE_FLAG | units as u32,
             Blocks(block_number) => block_number as u32,
         }
     }
+
+    /// Minimum number of seconds before a time based locktime expires, none for block based
+    /// locktimes
+    pub fn as_secs(&self) -> Option<u64> {
+        match *self {
+            RelativeLocktime::Time(units) => Some(u64::from(units) << SEQUENCE_LOCKTIME_GRANULARITY),
+            RelativeLocktime::Blocks(_) => None,
+        }
+    }
+
+    /// Check if an input with the given nSequence passes the `OP_CHECKSEQUENCEVERIFY` of this
+    /// locktime as defined in BIP112: same type and a value not lower
+    pub fn is_satisfied_by(&self, sequence: u32) -> bool {
+        use self::RelativeLocktime::*;
+
+        match (*self, RelativeLocktime::from_sequence(sequence)) {
+            (Blocks(required), Ok(Blocks(value))) => value >= required,
+            (Time(required), Ok(Time(value))) => value >= required,
+            _ => false,
+        }
+    }
+
+    /// Check that the input `input` of `tx`, with the nSequence `sequence`, can spend the
+    /// branch of `script` locked with `OP_CHECKSEQUENCEVERIFY`
+    pub(crate) fn check_spend(tx: TxKind, input: usize, script: &Script, sequence: u32) -> Result<()> {
+        match RelativeLocktime::from_script(script)?.is_satisfied_by(sequence) {
+            true => Ok(()),
+            false => Err(Error::InvalidSequence { tx, input }),
+        }
+    }
+}
+
+/// Value of the `OP_PUSHNUM_N` opcodes, small numbers are pushed with them in minimal scripts
+fn pushnum(op: opcodes::All) -> Option<u32> {
+    (1..=16).find(|n| op == opcodes::All::from(0x50 + *n as u8))
+}
+
+/// Decode a positive and minimally encoded script number of at most 5 bytes, as accepted by
+/// `OP_CHECKSEQUENCEVERIFY` which only considers its lower 32 bits
+fn read_csv_arg(bytes: &[u8]) -> Result<u32> {
+    if bytes.len() > 5 {
+        return Err(Error::InvalidLocktime("argument too long"));
+    }
+    if let Some(last) = bytes.last() {
+        if last & 0x80 != 0 {
+            return Err(Error::InvalidLocktime("negative argument"));
+        }
+        // A zero most significant byte is only allowed to clear the sign bit of the previous one
+        if *last == 0 && (bytes.len() == 1 || bytes[bytes.len() - 2] & 0x80 == 0) {
+            return Err(Error::InvalidLocktime("non-minimal argument"));
+        }
+    }
+    let value = bytes.iter().rev().fold(0u64, |value, byte| value << 8 | u64::from(*byte));
+    Ok(value as u32)
 }
 
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
-    use super::{RelativeLocktime, common};
+    use super::{RelativeLocktime, Error, TxKind, common, btc, xmr};
+    use super::amount::{Satoshi, Piconero};
+    use bitcoin::blockdata::opcodes::{self, OP_CSV};
+    use bitcoin::blockdata::script::Builder;
+    use std::time::Duration;
 
     #[test]
     fn relative_locktime_blocks() {
@@ -129,12 +640,134 @@ mod tests {
         assert_eq!(0b00000000_01000000_00000000_00100000, n_sequence.as_u32());
     }
 
+    #[test]
+    fn relative_locktime_constructors() {
+        assert_eq!(RelativeLocktime::Blocks(144), RelativeLocktime::from_blocks(144).unwrap());
+        assert_eq!(RelativeLocktime::Blocks(0xffff), RelativeLocktime::from_blocks(0xffff).unwrap());
+        assert!(RelativeLocktime::from_blocks(0).is_err());
+        assert!(RelativeLocktime::from_blocks(0x1_0000).is_err());
+
+        // Durations are rounded up to the next 512 seconds unit
+        let time = |secs| RelativeLocktime::from_duration(Duration::from_secs(secs));
+        assert_eq!(RelativeLocktime::Time(1), time(1).unwrap());
+        assert_eq!(RelativeLocktime::Time(1), time(512).unwrap());
+        assert_eq!(RelativeLocktime::Time(8), time(3_600).unwrap());
+        assert_eq!(RelativeLocktime::Time(2), RelativeLocktime::from_duration(Duration::new(512, 1)).unwrap());
+        assert_eq!(RelativeLocktime::Time(0xffff), time(0xffff * 512).unwrap());
+        assert_eq!(Some(3_584), RelativeLocktime::Time(7).as_secs());
+        assert_eq!(None, RelativeLocktime::Blocks(7).as_secs());
+        match time(0xffff * 512 + 1) {
+            Err(Error::InvalidLocktime(_)) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+        assert!(time(0).is_err());
+        assert!(time(std::u64::MAX).is_err());
+    }
+
+    #[test]
+    fn relative_locktime_from_sequence() {
+        for locktime in [RelativeLocktime::Blocks(10), RelativeLocktime::Time(10), RelativeLocktime::Time(0xffff)].iter() {
+            assert_eq!(*locktime, RelativeLocktime::from_sequence(locktime.as_u32()).unwrap());
+        }
+        // Bits not defined by BIP68 are ignored
+        assert_eq!(RelativeLocktime::Blocks(10), RelativeLocktime::from_sequence(0x0001_000a).unwrap());
+        assert!(RelativeLocktime::from_sequence(std::u32::MAX).is_err());
+        assert!(RelativeLocktime::from_sequence(1 << 31 | 10).is_err());
+    }
+
+    #[test]
+    fn relative_locktime_from_script() {
+        let pubkey = btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&[1; 32]).unwrap());
+        // Small values are pushed with OP_PUSHNUM_N
+        for locktime in [RelativeLocktime::Blocks(6), RelativeLocktime::Blocks(144), RelativeLocktime::Time(8)].iter() {
+            let refund = btc::scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], locktime.as_u32());
+            assert_eq!(*locktime, RelativeLocktime::from_script(&refund).unwrap());
+            let swaplock = btc::scripts::create_adaptor_swaplock(&pubkey, &pubkey, locktime.as_u32());
+            assert_eq!(*locktime, RelativeLocktime::from_script(&swaplock).unwrap());
+        }
+        let no_csv = Builder::new().push_int(10).push_opcode(opcodes::all::OP_DROP).into_script();
+        assert!(RelativeLocktime::from_script(&no_csv).is_err());
+        let negative = Builder::new().push_int(-10).push_opcode(OP_CSV).into_script();
+        assert!(RelativeLocktime::from_script(&negative).is_err());
+        let disabled = Builder::new().push_int(1 << 31 | 10).push_opcode(OP_CSV).into_script();
+        assert!(RelativeLocktime::from_script(&disabled).is_err());
+    }
+
+    #[test]
+    fn relative_locktime_satisfied_by() {
+        let pubkey = btc::PublicKey::from_secret_key(&btc::PrivateKey::parse(&[1; 32]).unwrap());
+        let refund = btc::scripts::create_refund(&pubkey, &pubkey, &[[3; 32]], RelativeLocktime::Time(8).as_u32());
+
+        assert!(RelativeLocktime::Time(8).is_satisfied_by(RelativeLocktime::Time(9).as_u32()));
+        assert!(!RelativeLocktime::Time(8).is_satisfied_by(RelativeLocktime::Time(7).as_u32()));
+        // Same value but counted in blocks
+        assert!(!RelativeLocktime::Time(8).is_satisfied_by(RelativeLocktime::Blocks(8).as_u32()));
+        assert!(!RelativeLocktime::Blocks(8).is_satisfied_by(std::u32::MAX));
+
+        assert!(RelativeLocktime::check_spend(TxKind::ClaimRefund, 0, &refund, RelativeLocktime::Time(8).as_u32()).is_ok());
+        match RelativeLocktime::check_spend(TxKind::ClaimRefund, 0, &refund, RelativeLocktime::Blocks(8).as_u32()) {
+            Err(Error::InvalidSequence { tx: TxKind::ClaimRefund, input: 0 }) => (),
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
+
     #[test]
     fn new_common_param() {
         let t_0 = RelativeLocktime::Blocks(144);
         let t_1 = RelativeLocktime::Blocks(32);
-        let params = common::Params::new(t_0, t_1);
+        let networks = common::Networks::new(common::BtcNetwork::Testnet, xmr::address::Network::Stagenet);
+        let amounts = common::Amounts::new(Satoshi(50_000), Piconero(1_000_000_000_000));
+        let params = common::Params::new(t_0, t_1, networks, amounts).unwrap();
         assert_eq!(144u32, params.t_0.as_u32());
         assert_eq!(32u32, params.t_1.as_u32());
+
+        match common::Params::new(RelativeLocktime::Blocks(0), t_1, networks, amounts) {
+            Err(Error::InvalidLocktime(_)) => (),
+            _ => panic!("zero locktime is not a valid BIP68 locktime"),
+        }
+        assert!(common::Params::new(t_0, RelativeLocktime::Time(0), networks, amounts).is_err());
+    }
+
+    #[test]
+    fn exchange_rate() {
+        assert_eq!(Some(50_000), common::Amounts::new(Satoshi(50_000), Piconero(1_000_000_000_000)).sat_per_xmr());
+        assert_eq!(Some(2_000_000), common::Amounts::new(Satoshi(1_000_000), Piconero(500_000_000_000)).sat_per_xmr());
+        assert_eq!(None, common::Amounts::new(Satoshi(1_000_000), Piconero(0)).sat_per_xmr());
+    }
+
+    #[test]
+    fn error_display_context() {
+        let err = Error::InvalidSignature { tx: TxKind::Refund, input: 0, sig: "sig_a" };
+        assert_eq!("refund tx sig_a invalid on input 0", err.to_string());
+        assert_eq!("invalid_signature", err.code());
+
+        let err = Error::AmountBelowFee { tx: TxKind::Buy, amount: 100, fee: 250 };
+        assert_eq!("insufficient amount for fee in buy tx: 100 < 250", err.to_string());
+        assert_eq!("amount_below_fee", err.code());
+    }
+
+    #[test]
+    fn signature_check_context() {
+        let err = Error::SignatureCheck("encrypted signature").on_signature(TxKind::SpendRefund, 0, "sig_a");
+        assert_eq!("spend refund tx sig_a invalid on input 0", err.to_string());
+        let err = Error::InvalidProof.on_signature(TxKind::SpendRefund, 0, "sig_a");
+        assert_eq!("invalid_proof", err.code());
+    }
+
+    #[test]
+    fn btc_network_addresses() {
+        use std::str::FromStr;
+        use bitcoin::Address;
+        use super::common::BtcNetwork;
+
+        let testnet = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        assert!(BtcNetwork::Testnet.accepts(&testnet));
+        assert!(BtcNetwork::Signet.accepts(&testnet));
+        assert!(!BtcNetwork::Mainnet.accepts(&testnet));
+        assert!(!BtcNetwork::Regtest.accepts(&testnet));
+
+        let regtest = Address::from_str("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080").unwrap();
+        assert!(BtcNetwork::Regtest.accepts(&regtest));
+        assert!(BtcNetwork::Testnet.check(&regtest).is_err());
     }
 }
@@ -0,0 +1,205 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Swap state machine shared by both roles and serializable snapshots of a swap in
+//! progress. Snapshots contain the secrets of the swap and must be stored safely, they
+//! allow a crashed node to reload a swap and continue or refund before the timelocks
+//! expire.
+
+use crate::types::{Result, Error};
+use crate::types::{btc, xmr, wire};
+
+/// Steps of a swap, from the setup to one of the final states
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapState {
+    /// Setup parameters generated, waiting for the counterparty's parameters
+    Initialized,
+    /// Both setups exchanged and verified
+    SetupVerified,
+    /// Funding and refund transactions created by the buyer
+    TransactionsCreated,
+    /// Refund transaction signed by both parties
+    RefundSigned,
+    /// Funding transaction broadcast
+    Funded,
+    /// Monero locked on the shared address
+    XmrLocked,
+    /// Secret `s` released by the buyer
+    SecretReleased,
+    /// Buy transaction spent the swaplock, final
+    Bought,
+    /// Refund transaction broadcast after `t_0`
+    RefundStarted,
+    /// Bitcoins refunded to the buyer, final
+    Refunded,
+    /// Refund claimed by the seller after `t_1`, final
+    Punished,
+}
+
+impl SwapState {
+    /// Check if the state can move to `next`
+    pub fn can_transition(&self, next: SwapState) -> bool {
+        use self::SwapState::*;
+
+        match (*self, next) {
+            (Initialized, SetupVerified) => true,
+            // The seller goes directly to a signed refund when verifying the transactions
+            (SetupVerified, TransactionsCreated) | (SetupVerified, RefundSigned) => true,
+            (TransactionsCreated, RefundSigned) => true,
+            (RefundSigned, Funded) => true,
+            (Funded, XmrLocked) => true,
+            (XmrLocked, SecretReleased) => true,
+            (SecretReleased, Bought) => true,
+            (Funded, RefundStarted) | (XmrLocked, RefundStarted) | (SecretReleased, RefundStarted) => true,
+            (RefundStarted, Refunded) | (RefundStarted, Punished) => true,
+            _ => false,
+        }
+    }
+
+    /// Move to the next state
+    pub fn transition(&mut self, next: SwapState) -> Result<()> {
+        if !self.can_transition(next) {
+            return Err(Error::InvalidTransition);
+        }
+        *self = next;
+        Ok(())
+    }
+
+    /// Bitcoins are locked and the refund transaction can be broadcast after `t_0`
+    pub fn can_refund(&self) -> bool {
+        self.can_transition(SwapState::RefundStarted)
+    }
+
+    /// No further action is possible
+    pub fn is_final(&self) -> bool {
+        use self::SwapState::*;
+
+        match *self {
+            Bought | Refunded | Punished => true,
+            _ => false,
+        }
+    }
+}
+
+/// Transactions known at some point of the swap, hex encoded
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwapTransactions {
+    pub btx_1: Option<String>,
+    pub btx_2: Option<String>,
+    pub btx_2_signed: Option<String>,
+    pub xtx: Option<xmr::LockedXmr>,
+    pub buy: Option<String>,
+    pub spend_refund: Option<String>,
+    pub claim_refund: Option<String>,
+}
+
+/// Snapshot of a swap with all the secrets needed to resume it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot<P, S> {
+    version: u16,
+    pub state: SwapState,
+    pub parameters: Option<P>,
+    pub setup: Option<S>,
+    pub transactions: SwapTransactions,
+}
+
+/// Buyer's snapshot
+pub type BuyerSnapshot = Snapshot<btc::SetupParams, btc::Setup>;
+
+/// Seller's snapshot
+pub type SellerSnapshot = Snapshot<xmr::SetupParams, xmr::Setup>;
+
+impl<P, S> Snapshot<P, S> where P: serde::Serialize, S: serde::Serialize {
+    /// Create a snapshot, parameters and setup can be borrowed to serialize a running swap
+    pub fn new(state: SwapState, parameters: Option<P>, setup: Option<S>, transactions: SwapTransactions) -> Snapshot<P, S> {
+        Snapshot { version: wire::VERSION, state, parameters, setup, transactions }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+impl<P, S> Snapshot<P, S> where P: serde::de::DeserializeOwned, S: serde::de::DeserializeOwned {
+    pub fn from_json(s: &str) -> Result<Snapshot<P, S>> {
+        let snapshot: Snapshot<P, S> = serde_json::from_str(s)?;
+        if snapshot.version != wire::VERSION {
+            return Err(Error::UnsupportedVersion(snapshot.version));
+        }
+        // A verified setup is required past the first state
+        if snapshot.state != SwapState::Initialized && snapshot.setup.is_none() {
+            return Err(Error::MissingValue("setup"));
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::{Protocol, Btc, Xmr};
+    use crate::types::{common, btc, xmr, RelativeLocktime};
+    use crate::types::amount::{Satoshi, Piconero};
+    use super::{SwapState, SwapTransactions, BuyerSnapshot};
+
+    #[test]
+    fn transitions() {
+        let mut state = SwapState::Initialized;
+        assert!(state.transition(SwapState::Funded).is_err());
+        state.transition(SwapState::SetupVerified).unwrap();
+        state.transition(SwapState::RefundSigned).unwrap();
+        assert!(!state.can_refund());
+        state.transition(SwapState::Funded).unwrap();
+        assert!(state.can_refund());
+        state.transition(SwapState::RefundStarted).unwrap();
+        state.transition(SwapState::Punished).unwrap();
+        assert!(state.is_final());
+        assert!(state.transition(SwapState::Refunded).is_err());
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let params = common::Params::new(
+            RelativeLocktime::Blocks(10),
+            RelativeLocktime::Blocks(10),
+            common::Networks::new(common::BtcNetwork::Testnet, xmr::address::Network::Stagenet),
+            common::Amounts::new(Satoshi(50_000), Piconero(1_000_000_000_000)),
+        ).unwrap();
+        let btc_params = Btc::setup(params.clone(), &mut rng).unwrap();
+        let xmr_params = Xmr::setup(params, &mut rng).unwrap();
+        let setup = Btc::verify_setup(&btc_params, &xmr::ExportedSetupParams::from(&xmr_params)).unwrap();
+        let s = setup.get_s();
+
+        let snapshot = BuyerSnapshot::new(
+            SwapState::SetupVerified,
+            Some(btc_params),
+            Some(setup),
+            SwapTransactions::default(),
+        );
+        let json = snapshot.to_json().unwrap();
+        let restored = BuyerSnapshot::from_json(&json).unwrap();
+        assert_eq!(SwapState::SetupVerified, restored.state);
+        assert_eq!(s, restored.setup.unwrap().get_s());
+        let _ = btc::ExportedSetupParams::from(&restored.parameters.unwrap());
+
+        let incomplete = BuyerSnapshot::new(SwapState::Funded, None, None, SwapTransactions::default());
+        assert!(BuyerSnapshot::from_json(&incomplete.to_json().unwrap()).is_err());
+    }
+}
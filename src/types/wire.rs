@@ -0,0 +1,426 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Versioned wire format of the messages exchanged between the buyer and the seller.
+//! Messages are encoded in JSON, with keys and hashes as hex strings, or in a compact
+//! binary encoding. Keys, scalars and transactions are validated when decoded.
+
+use crate::types::{Result, Error};
+use crate::types::{btc, xmr, adaptor};
+use crate::types::btc::adaptor::EncryptedSignature;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+/// Current version of the wire format
+pub const VERSION: u16 = 1;
+
+/// Messages sent from one party to the other
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    /// Buyer's public setup parameters
+    BtcSetup(btc::ExportedSetupParams),
+    /// Seller's public setup parameters
+    XmrSetup(xmr::ExportedSetupParams),
+    /// Funding and refund transactions with the buyer's refund signature
+    InitialTransactions(btc::InitialTransactions),
+    /// Refund transaction signed by the seller
+    VerifiedTransaction(xmr::VerifiedTransaction),
+    /// Monero lock transaction
+    LockedXmr(xmr::LockedXmr),
+    /// Secret `s` released by the buyer once the Monero are locked
+    #[serde(with = "bytes32")]
+    Secret([u8; 32]),
+    /// Adaptor variant initial transactions
+    AdaptorInitialTransactions(adaptor::InitialTransactions),
+    /// Adaptor variant refund signature and encrypted spend refund signature
+    AdaptorVerifiedTransactions(adaptor::VerifiedTransactions),
+    /// Adaptor variant encrypted signature on the buy transaction
+    EncryptedSignature(EncryptedSignature),
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u16,
+    message: &'a Message,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OwnedEnvelope {
+    version: u16,
+    message: Message,
+}
+
+impl Message {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&Envelope { version: VERSION, message: self })?)
+    }
+
+    /// Decode a JSON message, invalid keys, scalars or transactions are reported as
+    /// invalid messages
+    pub fn from_json(s: &str) -> Result<Message> {
+        let envelope: OwnedEnvelope = serde_json::from_str(s).map_err(|e| {
+            if e.is_data() { Error::InvalidMessage } else { Error::Json(e) }
+        })?;
+        Message::check_version(envelope)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&Envelope { version: VERSION, message: self })?)
+    }
+
+    /// Decode a binary message, invalid keys, scalars or transactions are reported as
+    /// invalid messages
+    pub fn from_bytes(bytes: &[u8]) -> Result<Message> {
+        let envelope: OwnedEnvelope = bincode::deserialize(bytes).map_err(|e| match *e {
+            bincode::ErrorKind::Custom(_) => Error::InvalidMessage,
+            _ => Error::Binary(e),
+        })?;
+        // Reject trailing bytes
+        if bincode::serialized_size(&Envelope { version: envelope.version, message: &envelope.message })? != bytes.len() as u64 {
+            return Err(Error::InvalidMessage);
+        }
+        Message::check_version(envelope)
+    }
+
+    fn check_version(envelope: OwnedEnvelope) -> Result<Message> {
+        match envelope.version {
+            VERSION => Ok(envelope.message),
+            version => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Byte strings, hex encoded in human readable formats
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Bytes, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            Ok(Bytes(hex::decode(&s).map_err(D::Error::custom)?))
+        } else {
+            Ok(Bytes(Vec::<u8>::deserialize(deserializer)?))
+        }
+    }
+}
+
+fn array32<E: DeError>(bytes: &[u8]) -> std::result::Result<[u8; 32], E> {
+    if bytes.len() != 32 {
+        return Err(E::invalid_length(bytes.len(), &"32 bytes"));
+    }
+    let mut array = [0u8; 32];
+    array.copy_from_slice(bytes);
+    Ok(array)
+}
+
+pub(crate) mod bytes32 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(bytes.to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<[u8; 32], D::Error> {
+        array32(&Bytes::deserialize(deserializer)?.0)
+    }
+}
+
+pub(crate) mod bytes32_pair {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(pair: &[[u8; 32]; 2], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (Bytes(pair[0].to_vec()), Bytes(pair[1].to_vec())).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<[[u8; 32]; 2], D::Error> {
+        let (a, b) = <(Bytes, Bytes)>::deserialize(deserializer)?;
+        Ok([array32(&a.0)?, array32(&b.0)?])
+    }
+}
+
+/// Compressed secp256k1 public keys
+pub(crate) mod secp_pubkey {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(key: &btc::PublicKey, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(key.serialize_compressed().to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<btc::PublicKey, D::Error> {
+        let bytes = Bytes::deserialize(deserializer)?.0;
+        if bytes.len() != 33 {
+            return Err(D::Error::invalid_length(bytes.len(), &"33 bytes"));
+        }
+        let mut array = [0u8; 33];
+        array.copy_from_slice(&bytes);
+        btc::PublicKey::parse_compressed(&array).map_err(|_| D::Error::custom("invalid secp256k1 point"))
+    }
+}
+
+/// secp256k1 private keys, only found in local snapshots
+pub(crate) mod secp_privkey {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(key: &btc::PrivateKey, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(key.serialize().to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<btc::PrivateKey, D::Error> {
+        let bytes = array32(&Bytes::deserialize(deserializer)?.0)?;
+        btc::PrivateKey::parse(&bytes).map_err(|_| D::Error::custom("invalid secp256k1 private key"))
+    }
+}
+
+/// Compact 64 bytes ECDSA signatures
+pub(crate) mod secp_signature {
+    use super::*;
+    use secp256k1::Signature;
+
+    pub fn serialize<S: Serializer>(sig: &Signature, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(sig.serialize().to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Signature, D::Error> {
+        let bytes = Bytes::deserialize(deserializer)?.0;
+        if bytes.len() != 64 {
+            return Err(D::Error::invalid_length(bytes.len(), &"64 bytes"));
+        }
+        let mut array = [0u8; 64];
+        array.copy_from_slice(&bytes);
+        let sig = Signature::parse(&array);
+        if sig.r.is_zero() || sig.s.is_zero() {
+            return Err(D::Error::custom("invalid signature"));
+        }
+        Ok(sig)
+    }
+}
+
+/// Pair of secp256k1 scalars, rejected if not reduced
+pub(crate) mod secp_scalar_pair {
+    use super::*;
+    use secp256k1::curve::Scalar;
+
+    fn scalar<E: DeError>(bytes: &[u8]) -> std::result::Result<Scalar, E> {
+        let mut scalar = Scalar::from_int(0);
+        if scalar.set_b32(&array32(bytes)?) {
+            return Err(E::custom("secp256k1 scalar overflow"));
+        }
+        Ok(scalar)
+    }
+
+    pub fn serialize<S: Serializer>(pair: &(Scalar, Scalar), serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (Bytes(pair.0.b32().to_vec()), Bytes(pair.1.b32().to_vec())).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<(Scalar, Scalar), D::Error> {
+        let (a, b) = <(Bytes, Bytes)>::deserialize(deserializer)?;
+        Ok((scalar(&a.0)?, scalar(&b.0)?))
+    }
+}
+
+/// Compressed ed25519 points, rejected if not in the prime order subgroup
+pub(crate) mod ed25519_point {
+    use super::*;
+    use curve25519_dalek::edwards::CompressedEdwardsY;
+
+    pub fn serialize<S: Serializer>(point: &xmr::PublicKey, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(point.compress().to_bytes().to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<xmr::PublicKey, D::Error> {
+        let bytes = array32(&Bytes::deserialize(deserializer)?.0)?;
+        match CompressedEdwardsY(bytes).decompress() {
+            Some(point) if point.is_torsion_free() => Ok(point),
+            _ => Err(D::Error::custom("invalid ed25519 point")),
+        }
+    }
+}
+
+/// Canonical ed25519 scalars
+pub(crate) mod ed25519_scalar {
+    use super::*;
+
+    pub(super) fn scalar<E: DeError>(bytes: &[u8]) -> std::result::Result<xmr::PrivateKey, E> {
+        xmr::PrivateKey::from_canonical_bytes(array32(bytes)?)
+            .ok_or_else(|| E::custom("non canonical ed25519 scalar"))
+    }
+
+    pub fn serialize<S: Serializer>(scalar: &xmr::PrivateKey, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(scalar.to_bytes().to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<xmr::PrivateKey, D::Error> {
+        scalar(&Bytes::deserialize(deserializer)?.0)
+    }
+}
+
+pub(crate) mod ed25519_scalar_pair {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(pair: &[xmr::PrivateKey; 2], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (Bytes(pair[0].to_bytes().to_vec()), Bytes(pair[1].to_bytes().to_vec())).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<[xmr::PrivateKey; 2], D::Error> {
+        let (a, b) = <(Bytes, Bytes)>::deserialize(deserializer)?;
+        Ok([ed25519_scalar::scalar(&a.0)?, ed25519_scalar::scalar(&b.0)?])
+    }
+}
+
+/// Hex encoded Bitcoin transactions, kept as hex strings but rejected if they do not
+/// decode
+pub(crate) mod btc_tx {
+    use super::*;
+    use bitcoin::consensus::encode::deserialize;
+
+    pub fn serialize<S: Serializer>(tx: &str, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(hex::decode(tx).map_err(serde::ser::Error::custom)?).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+        let bytes = Bytes::deserialize(deserializer)?.0;
+        deserialize::<bitcoin::Transaction>(&bytes).map_err(|_| D::Error::custom("invalid bitcoin transaction"))?;
+        Ok(hex::encode(bytes))
+    }
+}
+
+/// Hex encoded Monero transactions, kept as hex strings but rejected if they do not
+/// decode
+pub(crate) mod xmr_tx {
+    use super::*;
+    use crate::transactions::xmr::MoneroTransaction;
+
+    pub fn serialize<S: Serializer>(tx: &str, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Bytes(hex::decode(tx).map_err(serde::ser::Error::custom)?).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+        let bytes = Bytes::deserialize(deserializer)?.0;
+        MoneroTransaction::deserialize(&bytes).map_err(|_| D::Error::custom("invalid monero transaction"))?;
+        Ok(hex::encode(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::{Protocol, Btc, Xmr};
+    use crate::types::{common, btc, xmr, RelativeLocktime, Error};
+    use crate::types::amount::{Satoshi, Piconero};
+    use crate::types::xmr::keys;
+    use crate::transactions::xmr::Wallet;
+    use crate::backend::xmr::FakeMonero;
+    use super::{Message, VERSION};
+
+    fn setups() -> (btc::ExportedSetupParams, xmr::ExportedSetupParams) {
+        let mut rng = OsRng::new().expect("OsRng");
+        let params = common::Params::new(
+            RelativeLocktime::Blocks(10),
+            RelativeLocktime::Blocks(10),
+            common::Networks::new(common::BtcNetwork::Testnet, xmr::address::Network::Stagenet),
+            common::Amounts::new(Satoshi(50_000), Piconero(1_000_000_000_000)),
+        ).unwrap();
+        let btc_params = Btc::setup(params.clone(), &mut rng).unwrap();
+        let xmr_params = Xmr::setup(params, &mut rng).unwrap();
+        (btc::ExportedSetupParams::from(&btc_params), xmr::ExportedSetupParams::from(&xmr_params))
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let (btc_export, xmr_export) = setups();
+        let json = Message::BtcSetup(btc_export).to_json().unwrap();
+        let message = Message::from_json(&json).unwrap();
+        assert_eq!(json, message.to_json().unwrap());
+
+        let json = Message::XmrSetup(xmr_export).to_json().unwrap();
+        match Message::from_json(&json).unwrap() {
+            Message::XmrSetup(params) => {
+                assert!(params.proof_0.verify(&params.X_0, &params.X_0_secp).is_ok());
+            },
+            _ => panic!("wrong message"),
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let message = Message::Secret([7u8; 32]);
+        let bytes = message.to_bytes().unwrap();
+        match Message::from_bytes(&bytes).unwrap() {
+            Message::Secret(s) => assert_eq!([7u8; 32], s),
+            _ => panic!("wrong message"),
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Message::from_bytes(&trailing).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_version() {
+        let json = Message::Secret([7u8; 32]).to_json().unwrap()
+            .replace(&format!("\"version\":{}", VERSION), "\"version\":2");
+        match Message::from_json(&json) {
+            Err(Error::UnsupportedVersion(2)) => (),
+            _ => panic!("version must be rejected"),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_point() {
+        let (_, xmr_export) = setups();
+        let json = Message::XmrSetup(xmr_export).to_json().unwrap();
+        let json = json.replacen("\"X_0\":\"", "\"X_0\":\"ff", 1);
+        assert!(Message::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_xmr_tx() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let chain = FakeMonero::new();
+        let (x, a) = (xmr::PrivateKey::random(&mut rng), xmr::PrivateKey::random(&mut rng));
+        let signed = chain.transfer(&keys::public_key(&x), &keys::public_key(&a), 1_000_000_000).unwrap();
+
+        let locked = |tx_hex: &str| Message::LockedXmr(xmr::LockedXmr { tx_hex: tx_hex.into(), tx_hash: [0; 32] });
+        let message = locked(&signed.tx_hex);
+        assert!(Message::from_json(&message.to_json().unwrap()).is_ok());
+        assert!(Message::from_bytes(&message.to_bytes().unwrap()).is_ok());
+
+        // Only version 2 transactions are decoded
+        assert_eq!("02", &signed.tx_hex[..2]);
+        let message = locked(&format!("01{}", &signed.tx_hex[2..]));
+        match Message::from_json(&message.to_json().unwrap()) {
+            Err(Error::InvalidMessage) => (),
+            _ => panic!("monero transaction must decode"),
+        }
+        match Message::from_bytes(&message.to_bytes().unwrap()) {
+            Err(Error::InvalidMessage) => (),
+            _ => panic!("monero transaction must decode"),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Monero addresses: network byte, public spend and view keys and a Keccak checksum encoded
+//! with Monero's block based base58
+
+use super::{PublicKey, keys};
+use crate::types::{Result, Error};
+
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use tiny_keccak::keccak256;
+
+use std::fmt;
+use std::str::FromStr;
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Base58 is applied on blocks of 8 bytes encoded in 11 characters
+const FULL_BLOCK_SIZE: usize = 8;
+const FULL_ENCODED_BLOCK_SIZE: usize = 11;
+/// Encoded size of a block given its size in bytes
+const ENCODED_BLOCK_SIZES: [usize; 9] = [0, 2, 3, 5, 6, 7, 9, 10, 11];
+const CHECKSUM_SIZE: usize = 4;
+
+/// Monero network an address belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Network {
+    Mainnet,
+    Stagenet,
+    Testnet,
+}
+
+impl Network {
+    /// Fail if the address is not encoded for this network
+    pub fn check(&self, address: &Address) -> Result<()> {
+        match address.network == *self {
+            true => Ok(()),
+            false => Err(Error::InvalidNetwork),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Network> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "stagenet" => Ok(Network::Stagenet),
+            "testnet" => Ok(Network::Testnet),
+            _ => Err(Error::InvalidMoneroAddress("unknown network")),
+        }
+    }
+}
+
+/// Kind of Monero address, integrated addresses carry an 8 bytes payment id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressType {
+    Standard,
+    Integrated([u8; 8]),
+    Subaddress,
+}
+
+impl AddressType {
+    fn prefix(&self, network: Network) -> u64 {
+        use self::AddressType::*;
+        use self::Network::*;
+
+        match (network, self) {
+            (Mainnet, Standard) => 18,
+            (Mainnet, Integrated(_)) => 19,
+            (Mainnet, Subaddress) => 42,
+            (Stagenet, Standard) => 24,
+            (Stagenet, Integrated(_)) => 25,
+            (Stagenet, Subaddress) => 36,
+            (Testnet, Standard) => 53,
+            (Testnet, Integrated(_)) => 54,
+            (Testnet, Subaddress) => 63,
+        }
+    }
+}
+
+/// Network and address kind of a prefix, the payment id is left empty
+fn from_prefix(prefix: u64) -> Option<(Network, AddressType)> {
+    use self::AddressType::*;
+    use self::Network::*;
+
+    let decoded = match prefix {
+        18 => (Mainnet, Standard),
+        19 => (Mainnet, Integrated([0; 8])),
+        42 => (Mainnet, Subaddress),
+        24 => (Stagenet, Standard),
+        25 => (Stagenet, Integrated([0; 8])),
+        36 => (Stagenet, Subaddress),
+        53 => (Testnet, Standard),
+        54 => (Testnet, Integrated([0; 8])),
+        63 => (Testnet, Subaddress),
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+/// Monero address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Address {
+    pub network: Network,
+    pub addr_type: AddressType,
+    pub spend: PublicKey,
+    pub view: PublicKey,
+}
+
+impl Address {
+    /// Standard address of a spend and a view public key, e.g. the shared lock address `X`,
+    /// `a·G`
+    pub fn standard(network: Network, spend: &PublicKey, view: &PublicKey) -> Address {
+        Address {
+            network,
+            addr_type: AddressType::Standard,
+            spend: *spend,
+            view: *view,
+        }
+    }
+
+    /// Sweeps only pay standard addresses, subaddress outputs are derived from other keys
+    /// and the payment id of integrated addresses is not encoded
+    pub fn check_standard(&self) -> Result<()> {
+        match self.addr_type {
+            AddressType::Standard => Ok(()),
+            AddressType::Integrated(_) => Err(Error::InvalidMoneroAddress("integrated address")),
+            AddressType::Subaddress => Err(Error::InvalidMoneroAddress("subaddress")),
+        }
+    }
+
+    /// Raw bytes of the address: prefix, spend key, view key, payment id and checksum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        keys::write_varint(&mut bytes, self.addr_type.prefix(self.network));
+        bytes.extend_from_slice(self.spend.compress().as_bytes());
+        bytes.extend_from_slice(self.view.compress().as_bytes());
+        if let AddressType::Integrated(payment_id) = self.addr_type {
+            bytes.extend_from_slice(&payment_id);
+        }
+        let checksum = keccak256(&bytes);
+        bytes.extend_from_slice(&checksum[..CHECKSUM_SIZE]);
+        bytes
+    }
+
+    /// Parse raw address bytes and verify the checksum and the keys
+    pub fn from_bytes(bytes: &[u8]) -> Result<Address> {
+        if bytes.len() < CHECKSUM_SIZE {
+            return Err(Error::InvalidMoneroAddress("too short"));
+        }
+        let (data, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        if keccak256(data)[..CHECKSUM_SIZE] != checksum[..] {
+            return Err(Error::InvalidMoneroAddress("checksum mismatch"));
+        }
+
+        let mut pos = 0;
+        let prefix = keys::read_varint(data, &mut pos)
+            .map_err(|_| Error::InvalidMoneroAddress("invalid prefix"))?;
+        let (network, mut addr_type) = from_prefix(prefix)
+            .ok_or(Error::InvalidMoneroAddress("unknown prefix"))?;
+        let data = &data[pos..];
+
+        let expected = match addr_type {
+            AddressType::Integrated(_) => 72,
+            _ => 64,
+        };
+        if data.len() != expected {
+            return Err(Error::InvalidMoneroAddress("invalid length"));
+        }
+        if let AddressType::Integrated(ref mut payment_id) = addr_type {
+            payment_id.copy_from_slice(&data[64..]);
+        }
+
+        Ok(Address {
+            network,
+            addr_type,
+            spend: decompress(&data[..32])?,
+            view: decompress(&data[32..64])?,
+        })
+    }
+}
+
+fn decompress(bytes: &[u8]) -> Result<PublicKey> {
+    let mut key = [0u8; 32];
+    key.copy_from_slice(bytes);
+    CompressedEdwardsY(key).decompress().ok_or(Error::InvalidMoneroAddress("invalid public key"))
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&base58_encode(&self.to_bytes()))
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Address> {
+        Address::from_bytes(&base58_decode(s)?)
+    }
+}
+
+/// Encode bytes with Monero's base58, each block of 8 bytes is encoded in 11 characters and
+/// the last partial block is padded to the size given by `ENCODED_BLOCK_SIZES`
+pub fn base58_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for block in data.chunks(FULL_BLOCK_SIZE) {
+        let mut num = block.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+        let mut chars = vec![ALPHABET[0]; ENCODED_BLOCK_SIZES[block.len()]];
+        for c in chars.iter_mut().rev() {
+            *c = ALPHABET[(num % 58) as usize];
+            num /= 58;
+        }
+        encoded.extend(chars.into_iter().map(|c| c as char));
+    }
+    encoded
+}
+
+/// Decode a Monero base58 string, fail on invalid characters, block sizes or overflows
+pub fn base58_decode(s: &str) -> Result<Vec<u8>> {
+    let mut decoded = vec![];
+    for block in s.as_bytes().chunks(FULL_ENCODED_BLOCK_SIZE) {
+        let size = ENCODED_BLOCK_SIZES.iter().position(|size| *size == block.len())
+            .ok_or(Error::InvalidMoneroAddress("invalid base58 block size"))?;
+        let mut num = 0u128;
+        for c in block {
+            let digit = ALPHABET.iter().position(|a| a == c)
+                .ok_or(Error::InvalidMoneroAddress("invalid base58 character"))?;
+            num = num * 58 + digit as u128;
+        }
+        if size < FULL_BLOCK_SIZE && num >> (8 * size) != 0 || num > std::u64::MAX as u128 {
+            return Err(Error::InvalidMoneroAddress("base58 block overflow"));
+        }
+        decoded.extend_from_slice(&(num as u64).to_be_bytes()[FULL_BLOCK_SIZE - size..]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use crate::types::Error;
+    use crate::types::xmr::keys;
+    use super::{Address, AddressType, Network, base58_encode, base58_decode};
+
+    // Monero project donation address
+    const DONATION: &str = "44AFFq5kSiGBoZ4NMDwYtN18obc8AemS33DBLWs3H7otXft3XjrpDtQGv7SqSsaBYBb98uNbr2VBBEt7f2wfn3RVGQBEP3A";
+
+    #[test]
+    fn base58_roundtrip() {
+        for data in &[vec![], vec![0u8], vec![0xff; 7], vec![1u8; 8], (0u8..69).collect::<Vec<u8>>()] {
+            assert_eq!(data, &base58_decode(&base58_encode(data)).unwrap());
+        }
+        // A single byte block is encoded in 2 characters, 'zz' overflows it
+        assert!(base58_decode("zz").is_err());
+        assert!(base58_decode("0OIl").is_err());
+    }
+
+    #[test]
+    fn parse_mainnet_address() {
+        let address = Address::from_str(DONATION).unwrap();
+        assert_eq!(Network::Mainnet, address.network);
+        assert_eq!(AddressType::Standard, address.addr_type);
+        assert_eq!(DONATION, address.to_string());
+
+        // Flipping a character breaks the checksum
+        let mut tampered = DONATION.to_string();
+        tampered.replace_range(20..21, "1");
+        assert!(Address::from_str(&tampered).is_err());
+    }
+
+    #[test]
+    fn shared_address_roundtrip() {
+        let spend = keys::public_key(&keys::hash_to_scalar(b"spend"));
+        let view = keys::public_key(&keys::hash_to_scalar(b"view"));
+        for network in &[Network::Mainnet, Network::Stagenet, Network::Testnet] {
+            for addr_type in &[AddressType::Standard, AddressType::Integrated([7; 8]), AddressType::Subaddress] {
+                let address = Address { network: *network, addr_type: *addr_type, spend, view };
+                let encoded = address.to_string();
+                assert_eq!(address, Address::from_str(&encoded).unwrap());
+            }
+        }
+        let standard = Address::standard(Network::Stagenet, &spend, &view).to_string();
+        assert!(standard.starts_with('5'));
+        assert_eq!(95, standard.len());
+    }
+
+    #[test]
+    fn only_standard_destinations() {
+        let spend = keys::public_key(&keys::hash_to_scalar(b"spend"));
+        let view = keys::public_key(&keys::hash_to_scalar(b"view"));
+        let mut address = Address::standard(Network::Mainnet, &spend, &view);
+        assert!(address.check_standard().is_ok());
+        address.addr_type = AddressType::Integrated([7; 8]);
+        assert!(address.check_standard().is_err());
+        address.addr_type = AddressType::Subaddress;
+        assert!(address.check_standard().is_err());
+    }
+
+    #[test]
+    fn check_network() {
+        let spend = keys::public_key(&keys::hash_to_scalar(b"spend"));
+        let view = keys::public_key(&keys::hash_to_scalar(b"view"));
+        let address = Address::standard(Network::Stagenet, &spend, &view);
+        assert!(Network::Stagenet.check(&address).is_ok());
+        match Network::Mainnet.check(&address) {
+            Err(Error::InvalidNetwork) => (),
+            _ => panic!("stagenet address on mainnet"),
+        }
+    }
+}
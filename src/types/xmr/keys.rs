@@ -0,0 +1,187 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+
+//! Monero key derivation, output scanning and RingCT amount primitives
+
+use super::{PrivateKey, PublicKey};
+use crate::types::{Result, Error};
+
+use curve25519_dalek::constants;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use tiny_keccak::keccak256;
+
+/// Generator `H` used in RingCT Pedersen commitments `C = mask·G + amount·H`
+pub const H: [u8; 32] = [
+    0x8b, 0x65, 0x59, 0x70, 0x15, 0x37, 0x99, 0xaf,
+    0x2a, 0xea, 0xdc, 0x9f, 0xf1, 0xad, 0xd0, 0xea,
+    0x6c, 0x72, 0x51, 0xd5, 0x41, 0x54, 0xcf, 0xa9,
+    0x2c, 0x17, 0x3a, 0x0d, 0xd3, 0x9c, 0x1f, 0x94,
+];
+
+/// Append a Monero varint to a buffer
+pub fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Read a Monero varint from a buffer at the given position and advance the position
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut n = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf.get(*pos).ok_or(Error::InvalidMoneroTransaction)?;
+        *pos += 1;
+        if shift >= 64 {
+            return Err(Error::InvalidMoneroTransaction);
+        }
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+    }
+}
+
+/// Keccak256 of the data reduced modulo the group order, `Hs` in Monero
+pub fn hash_to_scalar(data: &[u8]) -> PrivateKey {
+    PrivateKey::from_bytes_mod_order(keccak256(data))
+}
+
+/// Compute the public key `k·G`
+pub fn public_key(k: &PrivateKey) -> PublicKey {
+    k * &constants::ED25519_BASEPOINT_TABLE
+}
+
+/// Decompress a point, fail if the encoding is invalid
+pub fn decompress(bytes: &[u8]) -> Result<PublicKey> {
+    if bytes.len() != 32 {
+        return Err(Error::InvalidMoneroTransaction);
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(bytes);
+    CompressedEdwardsY(key).decompress().ok_or(Error::InvalidMoneroTransaction)
+}
+
+/// Key derivation `D = 8·a·R` between a private view key and a transaction public key
+pub fn derivation(view: &PrivateKey, tx_pubkey: &PublicKey) -> PublicKey {
+    (view * tx_pubkey).mul_by_cofactor()
+}
+
+/// Shared scalar `Hs(D || i)` for the output at index `i`
+pub fn derivation_to_scalar(derivation: &PublicKey, index: u64) -> PrivateKey {
+    let mut data = derivation.compress().to_bytes().to_vec();
+    write_varint(&mut data, index);
+    hash_to_scalar(&data)
+}
+
+/// One-time output key `P = Hs(D || i)·G + X`
+pub fn derive_output_key(derivation: &PublicKey, index: u64, spend: &PublicKey) -> PublicKey {
+    public_key(&derivation_to_scalar(derivation, index)) + spend
+}
+
+/// One-time output private key `p = Hs(D || i) + x`
+pub fn derive_output_privkey(derivation: &PublicKey, index: u64, spend: &PrivateKey) -> PrivateKey {
+    derivation_to_scalar(derivation, index) + spend
+}
+
+/// Decode a compact RingCT amount (Bulletproof2 and later)
+pub fn decode_amount(shared: &PrivateKey, encrypted: &[u8; 8]) -> u64 {
+    let mut data = b"amount".to_vec();
+    data.extend_from_slice(shared.as_bytes());
+    let key = keccak256(&data);
+    let mut amount = [0u8; 8];
+    for (a, (e, k)) in amount.iter_mut().zip(encrypted.iter().zip(key.iter())) {
+        *a = e ^ k;
+    }
+    u64::from_le_bytes(amount)
+}
+
+/// Encode a compact RingCT amount (Bulletproof2 and later)
+pub fn encode_amount(shared: &PrivateKey, amount: u64) -> [u8; 8] {
+    // xor is its own inverse
+    let decoded = decode_amount(shared, &amount.to_le_bytes());
+    decoded.to_le_bytes()
+}
+
+/// Commitment mask derived from the shared scalar (Bulletproof2 and later)
+pub fn commitment_mask(shared: &PrivateKey) -> PrivateKey {
+    let mut data = b"commitment_mask".to_vec();
+    data.extend_from_slice(shared.as_bytes());
+    hash_to_scalar(&data)
+}
+
+/// Pedersen commitment `C = mask·G + amount·H`
+pub fn commit(mask: &PrivateKey, amount: u64) -> Result<PublicKey> {
+    let h = decompress(&H)?;
+    Ok(public_key(mask) + PrivateKey::from(amount) * h)
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use rand::rngs::OsRng;
+    use crate::types::xmr;
+    use super::{write_varint, read_varint, public_key, derivation, derive_output_key,
+        derive_output_privkey, derivation_to_scalar, encode_amount, decode_amount};
+
+    #[test]
+    fn varint() {
+        for n in &[0u64, 1, 127, 128, 300, 16384, std::u64::MAX] {
+            let mut buf = vec![];
+            write_varint(&mut buf, *n);
+            let mut pos = 0;
+            assert_eq!(*n, read_varint(&buf, &mut pos).unwrap());
+            assert_eq!(buf.len(), pos);
+        }
+        let mut buf = vec![];
+        write_varint(&mut buf, 300);
+        assert_eq!(vec![0xac, 0x02], buf);
+    }
+
+    #[test]
+    fn scan_output() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let a = xmr::PrivateKey::random(&mut rng);
+        let x = xmr::PrivateKey::random(&mut rng);
+        let r = xmr::PrivateKey::random(&mut rng);
+        let A = public_key(&a);
+        let X = public_key(&x);
+        let R = public_key(&r);
+
+        // Sender side with r and receiver side with a agree on the derivation
+        let sender = derivation(&r, &A);
+        let receiver = derivation(&a, &R);
+        assert!(sender == receiver);
+
+        let P = derive_output_key(&sender, 1, &X);
+        assert!(P == derive_output_key(&receiver, 1, &X));
+        assert!(P == public_key(&derive_output_privkey(&receiver, 1, &x)));
+        assert!(P != derive_output_key(&receiver, 0, &X));
+    }
+
+    #[test]
+    fn amount_encoding() {
+        let mut rng = OsRng::new().expect("OsRng");
+        let a = xmr::PrivateKey::random(&mut rng);
+        let R = public_key(&xmr::PrivateKey::random(&mut rng));
+        let shared = derivation_to_scalar(&derivation(&a, &R), 0);
+        let encrypted = encode_amount(&shared, 1_000_000_000_000);
+        assert_eq!(1_000_000_000_000, decode_amount(&shared, &encrypted));
+    }
+}
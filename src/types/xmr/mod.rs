@@ -0,0 +1,210 @@
+// Monero Swap Rust Library
+// Written in 2019 by
+//   h4sh3d <h4sh3d@truelevel.io>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+
+use bitcoin::Address;
+use curve25519_dalek::constants;
+use wasm_bindgen::prelude::*;
+
+use super::{btc, dleq, wire, common, RelativeLocktime};
+use super::hashlock::HashLock;
+use crate::transactions;
+use crate::backend;
+
+pub mod keys;
+pub mod address;
+
+pub type PrivateKey = curve25519_dalek::scalar::Scalar;
+
+pub type PublicKey = curve25519_dalek::edwards::EdwardsPoint;
+
+pub struct VerifyTransactions {
+    pub transactions: btc::InitialTransactions,
+    /// Outputs spent by `btx_1` as found on chain, in the order of its inputs
+    pub spent_outputs: Vec<bitcoin::TxOut>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifiedTransaction {
+    #[serde(with = "wire::btc_tx")]
+    pub btx_2_signed: String,
+}
+
+pub struct InitiateSwap<'a, W: transactions::xmr::Wallet, B: backend::xmr::MoneroBackend> {
+    pub wallet: &'a W,
+    /// Backend relaying the lock transaction
+    pub backend: &'a B,
+}
+
+/// Full Monero spend key recovered from the Bitcoin chain and the transaction
+/// sweeping the shared address
+#[derive(Debug, Clone)]
+pub struct SweptXmr {
+    pub x: PrivateKey,
+    pub sweep: transactions::xmr::SignedTransfer,
+}
+
+/// Monero lock transaction sent to the buyer for verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockedXmr {
+    #[serde(with = "wire::xmr_tx")]
+    pub tx_hex: String,
+    #[serde(with = "wire::bytes32")]
+    pub tx_hash: [u8; 32],
+}
+
+pub struct Swap {
+    pub funding: transactions::btc::funding::FundingTx,
+    pub address: Address,
+    pub s: [u8; 32],
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+}
+
+pub struct ClaimRefund<'a> {
+    pub btx_2_signed: &'a transactions::btc::refund::RefundTx<'a>,
+    pub address: Address,
+    /// Feerate in satoshi per virtual byte
+    pub feerate: u64,
+}
+
+pub struct RecoverXmrAfterRefund<'a, W: transactions::xmr::Wallet, B: backend::xmr::MoneroBackend> {
+    pub spend_refund: transactions::btc::spend_refund::SpendRefundTx,
+    /// Standard Monero address receiving the swept funds
+    pub dest: address::Address,
+    pub wallet: &'a W,
+    /// Backend relaying the sweep transaction
+    pub backend: &'a B,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct SetupParams {
+    #[serde(with = "wire::ed25519_scalar")]
+    pub(crate) a_0: PrivateKey,
+    #[serde(with = "wire::ed25519_scalar")]
+    pub(crate) x_0: PrivateKey,
+    #[serde(with = "wire::secp_privkey")]
+    pub(crate) b_a: btc::PrivateKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) B_a: btc::PublicKey,
+    /// Hash locks of the seller key share
+    pub(crate) h_0: HashLock,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_0_secp: btc::PublicKey,
+    pub(crate) proof_0: dleq::Proof,
+    pub(crate) t_0: RelativeLocktime,
+    pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct ExportedSetupParams {
+    #[serde(with = "wire::ed25519_scalar")]
+    pub(crate) a_0: PrivateKey,
+    #[serde(with = "wire::ed25519_point")]
+    pub(crate) X_0: PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) B_a: btc::PublicKey,
+    /// Hash locks of the seller key share
+    pub(crate) h_0: HashLock,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_0_secp: btc::PublicKey,
+    pub(crate) proof_0: dleq::Proof,
+    pub(crate) t_0: RelativeLocktime,
+    pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
+}
+
+impl From<&SetupParams> for ExportedSetupParams {
+    #[allow(non_snake_case)]
+    fn from(params: &SetupParams) -> ExportedSetupParams {
+        let SetupParams {
+            a_0,
+            x_0,
+            b_a: _,
+            B_a,
+            h_0,
+            X_0_secp,
+            proof_0,
+            t_0,
+            t_1,
+            networks,
+            amounts,
+        } = params;
+
+        let X_0 = x_0 * &constants::ED25519_BASEPOINT_TABLE;
+
+        ExportedSetupParams {
+            a_0: *a_0,
+            X_0,
+            B_a: B_a.clone(),
+            h_0: h_0.clone(),
+            X_0_secp: X_0_secp.clone(),
+            proof_0: proof_0.clone(),
+            t_0: *t_0,
+            t_1: *t_1,
+            networks: *networks,
+            amounts: *amounts,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct Setup {
+    #[serde(with = "wire::ed25519_scalar")]
+    pub(crate) a: PrivateKey,
+    #[serde(with = "wire::ed25519_scalar")]
+    pub(crate) x_0: PrivateKey,
+    #[serde(with = "wire::ed25519_point")]
+    pub(crate) X: PublicKey,
+    #[serde(with = "wire::ed25519_point")]
+    pub(crate) X_1: PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) X_1_secp: btc::PublicKey,
+    #[serde(with = "wire::secp_privkey")]
+    pub(crate) b_a: btc::PrivateKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) B_a: btc::PublicKey,
+    #[serde(with = "wire::secp_pubkey")]
+    pub(crate) B_b: btc::PublicKey,
+    /// Hash locks of the seller key share
+    pub(crate) h_0: HashLock,
+    /// Hash locks of the buyer key share
+    pub(crate) h_1: HashLock,
+    #[serde(with = "wire::bytes32")]
+    pub(crate) h_2: [u8; 32],
+    pub(crate) t_0: RelativeLocktime,
+    pub(crate) t_1: RelativeLocktime,
+    pub(crate) networks: common::Networks,
+    pub(crate) amounts: common::Amounts,
+}
+
+impl Setup {
+    /// Address of the shared wallet receiving the locked Monero, spend key `X` and view key
+    /// `a·G`, to cross-check with the counterparty
+    pub fn lock_address(&self) -> address::Address {
+        address::Address::standard(self.networks.xmr, &self.X, &keys::public_key(&self.a))
+    }
+}
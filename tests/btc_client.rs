@@ -4,6 +4,7 @@ extern crate bitcoincore_rpc;
 extern crate rand;
 extern crate hex;
 extern crate bitcoin_bech32;
+extern crate serde_json;
 
 use rand::rngs::OsRng;
 use bitcoincore_rpc::Client;
@@ -15,16 +16,28 @@ use bitcoincore_rpc::json::AddressType;
 
 use lib::{Protocol, Phase, Btc, Xmr};
 use lib::types::{common, xmr, btc, RelativeLocktime};
+use lib::types::amount::{Satoshi, Piconero};
 use lib::transactions::btc::{refund, funding};
+use serde_json::{json, Value};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const T_0: u16 = 10;
 const T_1: u16 = 10;
-
-fn setup() -> (xmr::Setup, btc::Setup, Client, String) {
+// Feerate in satoshi per virtual byte
+const FEERATE: u64 = 2;
+// Amount locked in the swaplock in satoshi
+const SWAP_AMOUNT: u64 = 50_000_000;
+// Amount locked in the shared Monero address in piconero
+const XMR_AMOUNT: u64 = 1_000_000_000_000;
+
+fn setup(t_0: RelativeLocktime, t_1: RelativeLocktime) -> (xmr::Setup, btc::Setup, Client, String) {
     let mut rng = OsRng::new().expect("OsRng");
     let params = common::Params::new(
-        RelativeLocktime::Blocks(T_0),
-        RelativeLocktime::Blocks(T_1),
+        t_0,
+        t_1,
+        common::Networks::new(common::BtcNetwork::Regtest, xmr::address::Network::Testnet),
+        common::Amounts::new(Satoshi(SWAP_AMOUNT), Piconero(XMR_AMOUNT)),
     );
     let xmr_params = Xmr::setup(params.clone(), &mut rng).unwrap();
     let btc_params = Btc::setup(params, &mut rng).unwrap();
@@ -42,7 +55,7 @@ fn setup() -> (xmr::Setup, btc::Setup, Client, String) {
     (xmr_setup, btc_setup, client, address)
 }
 
-fn verified_txs(params: &(xmr::Setup, btc::Setup, Client, String)) -> (btc::InitialTransactions, xmr::VerifiedTransaction, Address, btc::PrivateKey) {
+fn verified_txs(params: &(xmr::Setup, btc::Setup, Client, String)) -> (btc::InitialTransactions, xmr::VerifiedTransaction, Address, btc::PrivateKey, btc::Utxo) {
     let (xmr_setup, btc_setup, client, address) = params;
 
     let _ = client.send_to_address(&address, 1.0f64, None, None, None);
@@ -67,32 +80,44 @@ fn verified_txs(params: &(xmr::Setup, btc::Setup, Client, String)) -> (btc::Init
         })
         .collect();
 
+    let utxo = utxos.remove(0);
     let init_txs = Btc::execute(&btc_setup, &btc::CreateTransactions {
-        utxo: utxos.remove(0),
+        utxos: vec![utxo.clone()],
+        change_address: address.clone(),
+        feerate: FEERATE,
     }).unwrap();
 
+    let spent = client.get_raw_transaction(&utxo.txid, None).unwrap();
     let verify_txs = xmr::VerifyTransactions {
         transactions: init_txs.clone(),
+        spent_outputs: vec![spent.output[utxo.vout as usize].clone()],
     };
     let verified_txs = Xmr::execute(&xmr_setup, &verify_txs).unwrap();
     let privkey_str = client.dump_priv_key(&address).unwrap();
     let bytes = base58::from_check(&privkey_str).unwrap();
     let privkey = btc::PrivateKey::parse_slice(&bytes[1..33]).unwrap();
-    (init_txs, verified_txs, address, privkey)
+    (init_txs, verified_txs, address, privkey, utxo)
 }
 
 fn setup_btx1() -> (Client, btc::Setup, xmr::Setup, String, String) {
-    let setup  = setup();
-    let (init_txs, verified_txs, address, privkey) = verified_txs(&setup);
+    setup_btx1_with(RelativeLocktime::Blocks(T_0), RelativeLocktime::Blocks(T_1))
+}
+
+fn setup_btx1_with(t_0: RelativeLocktime, t_1: RelativeLocktime) -> (Client, btc::Setup, xmr::Setup, String, String) {
+    let setup  = setup(t_0, t_1);
+    let (init_txs, verified_txs, address, privkey, utxo) = verified_txs(&setup);
     let (xmr_setup, btc_setup, client, _) = setup;
 
     let lock_funds = btc::LockFunds {
-        input: lib::types::btc::Input {
-            amount: 100_000_000,
+        inputs: vec![lib::types::btc::Input {
+            txid: utxo.txid,
+            vout: utxo.vout,
+            amount: utxo.amount,
             address: &address,
             privkey: &privkey,
-        },
+        }],
         btx_1: init_txs.btx_1.clone(),
+        btx_2: init_txs.btx_2.clone(),
         btx_2_signed: verified_txs.btx_2_signed,
     };
     let tx = Btc::execute(&btc_setup, &lock_funds).unwrap();
@@ -152,6 +177,25 @@ fn broadcast_btx2_after_locktime_xmr() {
     assert_eq!(false, client.send_raw_transaction(&btx_2_signed).is_err());
 }
 
+#[test]
+fn broadcast_btx2_time_locktime() {
+    let t_0 = RelativeLocktime::from_duration(Duration::from_secs(3_600)).unwrap();
+    let (client, _, _, _, btx_2_signed) = setup_btx1_with(t_0, RelativeLocktime::Blocks(T_1));
+
+    // Blocks alone do not move the median time past of the last 11 blocks far enough
+    let _ = client.generate(11, None);
+    // Should throw an Error non-BIP68 final
+    assert_eq!(true, client.send_raw_transaction(&btx_2_signed).is_err());
+
+    // Mine 11 blocks after the locktime duration to move the median time past over it
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let _: Value = client.call("setmocktime", &[json!(now + 2 * t_0.as_secs().unwrap())]).unwrap();
+    let _ = client.generate(11, None);
+    let res = client.send_raw_transaction(&btx_2_signed);
+    let _: Value = client.call("setmocktime", &[json!(0)]).unwrap();
+    assert_eq!(false, res.is_err());
+}
+
 fn lock_funds_and_start_refund() -> (xmr::Setup, btc::Setup, Client, String, String) {
     let (client, btc_setup, xmr_setup, btx_1, btx_2) = setup_btx1();
 
@@ -181,6 +225,7 @@ fn spend_refund() {
     let spend_refund = btc::SpendRefund {
         btx_2_signed: &btx_2,
         address: final_address,
+        feerate: FEERATE,
     };
     let tx = Btc::execute(&btc_setup, &spend_refund).unwrap();
     assert_eq!(false, client.send_raw_transaction(&tx).is_err());
@@ -206,6 +251,7 @@ fn claim_refund_before_locktime() {
     let claim_refund = xmr::ClaimRefund {
         btx_2_signed: &btx_2,
         address: final_address,
+        feerate: FEERATE,
     };
     let tx = Xmr::execute(&xmr_setup, &claim_refund).unwrap();
     assert_eq!(true, client.send_raw_transaction(&tx).is_err());
@@ -231,6 +277,7 @@ fn claim_refund_after_locktime() {
     let claim_refund = xmr::ClaimRefund {
         btx_2_signed: &btx_2,
         address: final_address,
+        feerate: FEERATE,
     };
     let tx = Xmr::execute(&xmr_setup, &claim_refund).unwrap();
     assert_eq!(false, client.send_raw_transaction(&tx).is_err());
@@ -252,6 +299,7 @@ fn buy_bitcoin() {
         funding: btx_1,
         address,
         s: btc_setup.get_s(),
+        feerate: FEERATE,
     }).unwrap();
     client.send_raw_transaction(&tx).unwrap();
 }